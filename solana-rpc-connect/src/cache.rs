@@ -0,0 +1,141 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+use tokio::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+// Балансы кошельков, полученные не раньше чем ttl назад
+pub struct BalanceCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, u64)>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl BalanceCache {
+    pub fn new(ttl: Duration) -> Self {
+        BalanceCache {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, wallet: &str) -> Option<u64> {
+        let cached = self
+            .entries
+            .lock()
+            .unwrap()
+            .get(wallet)
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < self.ttl)
+            .map(|(_, balance)| *balance);
+
+        match cached {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+
+        cached
+    }
+
+    pub fn insert(&self, wallet: &str, balance: u64) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(wallet.to_string(), (Instant::now(), balance));
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// Берем баланс из кэша, а при промахе запрашиваем и запоминаем только успешный ответ
+pub async fn cached_balance<F, Fut, E>(
+    cache: &BalanceCache,
+    wallet: String,
+    fetch: F,
+) -> (String, Result<u64, E>)
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = (String, Result<u64, E>)>,
+{
+    if let Some(balance) = cache.get(&wallet) {
+        return (wallet, Ok(balance));
+    }
+
+    let (wallet, balance) = fetch(wallet).await;
+    if let Ok(amount) = balance {
+        cache.insert(&wallet, amount);
+    }
+
+    (wallet, balance)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    async fn poll(cache: &BalanceCache, fetches: &AtomicUsize) -> Result<u64, String> {
+        cached_balance(cache, "wallet".to_string(), |wallet| async move {
+            fetches.fetch_add(1, Ordering::Relaxed);
+            (wallet, Ok(42))
+        })
+        .await
+        .1
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_poll_within_ttl_is_served_from_cache() {
+        let cache = BalanceCache::new(Duration::from_secs(10));
+        let fetches = AtomicUsize::new(0);
+
+        assert_eq!(poll(&cache, &fetches).await, Ok(42));
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert_eq!(poll(&cache, &fetches).await, Ok(42));
+
+        assert_eq!(fetches.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1 });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_poll_after_ttl_refetches() {
+        let cache = BalanceCache::new(Duration::from_secs(10));
+        let fetches = AtomicUsize::new(0);
+
+        poll(&cache, &fetches).await.unwrap();
+        tokio::time::advance(Duration::from_secs(11)).await;
+        poll(&cache, &fetches).await.unwrap();
+
+        assert_eq!(fetches.load(Ordering::Relaxed), 2);
+        assert_eq!(cache.stats(), CacheStats { hits: 0, misses: 2 });
+    }
+
+    #[tokio::test]
+    async fn test_failed_fetch_is_not_cached() {
+        let cache = BalanceCache::new(Duration::from_secs(10));
+
+        let (_, balance) = cached_balance(&cache, "wallet".to_string(), |wallet| async {
+            (wallet, Err("Failed to parse balance".to_string()))
+        })
+        .await;
+
+        assert!(balance.is_err());
+        assert_eq!(cache.get("wallet"), None);
+    }
+}
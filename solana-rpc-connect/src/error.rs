@@ -0,0 +1,46 @@
+use std::{fmt, time::Duration};
+
+// Почему не удалось получить баланс кошелька
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BalanceError {
+    // Запрос не дошел до ноды
+    Network(String),
+    // Ответ пришел, но в нем нет ожидаемого баланса
+    Decode(String),
+    // Адрес кошелька не является base58-ключом из 32 байт
+    InvalidAddress(String),
+    // Нода вернула JSON-RPC ошибку
+    RpcError { code: i64, message: String },
+    // HTTP 429; retry_after - сколько нода просит подождать в заголовке Retry-After
+    RateLimited { retry_after: Option<Duration> },
+}
+
+impl fmt::Display for BalanceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BalanceError::Network(err) => write!(f, "Network error: {}", err),
+            BalanceError::Decode(err) => write!(f, "Failed to decode response: {}", err),
+            BalanceError::InvalidAddress(address) => write!(f, "Invalid address: {}", address),
+            BalanceError::RpcError { code, message } => {
+                write!(f, "RPC error {}: {}", code, message)
+            }
+            BalanceError::RateLimited {
+                retry_after: Some(retry_after),
+            } => write!(f, "Rate limited by node, retry after {:?}", retry_after),
+            BalanceError::RateLimited { retry_after: None } => write!(f, "Rate limited by node"),
+        }
+    }
+}
+
+impl BalanceError {
+    // Сбой сети или перегруженная нода (коды -32000..-32099), запрос стоит повторить
+    pub fn is_transient(&self) -> bool {
+        match self {
+            BalanceError::Network(_) | BalanceError::RateLimited { .. } => true,
+            BalanceError::RpcError { code, .. } => (-32099..=-32000).contains(code),
+            BalanceError::Decode(_) | BalanceError::InvalidAddress(_) => false,
+        }
+    }
+}
+
+impl std::error::Error for BalanceError {}
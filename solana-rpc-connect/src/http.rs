@@ -0,0 +1,137 @@
+use reqwest::{
+    header::{HeaderMap, HeaderValue},
+    Client,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+// Настройки HTTP-клиента для частых запросов балансов
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    pub pool_max_idle_per_host: usize,
+    pub pool_idle_timeout_secs: u64,
+    pub tcp_keepalive_secs: Option<u64>,
+    // Сразу говорить с нодой по HTTP/2, без согласования версии; нода должна его поддерживать
+    pub http2_prior_knowledge: bool,
+    // Имя в User-Agent и заголовке solana-client, по нему запросы видны в логах общей ноды
+    pub client_name: String,
+    // Метка запуска: попадает в заголовки и в id каждого запроса
+    pub run_id: Option<String>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            pool_max_idle_per_host: 32,
+            pool_idle_timeout_secs: 90,
+            tcp_keepalive_secs: Some(60),
+            http2_prior_knowledge: false,
+            client_name: env!("CARGO_PKG_NAME").to_string(),
+            run_id: None,
+        }
+    }
+}
+
+impl HttpConfig {
+    pub fn user_agent(&self) -> String {
+        let agent = format!("{}/{}", self.client_name, env!("CARGO_PKG_VERSION"));
+        match &self.run_id {
+            Some(run_id) => format!("{} (run {})", agent, run_id),
+            None => agent,
+        }
+    }
+}
+
+// Id запросов JSON-RPC растут на единицу; с run_id это строка вида "<run_id>-<n>"
+pub struct RequestIds {
+    run_id: Option<String>,
+    next: AtomicU64,
+}
+
+impl RequestIds {
+    pub fn new(run_id: Option<String>) -> Self {
+        RequestIds {
+            run_id,
+            next: AtomicU64::new(1),
+        }
+    }
+
+    pub fn next_id(&self) -> Value {
+        let id = self.next.fetch_add(1, Ordering::Relaxed);
+        match &self.run_id {
+            Some(run_id) => Value::String(format!("{}-{}", run_id, id)),
+            None => Value::from(id),
+        }
+    }
+}
+
+// reqwest не дает прочитать настройки готового Client, поэтому храним их рядом
+pub struct HttpClient {
+    client: Client,
+    config: HttpConfig,
+}
+
+impl HttpClient {
+    pub fn new(config: HttpConfig) -> reqwest::Result<Self> {
+        let mut headers = HeaderMap::new();
+        if let Ok(name) = HeaderValue::from_str(&config.user_agent()) {
+            headers.insert("solana-client", name);
+        }
+
+        let mut builder = Client::builder()
+            .user_agent(config.user_agent())
+            .default_headers(headers)
+            .pool_max_idle_per_host(config.pool_max_idle_per_host)
+            .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout_secs))
+            .tcp_keepalive(config.tcp_keepalive_secs.map(Duration::from_secs));
+
+        if config.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+
+        Ok(HttpClient {
+            client: builder.build()?,
+            config,
+        })
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn config(&self) -> &HttpConfig {
+        &self.config
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_client_is_built_with_configured_pool() {
+        let config: HttpConfig = serde_yaml::from_str(
+            "pool_max_idle_per_host: 4\ntcp_keepalive_secs: 15\nhttp2_prior_knowledge: true",
+        )
+        .unwrap();
+
+        let http = HttpClient::new(config).expect("Client should build");
+
+        assert_eq!(
+            http.config(),
+            &HttpConfig {
+                pool_max_idle_per_host: 4,
+                pool_idle_timeout_secs: 90,
+                tcp_keepalive_secs: Some(15),
+                http2_prior_knowledge: true,
+                client_name: "solana-rpc-connect".to_string(),
+                run_id: None,
+            }
+        );
+    }
+}
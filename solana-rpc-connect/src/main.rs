@@ -1,14 +1,51 @@
-use reqwest::Client;
+use cache::{cached_balance, BalanceCache};
+use error::BalanceError;
+use http::{HttpClient, HttpConfig, RequestIds};
+use logging::{init_logging, LogFormat};
+use reqwest::{header::RETRY_AFTER, Client, Response, StatusCode};
+use retry::BalanceRetry;
 use serde::Deserialize;
-use std::fs;
+use std::{fs, future, path::Path, sync::Arc};
 use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+use watch::ConfigWatcher;
+
+mod cache;
+mod error;
+mod http;
+#[path = "../../solana-rpc-transactions/src/logging.rs"]
+mod logging;
+mod retry;
+mod watch;
 
 static CONFIG_PATH: &str = "config.yaml";
+// Сколько ждать тишины после правки конфига, прежде чем его перечитать
+static WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
 
 #[derive(Deserialize)]
 struct Config {
     wallets: Vec<String>,
     rcp_url: String,
+    #[serde(default)]
+    log_format: LogFormat,
+    // Сколько секунд баланс кошелька считается актуальным
+    #[serde(default)]
+    balance_cache_ttl_secs: u64,
+    // Если задан, балансы опрашиваются повторно с этим интервалом
+    #[serde(default)]
+    poll_interval_secs: Option<u64>,
+    #[serde(default)]
+    http: HttpConfig,
+    #[serde(default)]
+    balance_retry: BalanceRetry,
+}
+
+// Ошибка возвращается, а не роняет процесс: в режиме --watch
+// при неудачной перезагрузке остается прежний конфиг
+fn load_config(path: &str) -> Result<Config, String> {
+    let content =
+        fs::read_to_string(path).map_err(|err| format!("Failed to read {}: {}", path, err))?;
+    serde_yaml::from_str(&content).map_err(|err| format!("Failed to parse {}: {}", path, err))
 }
 
 async fn health_check(rpc_url: &str, client: &Client) -> Result<bool, String> {
@@ -19,14 +56,77 @@ async fn health_check(rpc_url: &str, client: &Client) -> Result<bool, String> {
     }
 }
 
+// Адрес проверяется до запроса, чтобы не тратить на него обращение к ноде
+fn validate_address(wallet: &str) -> Result<(), BalanceError> {
+    match bs58::decode(wallet).into_vec() {
+        Ok(bytes) if bytes.len() == 32 => Ok(()),
+        _ => Err(BalanceError::InvalidAddress(wallet.to_string())),
+    }
+}
+
 async fn get_balance(
     wallet: String,
     rpc_url: &str,
     client: &Client,
-) -> (String, Result<u64, String>) {
+    ids: &RequestIds,
+    retry: &BalanceRetry,
+) -> (String, Result<u64, BalanceError>) {
+    if let Err(err) = validate_address(&wallet) {
+        return (wallet, Err(err));
+    }
+
+    let mut attempt = 1;
+    let mut rate_limited_for = Duration::ZERO;
+    loop {
+        match request_balance(&wallet, rpc_url, client, ids).await {
+            Err(BalanceError::RateLimited {
+                retry_after: Some(wait),
+            }) if attempt < retry.attempts => {
+                if rate_limited_for + wait > retry.max_rate_limit_wait() {
+                    warn!(
+                        "Node asked to wait {:?} more for {}, over the {:?} limit",
+                        wait,
+                        wallet,
+                        retry.max_rate_limit_wait()
+                    );
+                    return (
+                        wallet,
+                        Err(BalanceError::RateLimited {
+                            retry_after: Some(wait),
+                        }),
+                    );
+                }
+                warn!(
+                    "Attempt {}/{} to fetch balance of {} was rate limited, retry in {:?}",
+                    attempt, retry.attempts, wallet, wait
+                );
+                sleep(wait).await;
+                rate_limited_for += wait;
+                attempt += 1;
+            }
+            Err(err) if err.is_transient() && attempt < retry.attempts => {
+                let backoff = retry.backoff(attempt);
+                warn!(
+                    "Attempt {}/{} to fetch balance of {} failed: {}, retry in {:?}",
+                    attempt, retry.attempts, wallet, err, backoff
+                );
+                sleep(backoff).await;
+                attempt += 1;
+            }
+            result => return (wallet, result),
+        }
+    }
+}
+
+async fn request_balance(
+    wallet: &str,
+    rpc_url: &str,
+    client: &Client,
+    ids: &RequestIds,
+) -> Result<u64, BalanceError> {
     let request_body = serde_json::json!({
         "jsonrpc": "2.0",
-        "id": 1,
+        "id": ids.next_id(),
         "method": "getBalance",
         "params": [wallet]
     });
@@ -34,37 +134,75 @@ async fn get_balance(
     let response = client.post(rpc_url).json(&request_body).send().await;
 
     match response {
+        Ok(resp) if resp.status() == StatusCode::TOO_MANY_REQUESTS => {
+            Err(BalanceError::RateLimited {
+                retry_after: retry_after(&resp),
+            })
+        }
         Ok(resp) => match resp.json::<serde_json::Value>().await {
-            Ok(json) => {
-                if let Some(balance) = json
-                    .get("result")
-                    .and_then(|r| r.get("value"))
-                    .and_then(|v| v.as_u64())
-                {
-                    (wallet, Ok(balance))
-                } else {
-                    (wallet, Err("Failed to parse balance".into()))
-                }
-            }
-            Err(_) => (wallet, Err("Failed to parse JSON response".into())),
+            Ok(json) => parse_balance_response(&json),
+            Err(err) => Err(BalanceError::Decode(err.to_string())),
         },
-        Err(err) => (wallet, Err(err.to_string())),
+        Err(err) => Err(BalanceError::Network(err.to_string())),
     }
 }
 
+// Retry-After в секундах; форму с HTTP-датой публичные ноды не присылают
+fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+fn parse_balance_response(json: &serde_json::Value) -> Result<u64, BalanceError> {
+    if let Some(error) = json.get("error") {
+        return Err(BalanceError::RpcError {
+            code: error
+                .get("code")
+                .and_then(|c| c.as_i64())
+                .unwrap_or_default(),
+            message: error
+                .get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or_default()
+                .to_string(),
+        });
+    }
+
+    json.get("result")
+        .and_then(|r| r.get("value"))
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| BalanceError::Decode("Failed to parse balance".to_string()))
+}
+
 async fn get_balances(
     http_client: &Client,
     wallets: Vec<String>,
     rpc_url: &str,
-) -> Vec<(String, Result<u64, String>)> {
-    let mut tasks: Vec<tokio::task::JoinHandle<(String, Result<u64, String>)>> = Vec::new();
+    cache: &Arc<BalanceCache>,
+    ids: &Arc<RequestIds>,
+    retry: BalanceRetry,
+) -> Vec<(String, Result<u64, BalanceError>)> {
+    let mut tasks: Vec<tokio::task::JoinHandle<(String, Result<u64, BalanceError>)>> = Vec::new();
 
     for wallet_address in wallets {
         let http_client = http_client.clone();
         let rpc_url = rpc_url.to_string();
+        let cache = Arc::clone(cache);
+        let ids = Arc::clone(ids);
 
-        let task =
-            tokio::spawn(async move { get_balance(wallet_address, &rpc_url, &http_client).await });
+        let task = tokio::spawn(async move {
+            cached_balance(&cache, wallet_address, |wallet| async move {
+                get_balance(wallet, &rpc_url, &http_client, &ids, &retry).await
+            })
+            .await
+        });
         tasks.push(task);
     }
 
@@ -80,43 +218,463 @@ async fn get_balances(
 
 #[tokio::main]
 async fn main() {
-    let config_content = fs::read_to_string(CONFIG_PATH).expect("Failed to read config file");
-    let config: Config =
-        serde_yaml::from_str(&config_content).expect("Failed to parse config file");
+    let config = load_config(CONFIG_PATH).unwrap_or_else(|err| panic!("{}", err));
+    init_logging(config.log_format);
+    let watch = std::env::args().any(|arg| arg == "--watch");
 
     if config.wallets.is_empty() {
-        println!("No wallets found in config file.");
+        warn!("No wallets found in config file.");
         return;
     }
 
-    let http_client = Client::new();
-    let rpc_url = config.rcp_url;
-    let balances = get_balances(&http_client, config.wallets, &rpc_url).await;
+    let http = HttpClient::new(config.http.clone()).expect("Failed to build HTTP client");
+    let http_client = http.client().clone();
+    info!(
+        "HTTP client {}: up to {} idle connections per host, HTTP/2 prior knowledge: {}",
+        http.config().user_agent(),
+        http.config().pool_max_idle_per_host,
+        http.config().http2_prior_knowledge
+    );
+    let mut rpc_url = config.rcp_url;
+    let mut wallets = config.wallets;
+    let cache = Arc::new(BalanceCache::new(Duration::from_secs(
+        config.balance_cache_ttl_secs,
+    )));
+    let ids = Arc::new(RequestIds::new(config.http.run_id.clone()));
+    let balances = get_balances(
+        &http_client,
+        wallets.clone(),
+        &rpc_url,
+        &cache,
+        &ids,
+        config.balance_retry,
+    )
+    .await;
 
     loop {
         let rpc_url = rpc_url.clone();
         let http_client = http_client.clone();
 
-        println!("Health check...");
+        info!("Health check...");
 
         let healt_check_req = tokio::spawn(async move {
             let rpc_url = rpc_url.clone();
             health_check(&rpc_url, &http_client).await
         });
 
-        if let Ok(_) = healt_check_req.await {
-            println!("Health check completed...");
+        if healt_check_req.await.is_ok() {
+            info!("Health check completed...");
             break;
         } else {
-            println!("Server is not responding, retry in 3 seconds...");
+            warn!("Server is not responding, retry in 3 seconds...");
             sleep(Duration::from_secs(3)).await;
         }
     }
 
-    for (wallet, balance) in balances {
+    print_balances(balances);
+
+    let mut watcher = if watch {
+        match ConfigWatcher::new(Path::new(CONFIG_PATH), WATCH_DEBOUNCE) {
+            Ok(watcher) => Some(watcher),
+            Err(err) => {
+                warn!("Failed to watch {}: {}", CONFIG_PATH, err);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let poll_interval = config.poll_interval_secs.map(Duration::from_secs);
+    if poll_interval.is_none() && watcher.is_none() {
+        return;
+    }
+
+    loop {
+        // Без интервала или без наблюдения соответствующая ветка просто никогда не срабатывает
+        tokio::select! {
+            _ = async {
+                match poll_interval {
+                    Some(interval) => sleep(interval).await,
+                    None => future::pending().await,
+                }
+            } => {}
+            changed = async {
+                match watcher.as_mut() {
+                    Some(watcher) => watcher.changed().await,
+                    None => future::pending().await,
+                }
+            } => {
+                if !changed {
+                    warn!("Stopped watching {}", CONFIG_PATH);
+                    watcher = None;
+                    if poll_interval.is_none() {
+                        return;
+                    }
+                    continue;
+                }
+                match load_config(CONFIG_PATH) {
+                    Ok(reloaded) => {
+                        info!(
+                            "Reloaded {}: {} wallets",
+                            CONFIG_PATH,
+                            reloaded.wallets.len()
+                        );
+                        rpc_url = reloaded.rcp_url;
+                        wallets = reloaded.wallets;
+                    }
+                    Err(err) => {
+                        warn!("{}, keeping the previous config", err);
+                        continue;
+                    }
+                }
+            }
+        }
+
+        let balances = get_balances(
+            &http_client,
+            wallets.clone(),
+            &rpc_url,
+            &cache,
+            &ids,
+            config.balance_retry,
+        )
+        .await;
+        print_balances(balances);
+
+        let stats = cache.stats();
+        info!(
+            "Balance cache: {} hits, {} misses",
+            stats.hits, stats.misses
+        );
+    }
+}
+
+// Балансы по убыванию, равные - по адресу кошелька, ошибки в конце,
+// чтобы вывод разных запусков можно было сравнивать
+fn summarize_balances(
+    mut balances: Vec<(String, Result<u64, BalanceError>)>,
+) -> Vec<(String, Result<u64, BalanceError>)> {
+    balances.sort_by(|(a_wallet, a_balance), (b_wallet, b_balance)| {
+        match (a_balance, b_balance) {
+            (Ok(a), Ok(b)) => b.cmp(a),
+            (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+            (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+            (Err(_), Err(_)) => std::cmp::Ordering::Equal,
+        }
+        .then_with(|| a_wallet.cmp(b_wallet))
+    });
+    balances
+}
+
+fn print_balances(balances: Vec<(String, Result<u64, BalanceError>)>) {
+    for (wallet, balance) in summarize_balances(balances) {
         match balance {
             Ok(amount) => println!("Wallet: {}, Balance: {}", wallet, amount),
             Err(err) => println!("Wallet: {}, Error: {}", wallet, err),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    static WALLET: &str = "11111111111111111111111111111111";
+
+    // HTTP-сервер, отвечающий на запросы по очереди заданными телами
+    async fn serve(bodies: Vec<&'static str>) -> String {
+        serve_recorded(bodies).await.0
+    }
+
+    // То же, но сырые запросы сохраняются, чтобы тест мог проверить заголовки и тело
+    async fn serve_recorded(bodies: Vec<&'static str>) -> (String, Arc<Mutex<Vec<String>>>) {
+        serve_responses(bodies.into_iter().map(ok_response).collect()).await
+    }
+
+    fn ok_response(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+
+    // Ответы целиком, со статусом и заголовками
+    async fn serve_responses(responses: Vec<String>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0u8; 4096];
+                let read = socket.read(&mut request).await.unwrap();
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(&request[..read]).to_string());
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, requests)
+    }
+
+    async fn serve_once(body: &'static str) -> String {
+        serve(vec![body]).await
+    }
+
+    async fn fetch(url: &str, wallet: &str) -> Result<u64, BalanceError> {
+        let retry = BalanceRetry {
+            attempts: 1,
+            ..BalanceRetry::default()
+        };
+        get_balance(
+            wallet.to_string(),
+            url,
+            &Client::new(),
+            &RequestIds::new(None),
+            &retry,
+        )
+        .await
+        .1
+    }
+
+    async fn next_change(watcher: &mut ConfigWatcher) -> bool {
+        tokio::time::timeout(Duration::from_secs(5), watcher.changed())
+            .await
+            .expect("Config change should be noticed")
+    }
+
+    #[tokio::test]
+    async fn test_config_change_triggers_reload() {
+        let directory =
+            std::env::temp_dir().join(format!("rpc-connect-watch-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("config.yaml");
+        let path_str = path.to_str().unwrap();
+        let config = |wallets: &[&str]| {
+            format!(
+                "wallets: [{}]\nrcp_url: http://127.0.0.1:8899\n",
+                wallets.join(", ")
+            )
+        };
+        fs::write(&path, config(&[WALLET])).unwrap();
+        let mut watcher = ConfigWatcher::new(&path, Duration::from_millis(100)).unwrap();
+
+        // Сломанный конфиг тоже дает перезагрузку, но она возвращает ошибку, а не падает
+        fs::write(&path, "wallets: [").unwrap();
+        assert!(next_change(&mut watcher).await);
+        assert!(load_config(path_str).is_err());
+
+        // Несколько быстрых правок сливаются в одну перезагрузку
+        fs::write(&path, config(&[WALLET])).unwrap();
+        fs::write(&path, config(&[WALLET, WALLET])).unwrap();
+        assert!(next_change(&mut watcher).await);
+        assert_eq!(load_config(path_str).unwrap().wallets.len(), 2);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(300), watcher.changed())
+                .await
+                .is_err()
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_summary_breaks_balance_ties_by_address() {
+        let balances = vec![
+            ("wallet-c".to_string(), Ok(10)),
+            (
+                "wallet-b".to_string(),
+                Err(BalanceError::Decode("bad".to_string())),
+            ),
+            ("wallet-b2".to_string(), Ok(10)),
+            ("wallet-a".to_string(), Ok(10)),
+            ("wallet-d".to_string(), Ok(50)),
+        ];
+
+        let wallets: Vec<String> = summarize_balances(balances)
+            .into_iter()
+            .map(|(wallet, _)| wallet)
+            .collect();
+
+        assert_eq!(
+            wallets,
+            ["wallet-d", "wallet-a", "wallet-b2", "wallet-c", "wallet-b"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_balance_is_parsed() {
+        let url =
+            serve_once(r#"{"jsonrpc":"2.0","id":1,"result":{"context":{"slot":1},"value":42}}"#)
+                .await;
+
+        assert_eq!(fetch(&url, WALLET).await, Ok(42));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_address_is_rejected_before_request() {
+        assert_eq!(
+            fetch("http://127.0.0.1:1", "not-a-wallet").await,
+            Err(BalanceError::InvalidAddress("not-a-wallet".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_node_is_network_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        assert!(matches!(
+            fetch(&url, WALLET).await,
+            Err(BalanceError::Network(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unexpected_response_is_decode_error() {
+        let url = serve_once(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#).await;
+        assert!(matches!(
+            fetch(&url, WALLET).await,
+            Err(BalanceError::Decode(_))
+        ));
+
+        let url = serve_once("not json").await;
+        assert!(matches!(
+            fetch(&url, WALLET).await,
+            Err(BalanceError::Decode(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rpc_error_keeps_code_and_message() {
+        let url = serve_once(
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32602,"message":"Invalid param"}}"#,
+        )
+        .await;
+
+        assert_eq!(
+            fetch(&url, WALLET).await,
+            Err(BalanceError::RpcError {
+                code: -32602,
+                message: "Invalid param".to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transient_failure_is_retried() {
+        let url = serve(vec![
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32005,"message":"Node is behind"}}"#,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"context":{"slot":1},"value":42}}"#,
+        ])
+        .await;
+        let retry = BalanceRetry {
+            attempts: 3,
+            initial_backoff_ms: 1,
+            ..BalanceRetry::default()
+        };
+
+        let (_, balance) = get_balance(
+            WALLET.to_string(),
+            &url,
+            &Client::new(),
+            &RequestIds::new(None),
+            &retry,
+        )
+        .await;
+
+        assert_eq!(balance, Ok(42));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_request_waits_for_retry_after() {
+        let rate_limited = "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 1\r\nConnection: close\r\nContent-Length: 0\r\n\r\n";
+        let balance = r#"{"jsonrpc":"2.0","id":1,"result":{"context":{"slot":1},"value":42}}"#;
+        let (url, requests) =
+            serve_responses(vec![rate_limited.to_string(), ok_response(balance)]).await;
+        let retry = BalanceRetry {
+            attempts: 3,
+            initial_backoff_ms: 1,
+            ..BalanceRetry::default()
+        };
+
+        let started = std::time::Instant::now();
+        let (_, result) = get_balance(
+            WALLET.to_string(),
+            &url,
+            &Client::new(),
+            &RequestIds::new(None),
+            &retry,
+        )
+        .await;
+
+        assert_eq!(result, Ok(42));
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        // Ожидание сверх лимита не выполняется, ошибка возвращается сразу
+        let (url, requests) = serve_responses(vec![rate_limited.to_string()]).await;
+        let capped = BalanceRetry {
+            max_rate_limit_wait_ms: 500,
+            ..retry
+        };
+        let (_, result) = get_balance(
+            WALLET.to_string(),
+            &url,
+            &Client::new(),
+            &RequestIds::new(None),
+            &capped,
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            Err(BalanceError::RateLimited {
+                retry_after: Some(Duration::from_secs(1))
+            })
+        );
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_requests_carry_user_agent_and_unique_ids() {
+        let balance = r#"{"jsonrpc":"2.0","id":1,"result":{"context":{"slot":1},"value":42}}"#;
+        let (url, requests) = serve_recorded(vec![balance, balance]).await;
+        let http = HttpClient::new(HttpConfig {
+            client_name: "balance-probe".to_string(),
+            run_id: Some("run-7".to_string()),
+            ..HttpConfig::default()
+        })
+        .unwrap();
+        let ids = RequestIds::new(http.config().run_id.clone());
+        let retry = BalanceRetry {
+            attempts: 1,
+            ..BalanceRetry::default()
+        };
+
+        for _ in 0..2 {
+            let (_, balance) =
+                get_balance(WALLET.to_string(), &url, http.client(), &ids, &retry).await;
+            assert_eq!(balance, Ok(42));
+        }
+
+        let requests = requests.lock().unwrap();
+        let user_agent = format!("balance-probe/{} (run run-7)", env!("CARGO_PKG_VERSION"));
+        for (request, id) in requests.iter().zip(["run-7-1", "run-7-2"]) {
+            let request = request.to_lowercase();
+            assert!(request.contains(&format!("user-agent: {}", user_agent)));
+            assert!(request.contains(&format!("solana-client: {}", user_agent)));
+            assert!(request.contains(&format!(r#""id":"{}""#, id)));
+        }
+        assert_eq!(requests.len(), 2);
+    }
+}
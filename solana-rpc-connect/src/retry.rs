@@ -0,0 +1,36 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+// Повтор getBalance при временных сбоях: attempts - общее число запросов,
+// пауза удваивается после каждой неудачной попытки. На 429 ждем столько,
+// сколько просит Retry-After, но суммарно не дольше max_rate_limit_wait_ms
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct BalanceRetry {
+    pub attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_rate_limit_wait_ms: u64,
+}
+
+impl Default for BalanceRetry {
+    fn default() -> Self {
+        BalanceRetry {
+            attempts: 3,
+            initial_backoff_ms: 200,
+            max_rate_limit_wait_ms: 30_000,
+        }
+    }
+}
+
+impl BalanceRetry {
+    pub fn max_rate_limit_wait(&self) -> Duration {
+        Duration::from_millis(self.max_rate_limit_wait_ms)
+    }
+
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        Duration::from_millis(
+            self.initial_backoff_ms
+                .saturating_mul(1 << (attempt - 1).min(16)),
+        )
+    }
+}
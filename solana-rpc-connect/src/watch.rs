@@ -0,0 +1,57 @@
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use tokio::{
+    sync::mpsc,
+    time::{timeout, Duration},
+};
+
+// Сообщает об изменениях одного файла. Следим за его каталогом: редакторы
+// часто сохраняют файл, переименовывая временный, и сам файл при этом пропадает
+pub struct ConfigWatcher {
+    _watcher: RecommendedWatcher,
+    changes: mpsc::UnboundedReceiver<()>,
+    debounce: Duration,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: &Path, debounce: Duration) -> notify::Result<Self> {
+        let file_name = path.file_name().map(ToOwned::to_owned);
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        let (sender, changes) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            let relevant = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                && event
+                    .paths
+                    .iter()
+                    .any(|changed| changed.file_name() == file_name.as_deref());
+            if relevant {
+                let _ = sender.send(());
+            }
+        })?;
+        watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+
+        Ok(ConfigWatcher {
+            _watcher: watcher,
+            changes,
+            debounce,
+        })
+    }
+
+    // Ждет изменения, а затем паузы в debounce без новых событий, так что
+    // серия быстрых правок дает одну перезагрузку. false - наблюдение прекратилось
+    pub async fn changed(&mut self) -> bool {
+        if self.changes.recv().await.is_none() {
+            return false;
+        }
+
+        while let Ok(Some(())) = timeout(self.debounce, self.changes.recv()).await {}
+        true
+    }
+}
@@ -0,0 +1,331 @@
+use rand::Rng;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    system_instruction,
+    transaction::Transaction,
+};
+use std::{future::Future, sync::Arc, time::Duration};
+use tokio::{
+    sync::{Mutex, Semaphore},
+    time::{sleep, sleep_until, Instant},
+};
+use tracing::warn;
+
+static AIRDROP_POLL_INTERVAL: Duration = Duration::from_millis(500);
+static AIRDROP_MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+pub struct AirdropRetry {
+    pub attempts: usize,
+    pub initial_backoff: Duration,
+    // Сколько ждать подтверждения одного запроса перед повторным запросом
+    pub confirm_timeout: Duration,
+}
+
+impl Default for AirdropRetry {
+    fn default() -> Self {
+        AirdropRetry {
+            attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            confirm_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+// Devnet может молча потерять запрос airdrop, поэтому повторяется сам запрос,
+// а не только ожидание его подтверждения. Возвращает баланс после airdrop
+pub async fn request_airdrop_with_retry(
+    client: &RpcClient,
+    wallet: &Pubkey,
+    lamports: u64,
+    retry: &AirdropRetry,
+) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+    let mut backoff = retry.initial_backoff;
+
+    for attempt in 1..=retry.attempts {
+        match client.request_airdrop(wallet, lamports) {
+            Ok(signature) => {
+                if wait_for_confirmation(client, &signature, retry.confirm_timeout).await {
+                    return Ok(client.get_balance(wallet)?);
+                }
+                warn!(
+                    "Airdrop attempt {}/{} to {} was not confirmed within {:?}",
+                    attempt, retry.attempts, wallet, retry.confirm_timeout
+                );
+            }
+            Err(err) => warn!(
+                "Airdrop attempt {}/{} to {} failed: {}",
+                attempt, retry.attempts, wallet, err
+            ),
+        }
+
+        if attempt < retry.attempts {
+            sleep(jittered(backoff)).await;
+            backoff = (backoff * 2).min(AIRDROP_MAX_BACKOFF);
+        }
+    }
+
+    Err(format!(
+        "Airdrop of {} lamports to {} was not confirmed after {} attempts",
+        lamports, wallet, retry.attempts
+    )
+    .into())
+}
+
+type FundResult = Result<u64, Box<dyn std::error::Error + Send + Sync>>;
+
+// Источник лампортов для тестовых кошельков; fund возвращает баланс после подтверждения
+pub trait Funder: Send + Sync + 'static {
+    fn fund(&self, wallet: Pubkey, lamports: u64) -> impl Future<Output = FundResult> + Send;
+}
+
+// Airdrop от ноды, на devnet ограничен по частоте
+pub struct AirdropFunder {
+    pub client: Arc<RpcClient>,
+    pub retry: AirdropRetry,
+}
+
+impl Funder for AirdropFunder {
+    async fn fund(&self, wallet: Pubkey, lamports: u64) -> FundResult {
+        request_airdrop_with_retry(&self.client, &wallet, lamports, &self.retry).await
+    }
+}
+
+// Перевод с заранее пополненного кошелька, когда airdrop недоступен
+pub struct FaucetFunder {
+    pub client: Arc<RpcClient>,
+    pub faucet: Keypair,
+}
+
+impl Funder for FaucetFunder {
+    async fn fund(&self, wallet: Pubkey, lamports: u64) -> FundResult {
+        let transaction = Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(
+                &self.faucet.pubkey(),
+                &wallet,
+                lamports,
+            )],
+            Some(&self.faucet.pubkey()),
+            &[&self.faucet],
+            self.client.get_latest_blockhash()?,
+        );
+        self.client.send_and_confirm_transaction(&transaction)?;
+        Ok(self.client.get_balance(&wallet)?)
+    }
+}
+
+// Сколько пополнений идет одновременно и как часто можно начинать новое
+pub struct FundingLimits {
+    pub concurrency: usize,
+    pub min_interval: Duration,
+}
+
+impl Default for FundingLimits {
+    fn default() -> Self {
+        FundingLimits {
+            concurrency: 4,
+            min_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+// Пополняет все кошельки параллельно в пределах limits и дожидается каждого.
+// Результаты идут в порядке wallets: баланс после пополнения или текст ошибки
+pub async fn fund_wallets<F: Funder>(
+    funder: Arc<F>,
+    wallets: &[Pubkey],
+    lamports: u64,
+    limits: &FundingLimits,
+) -> Vec<(Pubkey, Result<u64, String>)> {
+    let permits = Arc::new(Semaphore::new(limits.concurrency.max(1)));
+    // Время, раньше которого нельзя начать следующее пополнение
+    let next_start = Arc::new(Mutex::new(Instant::now()));
+    let min_interval = limits.min_interval;
+
+    let tasks: Vec<_> = wallets
+        .iter()
+        .map(|&wallet| {
+            let (funder, permits, next_start) = (
+                Arc::clone(&funder),
+                Arc::clone(&permits),
+                Arc::clone(&next_start),
+            );
+            tokio::spawn(async move {
+                let _permit = permits
+                    .acquire_owned()
+                    .await
+                    .expect("Semaphore is never closed");
+                {
+                    let mut next_start = next_start.lock().await;
+                    sleep_until(*next_start).await;
+                    *next_start = Instant::now() + min_interval;
+                }
+                funder
+                    .fund(wallet, lamports)
+                    .await
+                    .map_err(|err| err.to_string())
+            })
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(wallets.len());
+    for (wallet, task) in wallets.iter().zip(tasks) {
+        let result = task.await.unwrap_or_else(|err| Err(err.to_string()));
+        if let Err(err) = &result {
+            warn!("Failed to fund {}: {}", wallet, err);
+        }
+        results.push((*wallet, result));
+    }
+    results
+}
+
+// Пауза случайна в пределах от половины до полного backoff,
+// чтобы несколько кошельков не повторяли запросы одновременно
+fn jittered(backoff: Duration) -> Duration {
+    backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+async fn wait_for_confirmation(
+    client: &RpcClient,
+    signature: &Signature,
+    timeout: Duration,
+) -> bool {
+    let start_time = Instant::now();
+
+    loop {
+        match client.confirm_transaction(signature) {
+            Ok(true) => return true,
+            Ok(false) => {}
+            Err(err) => warn!("Failed to check airdrop {}: {}", signature, err),
+        }
+
+        if start_time.elapsed() >= timeout {
+            return false;
+        }
+        sleep(AIRDROP_POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockRpc;
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+    use solana_program_test::{BanksClient, ProgramTest};
+    use solana_sdk::hash::Hash;
+
+    // Тот же перевод с faucet, что и FaucetFunder, но через банк ProgramTest.
+    // Банк теряет одновременные транзакции, пишущие в один аккаунт faucet,
+    // поэтому сами переводы идут по одному
+    struct BanksFaucet {
+        banks: Mutex<BanksClient>,
+        faucet: Keypair,
+        blockhash: Hash,
+    }
+
+    impl Funder for BanksFaucet {
+        async fn fund(&self, wallet: Pubkey, lamports: u64) -> FundResult {
+            let mut banks = self.banks.lock().await;
+            let transaction = Transaction::new_signed_with_payer(
+                &[system_instruction::transfer(
+                    &self.faucet.pubkey(),
+                    &wallet,
+                    lamports,
+                )],
+                Some(&self.faucet.pubkey()),
+                &[&self.faucet],
+                self.blockhash,
+            );
+            banks.process_transaction(transaction).await?;
+            Ok(banks.get_balance(wallet).await?)
+        }
+    }
+
+    fn fast_retry(attempts: usize) -> AirdropRetry {
+        AirdropRetry {
+            attempts,
+            initial_backoff: Duration::from_millis(1),
+            confirm_timeout: Duration::ZERO,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_airdrop_is_requested_again_after_confirmation_timeout() {
+        let rpc = MockRpc::new();
+        rpc.push(
+            RpcRequest::GetSignatureStatuses,
+            Ok(json!({ "context": { "slot": 1 }, "value": [null] })),
+        );
+        let client = rpc.client();
+
+        let balance =
+            request_airdrop_with_retry(&client, &Pubkey::new_unique(), 1_000, &fast_retry(3))
+                .await
+                .expect("Second airdrop should be confirmed");
+
+        assert_eq!(balance, 50);
+        assert_eq!(rpc.calls(RpcRequest::RequestAirdrop), 2);
+        assert_eq!(rpc.calls(RpcRequest::GetBalance), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_airdrop_reports_exhaustion() {
+        let rpc = MockRpc::new();
+        for _ in 0..2 {
+            rpc.push(
+                RpcRequest::GetSignatureStatuses,
+                Ok(json!({ "context": { "slot": 1 }, "value": [null] })),
+            );
+        }
+        let client = rpc.client();
+        let wallet = Pubkey::new_unique();
+
+        let err = request_airdrop_with_retry(&client, &wallet, 1_000, &fast_retry(2))
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Airdrop of 1000 lamports to {} was not confirmed after 2 attempts",
+                wallet
+            )
+        );
+        assert_eq!(rpc.calls(RpcRequest::RequestAirdrop), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fund_wallets_funds_every_wallet() {
+        let (banks, faucet, blockhash) = ProgramTest::default().start().await;
+        let wallets = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        let funder = Arc::new(BanksFaucet {
+            banks: Mutex::new(banks.clone()),
+            faucet,
+            blockhash,
+        });
+        let limits = FundingLimits {
+            concurrency: 2,
+            min_interval: Duration::from_millis(1),
+        };
+
+        let results = fund_wallets(funder, &wallets, 1_000_000, &limits).await;
+
+        assert_eq!(
+            results,
+            wallets
+                .iter()
+                .map(|wallet| (*wallet, Ok(1_000_000)))
+                .collect::<Vec<_>>()
+        );
+        for wallet in wallets {
+            assert_eq!(banks.clone().get_balance(wallet).await.unwrap(), 1_000_000);
+        }
+    }
+}
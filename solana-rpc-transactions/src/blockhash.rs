@@ -0,0 +1,79 @@
+use solana_client::{client_error::Result as ClientResult, rpc_client::RpcClient};
+use solana_sdk::{commitment_config::CommitmentConfig, hash::Hash};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+// Blockhash живет 60-90 слотов, поэтому переводы кампании могут делить один
+// и тот же, а не запрашивать его перед каждой отправкой
+#[derive(Debug)]
+pub struct BlockhashCache {
+    refresh_interval: Duration,
+    cached: Mutex<Option<CachedBlockhash>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CachedBlockhash {
+    commitment: CommitmentConfig,
+    blockhash: Hash,
+    fetched_at: Instant,
+}
+
+impl Default for BlockhashCache {
+    fn default() -> Self {
+        BlockhashCache::new(Duration::from_secs(2))
+    }
+}
+
+impl BlockhashCache {
+    // Нулевой интервал - blockhash запрашивается каждый раз
+    pub fn new(refresh_interval: Duration) -> Self {
+        BlockhashCache {
+            refresh_interval,
+            cached: Mutex::new(None),
+        }
+    }
+
+    // Блокировка держится на время запроса: задачи, пришедшие за blockhash
+    // одновременно, дождутся одного запроса вместо того, чтобы слать свои.
+    // Mutex из tokio, поэтому ожидающие задачи не занимают потоки рантайма.
+    // Клиент с другим commitment получает свой blockhash
+    pub async fn latest(&self, client: &RpcClient) -> ClientResult<Hash> {
+        let commitment = client.commitment();
+        let mut cached = self.cached.lock().await;
+
+        if let Some(entry) = *cached {
+            if entry.commitment == commitment && entry.fetched_at.elapsed() < self.refresh_interval
+            {
+                return Ok(entry.blockhash);
+            }
+        }
+
+        let (blockhash, _) = client.get_latest_blockhash_with_commitment(commitment)?;
+        *cached = Some(CachedBlockhash {
+            commitment,
+            blockhash,
+            fetched_at: Instant::now(),
+        });
+        Ok(blockhash)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockRpc;
+    use solana_client::rpc_request::RpcRequest;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_zero_interval_fetches_every_time() {
+        let rpc = MockRpc::new();
+        let client = rpc.client();
+        let cache = BlockhashCache::new(Duration::ZERO);
+
+        for _ in 0..3 {
+            cache.latest(&client).await.unwrap();
+        }
+
+        assert_eq!(rpc.calls(RpcRequest::GetLatestBlockhash), 3);
+    }
+}
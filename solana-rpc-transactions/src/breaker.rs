@@ -0,0 +1,88 @@
+use crate::report::TransferOutcome;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+// Останавливает кампанию, когда доля неудачных переводов превышает max_failure_rate.
+// Порог проверяется только после min_sample завершенных переводов, чтобы
+// первая же ошибка не прерывала запуск
+#[derive(Debug)]
+pub struct FailureBreaker {
+    max_failure_rate: f64,
+    min_sample: usize,
+    completed: AtomicUsize,
+    failed: AtomicUsize,
+    tripped: AtomicBool,
+}
+
+impl FailureBreaker {
+    pub fn new(max_failure_rate: f64, min_sample: usize) -> Self {
+        FailureBreaker {
+            max_failure_rate,
+            min_sample: min_sample.max(1),
+            completed: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            tripped: AtomicBool::new(false),
+        }
+    }
+
+    // Неудачными считаются те же исходы, что и в итогах по отправителям
+    pub fn record(&self, outcome: &TransferOutcome) {
+        let failed = matches!(
+            outcome,
+            TransferOutcome::Failed(..) | TransferOutcome::Dropped(_) | TransferOutcome::TimedOut
+        );
+        let completed = self.completed.fetch_add(1, Ordering::SeqCst) + 1;
+        let failed = if failed {
+            self.failed.fetch_add(1, Ordering::SeqCst) + 1
+        } else {
+            self.failed.load(Ordering::SeqCst)
+        };
+
+        if completed >= self.min_sample && failed as f64 / completed as f64 > self.max_failure_rate
+        {
+            self.tripped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    pub fn tripped(&self) -> bool {
+        self.tripped.load(Ordering::SeqCst)
+    }
+
+    // Сколько переводов завершилось и сколько из них неудачно
+    pub fn counts(&self) -> (usize, usize) {
+        (
+            self.completed.load(Ordering::SeqCst),
+            self.failed.load(Ordering::SeqCst),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::failure::FailureCategory;
+
+    #[test]
+    fn test_trips_only_after_min_sample() {
+        let breaker = FailureBreaker::new(0.5, 4);
+        let failed = TransferOutcome::Failed("boom".to_string(), FailureCategory::Other);
+
+        for _ in 0..3 {
+            breaker.record(&failed);
+        }
+        assert!(!breaker.tripped());
+
+        breaker.record(&TransferOutcome::Simulated);
+        assert!(breaker.tripped());
+        assert_eq!(breaker.counts(), (4, 3));
+    }
+
+    #[test]
+    fn test_rate_at_threshold_does_not_trip() {
+        let breaker = FailureBreaker::new(0.5, 2);
+
+        breaker.record(&TransferOutcome::TimedOut);
+        breaker.record(&TransferOutcome::Simulated);
+
+        assert!(!breaker.tripped());
+    }
+}
@@ -0,0 +1,366 @@
+use crate::failure::FailureCategory;
+use crate::report::TransferOutcome;
+use indicatif::{ProgressBar, ProgressStyle};
+use solana_client::{
+    client_error::ClientError, rpc_client::RpcClient,
+    rpc_request::MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS,
+};
+use solana_sdk::signature::Signature;
+use std::{
+    collections::{HashMap, HashSet},
+    io::{stdout, IsTerminal},
+    time::Duration,
+};
+use tokio::time::{sleep, Instant};
+use tracing::warn;
+
+static BATCH_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Прогресс подтверждения виден только в терминале, чтобы не засорять перенаправленный вывод
+pub fn confirmation_progress(total: usize) -> ProgressBar {
+    if !stdout().is_terminal() {
+        return ProgressBar::hidden();
+    }
+
+    let progress = ProgressBar::new(total as u64);
+    progress.set_style(
+        ProgressStyle::with_template("Confirming {bar:40} {pos}/{len} signatures ({elapsed})")
+            .expect("Progress template is valid"),
+    );
+    progress
+}
+
+// Подтверждаем сразу много подписей: get_signature_statuses принимает до 256 подписей
+// за запрос, опрашиваем пачками, пока все не разрешатся или не выйдет timeout
+// Блокхеши всех транзакций получены до начала подтверждения, поэтому через expiry_blocks
+// блоков от старта ни одна неподтвержденная транзакция уже не попадет в блок - она Dropped.
+// Dropped только те, у кого при последнем опросе статуса не было вовсе: попавшая в блок,
+// но еще не подтвержденная транзакция ждет до timeout, иначе повтор заплатил бы дважды
+// С min_confirmations подпись считается подтвержденной, только когда над ее слотом
+// набралось столько подтверждений или она финализирована
+pub async fn confirm_signatures(
+    client: &RpcClient,
+    signatures: &[Signature],
+    timeout: Duration,
+    expiry_blocks: u64,
+    min_confirmations: usize,
+    progress: &ProgressBar,
+) -> Result<Vec<(Signature, TransferOutcome)>, ClientError> {
+    let start_time = Instant::now();
+    let mut resolved: HashMap<Signature, TransferOutcome> = HashMap::new();
+    let mut pending: Vec<Signature> = signatures.to_vec();
+    let expiry_height = match client.get_block_height() {
+        Ok(height) => Some(height + expiry_blocks),
+        Err(err) => {
+            warn!(
+                "Failed to read block height, drops will not be detected: {}",
+                err
+            );
+            None
+        }
+    };
+
+    loop {
+        let mut still_pending = Vec::new();
+        // Подписи со статусом, но без нужного commitment или глубины
+        let mut landed = HashSet::new();
+        // Высоту читаем до статусов: если блокхеш истек еще до запроса статусов,
+        // ненайденная транзакция точно не попадет в блок
+        let expired = match expiry_height {
+            Some(expiry_height) => match client.get_block_height() {
+                Ok(height) => height > expiry_height,
+                Err(err) => {
+                    warn!("Failed to read block height: {}", err);
+                    false
+                }
+            },
+            None => false,
+        };
+
+        for chunk in pending.chunks(MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS) {
+            let statuses = client.get_signature_statuses(chunk)?.value;
+
+            for (signature, status) in chunk.iter().zip(statuses) {
+                match status {
+                    Some(status) => match &status.err {
+                        Some(err) => {
+                            resolved.insert(
+                                *signature,
+                                TransferOutcome::Failed(
+                                    err.to_string(),
+                                    FailureCategory::from_transaction_error(err),
+                                ),
+                            );
+                        }
+                        None if status.satisfies_commitment(client.commitment())
+                            && is_deep_enough(status.confirmations, min_confirmations) =>
+                        {
+                            resolved.insert(*signature, TransferOutcome::Confirmed(*signature));
+                        }
+                        None => {
+                            landed.insert(*signature);
+                            still_pending.push(*signature);
+                        }
+                    },
+                    None => still_pending.push(*signature),
+                }
+            }
+        }
+
+        progress.set_position((signatures.len() - still_pending.len()) as u64);
+        pending = still_pending;
+        if pending.is_empty() {
+            break;
+        }
+
+        if expired {
+            let (landed, dropped): (Vec<_>, Vec<_>) = pending
+                .into_iter()
+                .partition(|signature| landed.contains(signature));
+            if !dropped.is_empty() {
+                warn!(
+                    "{} signatures were dropped, their blockhash has expired",
+                    dropped.len()
+                );
+            }
+            for signature in dropped {
+                resolved.insert(signature, TransferOutcome::Dropped(signature));
+            }
+            pending = landed;
+            progress.set_position((signatures.len() - pending.len()) as u64);
+            if pending.is_empty() {
+                break;
+            }
+        }
+
+        if start_time.elapsed() >= timeout {
+            warn!(
+                "{} signatures were not confirmed within {:?}",
+                pending.len(),
+                timeout
+            );
+            for signature in &pending {
+                resolved.insert(
+                    *signature,
+                    TransferOutcome::Failed(
+                        "Confirmation timed out".to_string(),
+                        FailureCategory::TimedOut,
+                    ),
+                );
+            }
+            break;
+        }
+
+        sleep(BATCH_POLL_INTERVAL).await;
+    }
+
+    progress.finish();
+
+    Ok(signatures
+        .iter()
+        .map(|signature| (*signature, resolved[signature].clone()))
+        .collect())
+}
+
+// confirmations пуст у финализированной транзакции, глубже она уже не станет
+fn is_deep_enough(confirmations: Option<usize>, min_confirmations: usize) -> bool {
+    confirmations.is_none_or(|confirmations| confirmations >= min_confirmations)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockRpc;
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::clock::MAX_PROCESSING_AGE;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_confirm_300_signatures_in_two_batches() {
+        let rpc = MockRpc::new();
+        let client = rpc.client();
+        let signatures: Vec<Signature> = (0..300).map(|_| Signature::new_unique()).collect();
+
+        let progress = ProgressBar::hidden();
+        progress.set_length(signatures.len() as u64);
+
+        let outcomes = confirm_signatures(
+            &client,
+            &signatures,
+            Duration::from_secs(5),
+            MAX_PROCESSING_AGE as u64,
+            0,
+            &progress,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(progress.position(), 300);
+        assert_eq!(rpc.calls(RpcRequest::GetSignatureStatuses), 2);
+        let params = rpc.params(RpcRequest::GetSignatureStatuses);
+        assert_eq!(params[0][0].as_array().unwrap().len(), 256);
+        assert_eq!(params[1][0].as_array().unwrap().len(), 44);
+        assert_eq!(outcomes.len(), 300);
+        for (signature, outcome) in outcomes {
+            assert_eq!(outcome, TransferOutcome::Confirmed(signature));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_unresolved_signature_times_out() {
+        let rpc = MockRpc::new();
+        rpc.push(
+            RpcRequest::GetSignatureStatuses,
+            Ok(json!({ "context": { "slot": 1 }, "value": [null] })),
+        );
+        let client = rpc.client();
+        let signature = Signature::new_unique();
+
+        let outcomes = confirm_signatures(
+            &client,
+            &[signature],
+            Duration::ZERO,
+            MAX_PROCESSING_AGE as u64,
+            0,
+            &ProgressBar::hidden(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            outcomes,
+            vec![(
+                signature,
+                TransferOutcome::Failed(
+                    "Confirmation timed out".to_string(),
+                    FailureCategory::TimedOut
+                )
+            )]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_unlanded_signature_is_dropped_after_expiry() {
+        let rpc = MockRpc::new();
+        rpc.push(RpcRequest::GetBlockHeight, Ok(json!(100)))
+            .push(RpcRequest::GetBlockHeight, Ok(json!(200)))
+            .push(RpcRequest::GetBlockHeight, Ok(json!(251)));
+        for _ in 0..2 {
+            rpc.push(
+                RpcRequest::GetSignatureStatuses,
+                Ok(json!({ "context": { "slot": 1 }, "value": [null] })),
+            );
+        }
+        let client = rpc.client();
+        let signature = Signature::new_unique();
+
+        let outcomes = confirm_signatures(
+            &client,
+            &[signature],
+            Duration::from_secs(60),
+            150,
+            0,
+            &ProgressBar::hidden(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            outcomes,
+            vec![(signature, TransferOutcome::Dropped(signature))]
+        );
+        assert_eq!(rpc.calls(RpcRequest::GetSignatureStatuses), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_landed_signature_is_not_dropped_after_expiry() {
+        let rpc = MockRpc::new();
+        rpc.push(RpcRequest::GetBlockHeight, Ok(json!(100)))
+            .push(RpcRequest::GetBlockHeight, Ok(json!(251)))
+            .push(RpcRequest::GetBlockHeight, Ok(json!(252)));
+        let shallow = json!({
+            "slot": 1,
+            "confirmations": 1,
+            "err": null,
+            "status": { "Ok": null },
+            "confirmationStatus": "confirmed"
+        });
+        let deep = json!({
+            "slot": 1,
+            "confirmations": 5,
+            "err": null,
+            "status": { "Ok": null },
+            "confirmationStatus": "confirmed"
+        });
+        rpc.push(
+            RpcRequest::GetSignatureStatuses,
+            Ok(json!({ "context": { "slot": 1 }, "value": [shallow, null] })),
+        )
+        .push(
+            RpcRequest::GetSignatureStatuses,
+            Ok(json!({ "context": { "slot": 1 }, "value": [deep] })),
+        );
+        let client = rpc.client();
+        let (landed, unlanded) = (Signature::new_unique(), Signature::new_unique());
+
+        let outcomes = confirm_signatures(
+            &client,
+            &[landed, unlanded],
+            Duration::from_secs(60),
+            150,
+            3,
+            &ProgressBar::hidden(),
+        )
+        .await
+        .unwrap();
+
+        // Блокхеш истек уже на первом опросе, но дропнута только не попавшая в блок подпись
+        assert_eq!(
+            outcomes,
+            vec![
+                (landed, TransferOutcome::Confirmed(landed)),
+                (unlanded, TransferOutcome::Dropped(unlanded)),
+            ]
+        );
+        assert_eq!(rpc.calls(RpcRequest::GetSignatureStatuses), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_waits_for_min_confirmations() {
+        let rpc = MockRpc::new();
+        for confirmations in [1, 2, 3] {
+            rpc.push(
+                RpcRequest::GetSignatureStatuses,
+                Ok(json!({
+                    "context": { "slot": 1 },
+                    "value": [{
+                        "slot": 1,
+                        "confirmations": confirmations,
+                        "err": null,
+                        "status": { "Ok": null },
+                        "confirmationStatus": "confirmed"
+                    }]
+                })),
+            );
+        }
+        let client = rpc.client();
+        let signature = Signature::new_unique();
+
+        let outcomes = confirm_signatures(
+            &client,
+            &[signature],
+            Duration::from_secs(60),
+            MAX_PROCESSING_AGE as u64,
+            3,
+            &ProgressBar::hidden(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            outcomes,
+            vec![(signature, TransferOutcome::Confirmed(signature))]
+        );
+        assert_eq!(rpc.calls(RpcRequest::GetSignatureStatuses), 3);
+    }
+}
@@ -0,0 +1,77 @@
+use serde::Deserialize;
+use solana_sdk::signature::Signature;
+
+// Кластер для ссылок на explorer.solana.com
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cluster {
+    #[default]
+    MainnetBeta,
+    Devnet,
+    Testnet,
+    // Локальный валидатор на порту 8899
+    Localnet,
+}
+
+impl Cluster {
+    // Угадываем по адресу RPC; адрес частного провайдера без имени кластера считаем mainnet
+    pub fn from_rpc_url(rpc_url: &str) -> Self {
+        let url = rpc_url.to_lowercase();
+        if url.contains("devnet") {
+            Cluster::Devnet
+        } else if url.contains("testnet") {
+            Cluster::Testnet
+        } else if url.contains("localhost") || url.contains("127.0.0.1") {
+            Cluster::Localnet
+        } else {
+            Cluster::MainnetBeta
+        }
+    }
+}
+
+pub fn explorer_url(signature: &Signature, cluster: Cluster) -> String {
+    let query = match cluster {
+        Cluster::MainnetBeta => "",
+        Cluster::Devnet => "?cluster=devnet",
+        Cluster::Testnet => "?cluster=testnet",
+        Cluster::Localnet => "?cluster=custom&customUrl=http%3A%2F%2Flocalhost%3A8899",
+    };
+
+    format!("https://explorer.solana.com/tx/{}{}", signature, query)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_explorer_url_for_devnet_and_mainnet() {
+        let signature = Signature::new_unique();
+
+        assert_eq!(
+            explorer_url(
+                &signature,
+                Cluster::from_rpc_url("https://api.devnet.solana.com")
+            ),
+            format!(
+                "https://explorer.solana.com/tx/{}?cluster=devnet",
+                signature
+            )
+        );
+        assert_eq!(
+            explorer_url(
+                &signature,
+                Cluster::from_rpc_url("https://api.mainnet-beta.solana.com")
+            ),
+            format!("https://explorer.solana.com/tx/{}", signature)
+        );
+        assert_eq!(
+            Cluster::from_rpc_url("http://127.0.0.1:8899"),
+            Cluster::Localnet
+        );
+        assert_eq!(
+            Cluster::from_rpc_url("https://api.testnet.solana.com"),
+            Cluster::Testnet
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use crate::report::{TransferOutcome, TransferRecord};
+use solana_client::{client_error::ClientError, client_error::ClientErrorKind};
+use solana_sdk::{instruction::InstructionError, transaction::TransactionError};
+use std::{collections::BTreeMap, error::Error, fmt};
+
+// System Program: на счете отправителя не хватает лампортов для перевода
+const SYSTEM_ERROR_RESULT_WITH_NEGATIVE_LAMPORTS: u32 = 1;
+
+// Первопричина неудачного перевода, по ней группируются итоги кампании
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FailureCategory {
+    InsufficientFunds,
+    BlockhashExpired,
+    AccountNotFound,
+    // Сбой сети или ноды, а не самой транзакции
+    NodeError,
+    TimedOut,
+    Other,
+}
+
+impl fmt::Display for FailureCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FailureCategory::InsufficientFunds => write!(f, "insufficient funds"),
+            FailureCategory::BlockhashExpired => write!(f, "blockhash expired"),
+            FailureCategory::AccountNotFound => write!(f, "account not found"),
+            FailureCategory::NodeError => write!(f, "node error"),
+            FailureCategory::TimedOut => write!(f, "timed out"),
+            FailureCategory::Other => write!(f, "other"),
+        }
+    }
+}
+
+impl FailureCategory {
+    pub fn from_transaction_error(err: &TransactionError) -> Self {
+        match err {
+            TransactionError::InsufficientFundsForFee
+            | TransactionError::InsufficientFundsForRent { .. }
+            | TransactionError::InstructionError(
+                _,
+                InstructionError::Custom(SYSTEM_ERROR_RESULT_WITH_NEGATIVE_LAMPORTS),
+            ) => FailureCategory::InsufficientFunds,
+            TransactionError::BlockhashNotFound => FailureCategory::BlockhashExpired,
+            TransactionError::AccountNotFound | TransactionError::ProgramAccountNotFound => {
+                FailureCategory::AccountNotFound
+            }
+            _ => FailureCategory::Other,
+        }
+    }
+
+    // Ошибка транзакции бывает и в самой ошибке, и в результате preflight-симуляции
+    pub fn from_client_error(err: &ClientError) -> Self {
+        if let Some(err) = err.get_transaction_error() {
+            return Self::from_transaction_error(&err);
+        }
+
+        match err.kind() {
+            ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) | ClientErrorKind::RpcError(_) => {
+                FailureCategory::NodeError
+            }
+            _ => FailureCategory::Other,
+        }
+    }
+
+    // send_sol возвращает ошибки разных типов в Box<dyn Error>
+    pub fn from_error(err: &(dyn Error + 'static)) -> Self {
+        if let Some(err) = err.downcast_ref::<ClientError>() {
+            return Self::from_client_error(err);
+        }
+        if let Some(err) = err.downcast_ref::<TransactionError>() {
+            return Self::from_transaction_error(err);
+        }
+        FailureCategory::Other
+    }
+}
+
+// Гистограмма причин: Dropped - истекший blockhash, TimedOut - отдельная категория
+pub fn failure_histogram(records: &[TransferRecord]) -> BTreeMap<FailureCategory, usize> {
+    let mut histogram = BTreeMap::new();
+    for record in records {
+        let category = match &record.outcome {
+            TransferOutcome::Failed(_, category) => *category,
+            TransferOutcome::Dropped(_) => FailureCategory::BlockhashExpired,
+            TransferOutcome::TimedOut => FailureCategory::TimedOut,
+            TransferOutcome::Confirmed(_)
+            | TransferOutcome::Sent(_)
+            | TransferOutcome::Simulated => continue,
+        };
+        *histogram.entry(category).or_insert(0) += 1;
+    }
+    histogram
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+    use solana_client::rpc_response::RpcSimulateTransactionResult;
+    use solana_sdk::{pubkey::Pubkey, signature::Signature};
+    use std::{io, time::Duration};
+
+    fn record(outcome: TransferOutcome) -> TransferRecord {
+        TransferRecord {
+            sender: Pubkey::new_unique(),
+            receiver: Pubkey::new_unique(),
+            amount: 1,
+            duration: Duration::ZERO,
+            outcome,
+        }
+    }
+
+    fn failed(err: Box<dyn Error + Send + Sync>) -> TransferRecord {
+        record(TransferOutcome::Failed(
+            err.to_string(),
+            FailureCategory::from_error(err.as_ref()),
+        ))
+    }
+
+    #[test]
+    fn test_mixed_failures_are_grouped_by_category() {
+        let preflight = ClientError::from(RpcError::RpcResponseError {
+            code: -32002,
+            message: "Transaction simulation failed".to_string(),
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(
+                RpcSimulateTransactionResult {
+                    err: Some(TransactionError::InstructionError(
+                        0,
+                        InstructionError::Custom(SYSTEM_ERROR_RESULT_WITH_NEGATIVE_LAMPORTS),
+                    )),
+                    logs: None,
+                    accounts: None,
+                    units_consumed: None,
+                    return_data: None,
+                    inner_instructions: None,
+                },
+            ),
+        });
+        let records = vec![
+            failed(preflight.into()),
+            failed(Box::new(TransactionError::InsufficientFundsForFee)),
+            failed(ClientError::from(TransactionError::BlockhashNotFound).into()),
+            record(TransferOutcome::Dropped(Signature::new_unique())),
+            failed(Box::new(TransactionError::AccountNotFound)),
+            failed(ClientError::from(io::Error::from(io::ErrorKind::ConnectionReset)).into()),
+            failed(
+                ClientError::from(RpcError::RpcRequestError("node is behind".to_string())).into(),
+            ),
+            record(TransferOutcome::TimedOut),
+            failed("Balance changed by 0 lamports, expected 1".into()),
+            record(TransferOutcome::Confirmed(Signature::new_unique())),
+            record(TransferOutcome::Simulated),
+        ];
+
+        let histogram = failure_histogram(&records);
+
+        assert_eq!(
+            histogram.into_iter().collect::<Vec<_>>(),
+            [
+                (FailureCategory::InsufficientFunds, 2),
+                (FailureCategory::BlockhashExpired, 2),
+                (FailureCategory::AccountNotFound, 1),
+                (FailureCategory::NodeError, 2),
+                (FailureCategory::TimedOut, 1),
+                (FailureCategory::Other, 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use solana_sdk::pubkey::Pubkey;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// Ограничивает число одновременных переводов от одного отправителя,
+// разные отправители друг другу не мешают
+pub struct SenderLimiter {
+    limit: usize,
+    semaphores: Mutex<HashMap<Pubkey, Arc<Semaphore>>>,
+}
+
+impl SenderLimiter {
+    pub fn new(limit: usize) -> Self {
+        SenderLimiter {
+            limit: limit.max(1),
+            semaphores: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Разрешение держится до конца перевода и освобождается при drop
+    pub async fn acquire(&self, sender: &Pubkey) -> OwnedSemaphorePermit {
+        let semaphore = Arc::clone(
+            self.semaphores
+                .lock()
+                .unwrap()
+                .entry(*sender)
+                .or_insert_with(|| Arc::new(Semaphore::new(self.limit))),
+        );
+
+        semaphore
+            .acquire_owned()
+            .await
+            .expect("Sender semaphore is never closed")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::time::{sleep, Duration};
+
+    #[derive(Default)]
+    struct InFlight {
+        current: AtomicUsize,
+        max: AtomicUsize,
+    }
+
+    impl InFlight {
+        async fn run(&self) {
+            let current = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max.fetch_max(current, Ordering::SeqCst);
+            sleep(Duration::from_millis(100)).await;
+            self.current.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_same_sender_is_serialized_other_senders_run_concurrently() {
+        let limiter = Arc::new(SenderLimiter::new(1));
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let per_sender: Arc<HashMap<Pubkey, InFlight>> = Arc::new(
+            [(first, InFlight::default()), (second, InFlight::default())]
+                .into_iter()
+                .collect(),
+        );
+        let total = Arc::new(InFlight::default());
+
+        let tasks: Vec<_> = [first, first, second]
+            .into_iter()
+            .map(|sender| {
+                let limiter = Arc::clone(&limiter);
+                let per_sender = Arc::clone(&per_sender);
+                let total = Arc::clone(&total);
+
+                tokio::spawn(async move {
+                    let _permit = limiter.acquire(&sender).await;
+                    tokio::join!(per_sender[&sender].run(), total.run());
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(per_sender[&first].max.load(Ordering::SeqCst), 1);
+        assert_eq!(total.max.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,111 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Plain,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "plain" => Ok(Self::Plain),
+            "json" => Ok(Self::Json),
+            other => Err(format!("Unknown log format: {}", other)),
+        }
+    }
+}
+
+// Логи пишутся в stderr, чтобы не смешиваться с результатами в stdout
+pub fn init_logging(format: LogFormat) {
+    tracing::subscriber::set_global_default(build_subscriber(format, std::io::stderr))
+        .expect("Failed to initialize logging");
+}
+
+fn build_subscriber<W>(format: LogFormat, writer: W) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_writer(writer)
+        .with_target(true);
+
+    match format {
+        LogFormat::Plain => Box::new(builder.finish()),
+        LogFormat::Json => Box::new(builder.json().flatten_event(true).finish()),
+    }
+}
+
+// Буфер для перехвата логов в тестах других модулей
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct BufferWriter(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl BufferWriter {
+    pub(crate) fn subscriber(&self, format: LogFormat) -> Box<dyn Subscriber + Send + Sync> {
+        let writer = self.clone();
+        build_subscriber(format, move || writer.clone())
+    }
+
+    pub(crate) fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl std::io::Write for BufferWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// Собирает в строку все логи, записанные внутри f
+#[cfg(test)]
+pub(crate) fn capture_logs<F: FnOnce()>(format: LogFormat, f: F) -> String {
+    let buffer = BufferWriter::default();
+    tracing::subscriber::with_default(buffer.subscriber(format), f);
+    buffer.contents()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_json_log_record_fields() {
+        let output = capture_logs(LogFormat::Json, || {
+            tracing::info!("Health check completed");
+        });
+
+        let record: serde_json::Value =
+            serde_json::from_str(output.lines().next().expect("No log record written")).unwrap();
+
+        assert!(record["timestamp"].is_string());
+        assert_eq!(record["level"], "INFO");
+        assert_eq!(record["target"], module_path!());
+        assert_eq!(record["message"], "Health check completed");
+    }
+
+    #[test]
+    fn test_log_format_defaults_to_plain() {
+        assert_eq!(LogFormat::default(), LogFormat::Plain);
+        assert_eq!(
+            serde_yaml::from_str::<LogFormat>("json").unwrap(),
+            LogFormat::Json
+        );
+        assert_eq!("JSON".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
+}
@@ -1,18 +1,83 @@
+use airdrop::{fund_wallets, AirdropFunder, AirdropRetry, FaucetFunder, FundingLimits};
+use blockhash::BlockhashCache;
+use breaker::FailureBreaker;
+use confirm::{confirm_signatures, confirmation_progress};
+use explorer::Cluster;
+use failure::FailureCategory;
+use indicatif::ProgressBar;
+use inflight::SenderLimiter;
+use logging::{init_logging, LogFormat};
+use manifest::{config_digest, write_manifest, RunManifest};
+use output::{sender_rollups, AbortedDueToFailures, OutputSink, RunSummary, StdoutSink};
+use pipeline::{run_stages, StageLimits};
+use plan::{assign_receivers, shard_receivers, PairingStrategy, TransferAmount, TransferPlan};
+use rand::Rng;
+use reconcile::{expected_balances, fetch_balances, reconcile_balances};
+use reconfirm::{format_reconfirm, read_report_signatures, reconfirm_signatures};
+use report::{
+    write_dead_letters, write_latency_report, SkipReason, SkippedTransfer, TransferOutcome,
+    TransferRecord,
+};
 use serde::Deserialize;
-use solana_client::{client_error::ClientError, rpc_client::RpcClient};
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    rpc_client::RpcClient,
+    rpc_config::{RpcSendTransactionConfig, RpcTransactionConfig},
+    rpc_custom_error::{
+        JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE, JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+    },
+    rpc_request::RpcError,
+};
 use solana_sdk::{
+    clock::MAX_PROCESSING_AGE,
+    commitment_config::CommitmentLevel,
+    compute_budget::ComputeBudgetInstruction,
+    instruction::Instruction,
     pubkey::Pubkey,
     signature::{Keypair, Signature},
     signer::Signer,
     system_instruction,
     transaction::Transaction,
 };
-use std::time::Instant;
-use std::{fs, str::FromStr, sync::Arc};
-use tokio::{sync::mpsc, task::JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use sweep::sweep_tokens;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+mod airdrop;
+mod blockhash;
+mod breaker;
+mod confirm;
+mod explorer;
+mod failure;
+mod inflight;
+mod logging;
+mod manifest;
+#[cfg(test)]
+mod mock;
+mod output;
+mod pipeline;
+mod plan;
+mod reconcile;
+mod reconfirm;
+mod report;
+mod sweep;
 
 static CONFIG_PATH: &str = "config.yaml";
 static LAMPORTS: u64 = 2000000;
+static MAX_SEND_ATTEMPTS: usize = 3;
+static BATCH_CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+// Наибольший лимит compute units, который можно запросить на транзакцию
+static MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
 
 #[derive(Debug, Deserialize)]
 struct Wallet {
@@ -29,132 +94,1410 @@ struct ReceiverWallet {
     public_key: Pubkey,
 }
 
+// Как send_sol обрабатывает отправленную транзакцию
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ConfirmStrategy {
+    #[default]
+    AwaitConfirmation,
+    FireAndForget,
+    SimulateOnly,
+    // Отправляет без ожидания, а затем подтверждает все подписи пачками
+    BatchConfirm,
+    // Отправка и подтверждение идут отдельными стадиями со своими лимитами
+    Pipelined,
+}
+
 #[derive(Debug, Deserialize)]
 struct Config {
     wallets: Vec<Wallet>,
     receivers: Vec<String>,
     rpc_url: String,
+    #[serde(default)]
+    log_format: LogFormat,
+    #[serde(default)]
+    latency_report_path: Option<String>,
+    #[serde(default)]
+    confirm_strategy: ConfirmStrategy,
+    // Через сколько блоков неподтвержденный перевод batch_confirm считается Dropped
+    #[serde(default = "default_expiry_blocks")]
+    transaction_expiry_blocks: u64,
+    // Сколько переводов отправить по каждой паре отправитель-получатель
+    #[serde(default = "default_repetitions")]
+    repetitions: usize,
+    // Удалять повторяющихся получателей вместо простого предупреждения
+    #[serde(default)]
+    dedupe_receivers: bool,
+    // Получатели, удаленные как дубликаты, попадают в сводку пропущенных переводов
+    #[serde(skip)]
+    removed_receivers: Vec<String>,
+    // Только вывести план переводов, не обращаясь к сети; также включается флагом --dry-run
+    #[serde(default)]
+    dry_run: bool,
+    // Текст memo, добавляемый к каждому переводу, например id кампании
+    #[serde(default)]
+    memo: Option<String>,
+    // Не просить ноду симулировать транзакцию перед отправкой
+    #[serde(default)]
+    skip_preflight: bool,
+    #[serde(default)]
+    preflight_commitment: Option<CommitmentLevel>,
+    // После подтверждения сверять изменение баланса получателя с суммой перевода.
+    // Параллельные переводы тому же получателю тоже меняют баланс и дадут расхождение
+    #[serde(default)]
+    verify_transfers: bool,
+    // Искать статус подписи и в истории ноды, а не только в кэше недавних статусов
+    #[serde(default = "default_search_history")]
+    search_transaction_history: bool,
+    // Сколько переводов одного отправителя может быть в полете одновременно, без ограничения по умолчанию
+    #[serde(default)]
+    max_in_flight_per_sender: Option<usize>,
+    // Лимиты одновременных отправок и подтверждений для стратегии pipelined
+    #[serde(default)]
+    send_concurrency: Option<usize>,
+    #[serde(default)]
+    confirm_concurrency: Option<usize>,
+    // Перед отправкой запросить airdrop на каждый кошелек отправителя (devnet/testnet)
+    #[serde(default)]
+    airdrop_lamports: Option<u64>,
+    // Пополнять отправителей переводом с этого кошелька вместо airdrop, формат как у private_key
+    #[serde(default)]
+    faucet_private_key: Option<String>,
+    // Режим sweep: все SPL-токены отправителей переводятся на ATA этого адреса
+    #[serde(default)]
+    token_sweep_treasury: Option<String>,
+    // Этот процесс обрабатывает только свой шард получателей из shard_count
+    #[serde(default)]
+    shard_index: usize,
+    #[serde(default = "default_shard_count")]
+    shard_count: usize,
+    // Режим distribute pot: каждый отправитель делит эту сумму между получателями
+    // вместо фиксированных LAMPORTS каждому; при repetitions делёж повторяется
+    #[serde(default)]
+    distribute_pot: Option<u64>,
+    // Перевод считается окончательным, когда над его слотом набралось столько
+    // подтверждений или он финализирован; 0 - достаточно commitment клиента
+    #[serde(default)]
+    min_confirmations: usize,
+    // Кто кому переводит: по умолчанию каждый отправитель каждому получателю
+    #[serde(default)]
+    pairing_strategy: PairingStrategy,
+    // Сколько может длиться отправка с подтверждением одного перевода, без ограничения по умолчанию
+    #[serde(default)]
+    per_transfer_timeout_ms: Option<u64>,
+    // После кампании сверить итоговые балансы получателей с ожидаемыми по плану
+    #[serde(default)]
+    reconcile_balances: bool,
+    // Комиссия за перевод, которую платит получатель, сам бывший отправителем
+    #[serde(default = "default_transfer_fee")]
+    transfer_fee_lamports: u64,
+    // Добавить к итогам сводку по каждому отправителю
+    #[serde(default)]
+    per_sender_summary: bool,
+    // Как часто переводы запрашивают новый blockhash, 0 - перед каждой отправкой
+    #[serde(default = "default_blockhash_refresh_ms")]
+    blockhash_refresh_ms: u64,
+    // Сколько раз пытаться отправить перевод, по умолчанию MAX_SEND_ATTEMPTS
+    #[serde(default)]
+    max_send_attempts: Option<usize>,
+    // CSV с переводами, исчерпавшими все попытки, для ручного разбора
+    #[serde(default)]
+    dead_letter_path: Option<String>,
+    // Симулировать перевод перед отправкой и ставить лимит compute units
+    // по потреблению плюс этот запас; без него действует лимит по умолчанию
+    #[serde(default)]
+    compute_unit_margin: Option<u32>,
+    // Множитель всех сумм плана для масштабирования нагрузки, например 0.5 или 10
+    #[serde(default)]
+    amount_multiplier: Option<f64>,
+    // Кластер для ссылок на explorer, по умолчанию угадывается по rpc_url
+    #[serde(default)]
+    explorer_cluster: Option<Cluster>,
+    // JSON-манифест кампании с итогами и хэшем конфига
+    #[serde(default)]
+    manifest_path: Option<String>,
+    // Остановить кампанию, когда доля неудачных переводов превысит это значение (0.0-1.0);
+    // доля проверяется, только когда завершилось не меньше min_sample переводов
+    #[serde(default)]
+    max_failure_rate: Option<f64>,
+    #[serde(default = "default_min_sample")]
+    min_sample: usize,
+    // Хэш содержимого файла конфига, заполняется при загрузке
+    #[serde(skip)]
+    config_digest: String,
+}
+
+// Параметры отправки одного перевода, общие для всей кампании
+#[derive(Debug, Clone, Default)]
+struct SendOptions {
+    strategy: ConfirmStrategy,
+    memo: Option<String>,
+    skip_preflight: bool,
+    preflight_commitment: Option<CommitmentLevel>,
+    verify_balance: bool,
+    search_history: bool,
+    min_confirmations: usize,
+    transfer_timeout: Option<Duration>,
+    max_attempts: Option<usize>,
+    compute_unit_margin: Option<u32>,
+    // Общий для всех переводов кампании
+    blockhash_cache: Arc<BlockhashCache>,
+    // Номер перевода в кампании для nonce-memo, см. send_sol
+    transfer_nonce: Arc<AtomicU64>,
+    // Когда сработал, новые переводы не начинаются
+    breaker: Option<Arc<FailureBreaker>>,
+}
+
+impl SendOptions {
+    fn from_config(config: &Config) -> Self {
+        SendOptions {
+            strategy: config.confirm_strategy,
+            memo: config.memo.clone(),
+            skip_preflight: config.skip_preflight,
+            preflight_commitment: config.preflight_commitment,
+            verify_balance: config.verify_transfers,
+            search_history: config.search_transaction_history,
+            min_confirmations: config.min_confirmations,
+            transfer_timeout: config.per_transfer_timeout_ms.map(Duration::from_millis),
+            max_attempts: config.max_send_attempts,
+            compute_unit_margin: config.compute_unit_margin,
+            blockhash_cache: Arc::new(BlockhashCache::new(Duration::from_millis(
+                config.blockhash_refresh_ms,
+            ))),
+            transfer_nonce: Arc::default(),
+            breaker: config
+                .max_failure_rate
+                .map(|rate| Arc::new(FailureBreaker::new(rate, config.min_sample))),
+        }
+    }
+
+    fn aborted(&self) -> bool {
+        self.breaker
+            .as_ref()
+            .is_some_and(|breaker| breaker.tripped())
+    }
+
+    fn record_outcome(&self, outcome: &TransferOutcome) {
+        if let Some(breaker) = &self.breaker {
+            breaker.record(outcome);
+        }
+    }
+
+    fn max_attempts(&self) -> usize {
+        self.max_attempts.unwrap_or(MAX_SEND_ATTEMPTS)
+    }
+
+    fn rpc_send_config(&self) -> RpcSendTransactionConfig {
+        RpcSendTransactionConfig {
+            skip_preflight: self.skip_preflight,
+            preflight_commitment: self.preflight_commitment,
+            ..RpcSendTransactionConfig::default()
+        }
+    }
+}
+
+fn default_repetitions() -> usize {
+    1
+}
+
+fn default_shard_count() -> usize {
+    1
+}
+
+fn default_search_history() -> bool {
+    true
+}
+
+fn default_blockhash_refresh_ms() -> u64 {
+    2_000
+}
+
+fn default_transfer_fee() -> u64 {
+    5_000
+}
+
+fn default_min_sample() -> usize {
+    20
+}
+
+fn default_expiry_blocks() -> u64 {
+    MAX_PROCESSING_AGE as u64
 }
 
 // Отправка транзакции
-async fn send_sol(
+// Подписи всех отправленных попыток запоминаются: перед повтором проверяем,
+// не подтвердилась ли одна из них, чтобы не отправить перевод дважды
+// Подписывает любой Signer, не обязательно локальный Keypair: например, удаленный или HSM
+async fn send_sol<S: Signer>(
     client: &RpcClient,
-    sender: &Keypair,
+    sender: &S,
     receiver: &Pubkey,
     amount: u64,
+    options: &SendOptions,
 ) -> Result<Signature, Box<dyn std::error::Error + Send + Sync>> {
-    let instruction = system_instruction::transfer(&sender.pubkey(), receiver, amount);
-    let recent_blockhash = client
-        .get_latest_blockhash()
-        .expect("Cannot get latest blockhash");
+    let mut instructions =
+        transfer_instructions(&sender.pubkey(), receiver, amount, options.memo.as_deref());
+    // С общим blockhash повторы одной пары и суммы подписали бы одинаковые транзакции,
+    // и кластер принял бы только одну. Nonce свой у каждого перевода, но общий для его
+    // попыток: повтор по-прежнему совпадает с первой отправкой и не спишет сумму дважды
+    let nonce = options.transfer_nonce.fetch_add(1, Ordering::Relaxed);
+    instructions.push(spl_memo::build_memo(
+        format!("nonce:{}", nonce).as_bytes(),
+        &[],
+    ));
+    if let Some(margin) = options.compute_unit_margin {
+        let limit = simulated_unit_limit(client, sender, &instructions, margin, options).await?;
+        instructions.insert(0, ComputeBudgetInstruction::set_compute_unit_limit(limit));
+    }
+    let mut submitted: Vec<Signature> = Vec::new();
+    let mut last_error: Option<ClientError> = None;
+    let max_attempts = options.max_attempts();
+
+    for attempt in 1..=max_attempts {
+        if let Some(signature) = find_confirmed_signature(client, &submitted)? {
+            return Ok(signature);
+        }
+
+        let recent_blockhash = options.blockhash_cache.latest(client).await?;
+        let transaction = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&sender.pubkey()),
+            &[sender],
+            recent_blockhash,
+        );
 
+        let result = match options.strategy {
+            // Без preflight отправляем с нужным конфигом и сами ждем подтверждения
+            ConfirmStrategy::AwaitConfirmation if options.skip_preflight => {
+                submitted.push(transaction.signatures[0]);
+                match client.send_transaction_with_config(&transaction, options.rpc_send_config()) {
+                    Ok(signature) => client
+                        .poll_for_signature_with_commitment(&signature, client.commitment())
+                        .map(|_| signature),
+                    Err(err) => Err(err),
+                }
+            }
+            ConfirmStrategy::AwaitConfirmation => {
+                submitted.push(transaction.signatures[0]);
+                client.send_and_confirm_transaction(&transaction)
+            }
+            ConfirmStrategy::FireAndForget
+            | ConfirmStrategy::BatchConfirm
+            | ConfirmStrategy::Pipelined => {
+                submitted.push(transaction.signatures[0]);
+                client.send_transaction_with_config(&transaction, options.rpc_send_config())
+            }
+            ConfirmStrategy::SimulateOnly => return simulate_transfer(client, &transaction),
+        };
+
+        match result {
+            Ok(signature) => return Ok(signature),
+            Err(err) if classify_error(&err) == ErrorClass::Permanent => {
+                warn!(
+                    "Attempt {}/{} from wallet {} failed permanently: {}",
+                    attempt,
+                    max_attempts,
+                    sender.pubkey(),
+                    err
+                );
+                last_error = Some(err);
+                break;
+            }
+            Err(err) => {
+                warn!(
+                    "Attempt {}/{} from wallet {} failed: {}",
+                    attempt,
+                    max_attempts,
+                    sender.pubkey(),
+                    err
+                );
+                last_error = Some(err);
+            }
+        }
+    }
+
+    if let Some(signature) = find_confirmed_signature(client, &submitted)? {
+        return Ok(signature);
+    }
+
+    Err(last_error
+        .map(Into::into)
+        .unwrap_or_else(|| "No send attempts were made".into()))
+}
+
+// Инструкции перевода; memo, если задано, идет первой и подписывается отправителем
+fn transfer_instructions(
+    sender: &Pubkey,
+    receiver: &Pubkey,
+    amount: u64,
+    memo: Option<&str>,
+) -> Vec<Instruction> {
+    let mut instructions = Vec::with_capacity(2);
+
+    if let Some(memo) = memo {
+        instructions.push(spl_memo::build_memo(memo.as_bytes(), &[sender]));
+    }
+    instructions.push(system_instruction::transfer(sender, receiver, amount));
+
+    instructions
+}
+
+// Лимит compute units по симуляции: сколько потратил перевод плюс запас.
+// Симулируем уже с инструкцией лимита, чтобы ее стоимость тоже попала в замер
+async fn simulated_unit_limit<S: Signer>(
+    client: &RpcClient,
+    sender: &S,
+    instructions: &[Instruction],
+    margin: u32,
+    options: &SendOptions,
+) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+    let mut simulated = Vec::with_capacity(instructions.len() + 1);
+    simulated.push(ComputeBudgetInstruction::set_compute_unit_limit(
+        MAX_COMPUTE_UNIT_LIMIT,
+    ));
+    simulated.extend_from_slice(instructions);
+
+    let recent_blockhash = options.blockhash_cache.latest(client).await?;
     let transaction = Transaction::new_signed_with_payer(
-        &[instruction],
+        &simulated,
         Some(&sender.pubkey()),
         &[sender],
         recent_blockhash,
     );
+    let response = client.simulate_transaction(&transaction)?.value;
+    if let Some(err) = response.err {
+        return Err(format!("Simulation failed: {}", err).into());
+    }
 
-    let signature = client.send_and_confirm_transaction(&transaction)?;
+    let consumed = response
+        .units_consumed
+        .ok_or("Simulation did not report consumed compute units")?;
+    let limit = consumed
+        .saturating_add(margin as u64)
+        .min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32;
+    info!(
+        "Transfer from {} consumed {} compute units, limit set to {}",
+        sender.pubkey(),
+        consumed,
+        limit
+    );
 
-    Ok(signature)
+    Ok(limit)
+}
+
+// Перевод только симулируется на ноде, в сеть ничего не отправляется
+fn simulate_transfer(
+    client: &RpcClient,
+    transaction: &Transaction,
+) -> Result<Signature, Box<dyn std::error::Error + Send + Sync>> {
+    let response = client.simulate_transaction(transaction)?;
+
+    match response.value.err {
+        Some(err) => Err(err.into()),
+        None => Ok(transaction.signatures[0]),
+    }
+}
+
+// Ищем среди уже отправленных подписей успешно подтвержденную
+fn find_confirmed_signature(
+    client: &RpcClient,
+    submitted: &[Signature],
+) -> Result<Option<Signature>, Box<dyn std::error::Error + Send + Sync>> {
+    if submitted.is_empty() {
+        return Ok(None);
+    }
+
+    let statuses = client.get_signature_statuses(submitted)?.value;
+
+    Ok(submitted
+        .iter()
+        .zip(statuses)
+        .find(|(_, status)| {
+            status.as_ref().is_some_and(|status| {
+                status.err.is_none() && status.satisfies_commitment(client.commitment())
+            })
+        })
+        .map(|(signature, _)| *signature))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SignatureState {
+    Confirmed,
+    NotFound,
+}
+
+// Transient - сбой сети или ноды, запрос имеет смысл повторить
+// Permanent - транзакция или запрос отклонены, повтор не поможет
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorClass {
+    Transient,
+    Permanent,
+}
+
+fn classify_error(err: &ClientError) -> ErrorClass {
+    match err.kind() {
+        ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => ErrorClass::Transient,
+        ClientErrorKind::RpcError(RpcError::RpcRequestError(_)) => ErrorClass::Transient,
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. })
+            if *code == JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+                || *code == JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE =>
+        {
+            ErrorClass::Transient
+        }
+        _ => ErrorClass::Permanent,
+    }
+}
+
+#[derive(Debug)]
+struct StatusError {
+    class: ErrorClass,
+    error: ClientError,
+}
+
+impl From<ClientError> for StatusError {
+    fn from(error: ClientError) -> Self {
+        StatusError {
+            class: classify_error(&error),
+            error,
+        }
+    }
+}
+
+impl std::fmt::Display for StatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?} error: {}", self.class, self.error)
+    }
 }
 
 // Проверка статуса транзакции
+// Недавние статусы хранятся ограниченное время: с search_history нода ищет статус
+// и в истории, это тяжелее; если статус не найден, дополнительно смотрим get_transaction
 async fn check_transaction_status(
     client: &RpcClient,
     signature: &Signature,
-) -> Result<(), ClientError> {
-    return match client.get_signature_status(signature) {
-        Ok(value) => match value {
-            Some(value) => match value {
-                Ok(_) => Ok(()),
-                Err(err) => {
-                    println!("Transaction error!");
-                    Err(err.into())
-                }
-            },
-            None => return Ok(()),
+    search_history: bool,
+) -> Result<SignatureState, StatusError> {
+    let statuses = if search_history {
+        client.get_signature_statuses_with_history(&[*signature])
+    } else {
+        client.get_signature_statuses(&[*signature])
+    };
+
+    match statuses.map(|response| response.value.into_iter().next().flatten()) {
+        Ok(Some(status)) if status.satisfies_commitment(client.commitment()) => match status.err {
+            Some(err) => {
+                error!("Transaction error!");
+                Err(ClientError::from(err).into())
+            }
+            None => Ok(SignatureState::Confirmed),
         },
+        Ok(_) => Ok(lookup_transaction_history(client, signature).await?),
         Err(err) => {
-            println!("Transaction error!");
+            error!("Transaction error!");
             Err(err.into())
         }
+    }
+}
+
+async fn lookup_transaction_history(
+    client: &RpcClient,
+    signature: &Signature,
+) -> Result<SignatureState, ClientError> {
+    let config = RpcTransactionConfig {
+        commitment: Some(client.commitment()),
+        max_supported_transaction_version: Some(0),
+        ..RpcTransactionConfig::default()
     };
+
+    match client.get_transaction_with_config(signature, config) {
+        Ok(transaction) => match transaction.transaction.meta.and_then(|meta| meta.err) {
+            Some(err) => {
+                error!("Transaction error!");
+                Err(err.into())
+            }
+            None => Ok(SignatureState::Confirmed),
+        },
+        // Нода отвечает null, если транзакции нет в истории
+        Err(err) if matches!(err.kind(), ClientErrorKind::SerdeJson(_)) => {
+            Ok(SignatureState::NotFound)
+        }
+        Err(err) => Err(err),
+    }
 }
 
-// С каждого кошелька отправляем транзакции всем другим кошелькам
-async fn send_transactions<'a>(config: &'a Config, client: Arc<RpcClient>) {
-    let mut tasks: Vec<JoinHandle<Result<(), ()>>> = vec![];
-    let (senders, receivers) = process_wallets(config);
+// План кампании по Config: кто кому и сколько переводит, без обращения к сети.
+// Получатели, удаленные как дубликаты, записываются в пропущенные у каждого отправителя
+fn plan_transfers<R: Rng + ?Sized>(config: &Config, rng: &mut R) -> Result<TransferPlan, String> {
+    let senders: Vec<Pubkey> = config
+        .wallets
+        .iter()
+        .map(|wallet| get_public_key(&wallet.public_key))
+        .collect();
+    let receivers: Vec<Pubkey> = config
+        .receivers
+        .iter()
+        .map(|receiver| get_public_key(receiver))
+        .collect();
 
-    for sender_wallet in senders {
-        let sender_ref = Arc::new(sender_wallet);
-        let client = Arc::clone(&client);
+    if config.pairing_strategy == PairingStrategy::Zipped && senders.len() != receivers.len() {
+        warn!(
+            "Zipped pairing with {} senders and {} receivers, unmatched wallets are skipped",
+            senders.len(),
+            receivers.len()
+        );
+    }
+    let assignments: Vec<Vec<Pubkey>> =
+        assign_receivers(senders.len(), receivers.len(), config.pairing_strategy, rng)
+            .iter()
+            .map(|assigned| assigned.iter().map(|&index| receivers[index]).collect())
+            .collect();
 
-        receivers.iter().for_each(|receiver_wallet| {
-            let sender_ref = Arc::clone(&sender_ref);
-            let receiver_ref = Arc::new(*receiver_wallet);
-            let client = Arc::clone(&client);
+    let mut plan = TransferPlan::new(
+        &senders,
+        &assignments,
+        match config.distribute_pot {
+            Some(pot) => TransferAmount::Pot(pot),
+            None => TransferAmount::Fixed(LAMPORTS),
+        },
+        config.repetitions,
+    );
+    for sender in &senders {
+        plan.skipped.extend(
+            config
+                .removed_receivers
+                .iter()
+                .map(|removed| SkippedTransfer {
+                    sender: *sender,
+                    receiver: get_public_key(removed),
+                    reason: SkipReason::DuplicateReceiver,
+                }),
+        );
+    }
+
+    match config.amount_multiplier {
+        Some(multiplier) => plan.scaled(multiplier),
+        None => Ok(plan),
+    }
+}
 
-            let task = tokio::spawn(async move {
-                let start_time = Instant::now();
+// Хватит ли отправителям на все переводы плана вместе с комиссиями
+fn check_sender_balances(
+    client: &RpcClient,
+    plan: &TransferPlan,
+    fee_lamports: u64,
+) -> Result<(), String> {
+    for sender in &plan.senders {
+        let required = sender.total + sender.transfers as u64 * fee_lamports;
+        let balance = client
+            .get_balance(&sender.sender)
+            .map_err(|err| format!("Failed to read balance of {}: {}", sender.sender, err))?;
+        if balance < required {
+            return Err(format!(
+                "Sender {} has {} lamports, the scaled plan needs {}",
+                sender.sender, balance, required
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Выполняет план переводов, составленный plan_transfers
+// Пары обрабатываются параллельно, повторы внутри пары идут последовательно,
+// и каждый повтор подписывается со своим nonce в send_sol
+async fn send_transactions(
+    config: &Config,
+    plan: &TransferPlan,
+    client: Arc<RpcClient>,
+    sink: Arc<dyn OutputSink>,
+) -> Vec<TransferRecord> {
+    let strategy = config.confirm_strategy;
+    let repetitions = plan.repetitions;
+    let options = Arc::new(SendOptions::from_config(config));
+    let breaker = options.breaker.clone();
+    let limiter = config
+        .max_in_flight_per_sender
+        .map(|limit| Arc::new(SenderLimiter::new(limit)));
+
+    sink.progress(&plan.to_string());
+
+    if config.dry_run {
+        info!("Dry run, no transactions were sent");
+        return Vec::new();
+    }
 
-                match send_sol(
-                    &client,
-                    &sender_ref.private_key,
-                    &receiver_ref.public_key,
-                    LAMPORTS,
+    let started_at = SystemTime::now();
+    let skipped = &plan.skipped;
+    let (senders, _) = process_wallets(config);
+    let senders: HashMap<Pubkey, Arc<SenderWallet>> = senders
+        .into_iter()
+        .map(|wallet| (wallet.public_key, Arc::new(wallet)))
+        .collect();
+    let pairs: Vec<(Arc<SenderWallet>, Arc<ReceiverWallet>, u64)> = plan
+        .items
+        .iter()
+        .map(|item| {
+            let sender = senders
+                .get(&item.sender)
+                .expect("Plan only refers to configured senders");
+            let receiver = ReceiverWallet {
+                public_key: item.receiver,
+            };
+            (Arc::clone(sender), Arc::new(receiver), item.amount)
+        })
+        .collect();
+
+    // Балансы читаем до отправки, чтобы потом сверить их с планом
+    let initial_balances = config.reconcile_balances.then(|| {
+        let mut planned: Vec<Pubkey> = pairs
+            .iter()
+            .map(|(_, receiver, _)| receiver.public_key)
+            .collect();
+        planned.sort();
+        planned.dedup();
+        fetch_balances(&client, &planned)
+    });
+
+    let mut records = if strategy == ConfirmStrategy::Pipelined {
+        let limits = StageLimits {
+            send: config.send_concurrency,
+            confirm: config.confirm_concurrency,
+        };
+        send_pipelined(
+            pairs,
+            repetitions,
+            limits,
+            config.transaction_expiry_blocks,
+            Arc::clone(&client),
+            options,
+            limiter,
+            Arc::clone(&sink),
+        )
+        .await
+    } else {
+        send_per_pair(
+            pairs,
+            repetitions,
+            Arc::clone(&client),
+            options,
+            limiter,
+            Arc::clone(&sink),
+        )
+        .await
+    };
+
+    if strategy == ConfirmStrategy::BatchConfirm {
+        confirm_sent_records(
+            &client,
+            &mut records,
+            config.transaction_expiry_blocks,
+            config.min_confirmations,
+        )
+        .await;
+    }
+
+    let mut summary = RunSummary::new(&records, skipped);
+    if config.per_sender_summary {
+        summary.senders = sender_rollups(&records);
+    }
+    if let Some(breaker) = breaker.filter(|breaker| breaker.tripped()) {
+        let (sampled, failed) = breaker.counts();
+        error!(
+            "Campaign aborted: {} of {} transfers failed, {} planned transfers were not sent",
+            failed,
+            sampled,
+            plan.transfers.saturating_sub(records.len())
+        );
+        summary.aborted = Some(AbortedDueToFailures { sampled, failed });
+    }
+    sink.summary(&summary);
+
+    if let Some(path) = &config.dead_letter_path {
+        match write_dead_letters(path, &summary.dead_letters) {
+            Ok(()) => info!(
+                "Wrote {} dead letters to {}",
+                summary.dead_letters.len(),
+                path
+            ),
+            Err(err) => error!("Failed to write dead letters to {}: {}", path, err),
+        }
+    }
+
+    if let Some(path) = &config.manifest_path {
+        let manifest = RunManifest::new(
+            &config.config_digest,
+            started_at,
+            SystemTime::now(),
+            &summary,
+            &records,
+            config.latency_report_path.as_deref(),
+        );
+        match write_manifest(path, &manifest) {
+            Ok(()) => info!("Run manifest written to {}", path),
+            Err(err) => error!("Failed to write run manifest to {}: {}", path, err),
+        }
+    }
+
+    if let Some(initial) = initial_balances {
+        let expected = expected_balances(&initial, &records, config.transfer_fee_lamports);
+        let discrepancies = reconcile_balances(&client, &expected);
+        for discrepancy in &discrepancies {
+            error!(
+                "Balance of {} is {:?}, expected {}",
+                discrepancy.receiver, discrepancy.actual, discrepancy.expected
+            );
+        }
+        if discrepancies.is_empty() {
+            info!("Balances of {} receivers match the plan", expected.len());
+        }
+    }
+
+    records
+}
+
+// Каждая пара отправитель-получатель отправляет свои повторы отдельной задачей
+async fn send_per_pair(
+    pairs: Vec<(Arc<SenderWallet>, Arc<ReceiverWallet>, u64)>,
+    repetitions: usize,
+    client: Arc<RpcClient>,
+    options: Arc<SendOptions>,
+    limiter: Option<Arc<SenderLimiter>>,
+    sink: Arc<dyn OutputSink>,
+) -> Vec<TransferRecord> {
+    let mut tasks: Vec<JoinHandle<Vec<TransferRecord>>> = vec![];
+
+    for (sender_ref, receiver_ref, amount) in pairs {
+        let client = Arc::clone(&client);
+        let options = Arc::clone(&options);
+        let limiter = limiter.clone();
+        let sink = Arc::clone(&sink);
+
+        let task = tokio::spawn(async move {
+            let mut records = Vec::with_capacity(repetitions);
+
+            for _ in 0..repetitions {
+                let _permit = match &limiter {
+                    Some(limiter) => Some(limiter.acquire(&sender_ref.public_key).await),
+                    None => None,
+                };
+                if options.aborted() {
+                    break;
+                }
+                let record = send_transfer_with_timeout(
+                    Arc::clone(&client),
+                    Arc::clone(&sender_ref),
+                    Arc::clone(&receiver_ref),
+                    amount,
+                    Arc::clone(&options),
                 )
-                .await
-                {
-                    Ok(signature) => {
-                        let duration = start_time.elapsed();
-
-                        println!("Transaction Hash: {:?}, Time: {:?}", signature, duration);
-
-                        match check_transaction_status(&client, &signature).await {
-                            Ok(value) => Ok(value),
-                            Err(err) => {
-                                println!(
-                                    "Error sending from wallet {} to wallet {}: {:?}",
-                                    &sender_ref.public_key, &receiver_ref.public_key, err
-                                );
-                                return Ok(());
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        println!(
-                            "Error sending from wallet {}: {:?}",
-                            &sender_ref.public_key, e
-                        );
-                        return Ok(());
-                    }
+                .await;
+                options.record_outcome(&record.outcome);
+                sink.transfer(&record);
+                records.push(record);
+            }
+
+            records
+        });
+
+        tasks.push(task);
+    }
+
+    let mut records = Vec::with_capacity(tasks.len() * repetitions);
+    for task in tasks {
+        match task.await {
+            Ok(pair_records) => records.extend(pair_records),
+            Err(err) => error!("Transfer task failed: {}", err),
+        }
+    }
+    records
+}
+
+// Отправка и подтверждение идут отдельными стадиями: отправленные подписи сразу
+// уходят на подтверждение, а лимиты стадий не зависят друг от друга
+#[allow(clippy::too_many_arguments)]
+async fn send_pipelined(
+    pairs: Vec<(Arc<SenderWallet>, Arc<ReceiverWallet>, u64)>,
+    repetitions: usize,
+    limits: StageLimits,
+    expiry_blocks: u64,
+    client: Arc<RpcClient>,
+    options: Arc<SendOptions>,
+    limiter: Option<Arc<SenderLimiter>>,
+    sink: Arc<dyn OutputSink>,
+) -> Vec<TransferRecord> {
+    let transfers: Vec<_> = pairs
+        .into_iter()
+        .flat_map(|pair| std::iter::repeat_n(pair, repetitions))
+        .collect();
+    let send_client = Arc::clone(&client);
+    let confirm_options = Arc::clone(&options);
+    let min_confirmations = options.min_confirmations;
+
+    run_stages(
+        transfers,
+        limits,
+        move |(sender_ref, receiver_ref, amount): (Arc<SenderWallet>, Arc<ReceiverWallet>, u64)| {
+            let (client, options, limiter) = (
+                Arc::clone(&send_client),
+                Arc::clone(&options),
+                limiter.clone(),
+            );
+            async move {
+                let _permit = match &limiter {
+                    Some(limiter) => Some(limiter.acquire(&sender_ref.public_key).await),
+                    None => None,
+                };
+                if options.aborted() {
+                    return None;
                 }
-            });
+                // Таймаут общий на отправку и подтверждение, остаток уходит во вторую стадию
+                let deadline = options
+                    .transfer_timeout
+                    .map(|limit| tokio::time::Instant::now() + limit);
+                let record =
+                    send_transfer_with_timeout(client, sender_ref, receiver_ref, amount, options)
+                        .await;
+                Some((record, deadline))
+            }
+        },
+        move |sent: Option<(TransferRecord, Option<tokio::time::Instant>)>| {
+            let (client, sink, options) = (
+                Arc::clone(&client),
+                Arc::clone(&sink),
+                Arc::clone(&confirm_options),
+            );
+            async move {
+                // Перевод не начинался: кампания уже была остановлена
+                let (mut record, deadline) = sent?;
+                if let TransferOutcome::Sent(signature) = record.outcome {
+                    let confirm = confirm_one(&client, signature, expiry_blocks, min_confirmations);
+                    record.outcome = match deadline {
+                        Some(deadline) => tokio::time::timeout_at(deadline, confirm)
+                            .await
+                            .unwrap_or_else(|_| {
+                                warn!("Confirmation of {} timed out", signature);
+                                TransferOutcome::TimedOut
+                            }),
+                        None => confirm.await,
+                    };
+                }
+                options.record_outcome(&record.outcome);
+                sink.transfer(&record);
+                Some(record)
+            }
+        },
+    )
+    .await
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+async fn confirm_one(
+    client: &RpcClient,
+    signature: Signature,
+    expiry_blocks: u64,
+    min_confirmations: usize,
+) -> TransferOutcome {
+    match confirm_signatures(
+        client,
+        &[signature],
+        BATCH_CONFIRM_TIMEOUT,
+        expiry_blocks,
+        min_confirmations,
+        &ProgressBar::hidden(),
+    )
+    .await
+    {
+        Ok(mut outcomes) => outcomes
+            .pop()
+            .map_or(TransferOutcome::Sent(signature), |(_, outcome)| outcome),
+        Err(err) => {
+            error!("Failed to confirm {}: {}", signature, err);
+            TransferOutcome::Failed(err.to_string(), FailureCategory::from_client_error(&err))
+        }
+    }
+}
+
+// Перевод идет отдельной задачей: по истечении transfer_timeout она отменяется,
+// а перевод помечается TimedOut. Блокирующий RPC-вызов, который уже идет,
+// успевает завершиться, но после него задача не продолжается
+async fn send_transfer_with_timeout(
+    client: Arc<RpcClient>,
+    sender_ref: Arc<SenderWallet>,
+    receiver_ref: Arc<ReceiverWallet>,
+    amount: u64,
+    options: Arc<SendOptions>,
+) -> TransferRecord {
+    let Some(limit) = options.transfer_timeout else {
+        return send_transfer(&client, &sender_ref, &receiver_ref, amount, &options).await;
+    };
+
+    let (sender, receiver) = (sender_ref.public_key, receiver_ref.public_key);
+    let start_time = Instant::now();
+    let mut task = tokio::spawn(async move {
+        send_transfer(&client, &sender_ref, &receiver_ref, amount, &options).await
+    });
+
+    let outcome = match tokio::time::timeout(limit, &mut task).await {
+        Ok(Ok(record)) => return record,
+        Ok(Err(err)) => TransferOutcome::Failed(err.to_string(), FailureCategory::Other),
+        Err(_) => {
+            task.abort();
+            warn!(
+                "Transfer from {} to {} timed out after {:?}",
+                sender, receiver, limit
+            );
+            TransferOutcome::TimedOut
+        }
+    };
+
+    TransferRecord {
+        sender,
+        receiver,
+        amount,
+        duration: start_time.elapsed(),
+        outcome,
+    }
+}
 
-            tasks.push(task);
+// Подтверждаем все отправленные переводы разом вместо опроса каждой подписи
+async fn confirm_sent_records(
+    client: &RpcClient,
+    records: &mut [TransferRecord],
+    expiry_blocks: u64,
+    min_confirmations: usize,
+) {
+    let signatures: Vec<Signature> = records
+        .iter()
+        .filter_map(|record| match record.outcome {
+            TransferOutcome::Sent(signature) => Some(signature),
+            _ => None,
         })
+        .collect();
+
+    let progress = confirmation_progress(signatures.len());
+    match confirm_signatures(
+        client,
+        &signatures,
+        BATCH_CONFIRM_TIMEOUT,
+        expiry_blocks,
+        min_confirmations,
+        &progress,
+    )
+    .await
+    {
+        Ok(outcomes) => {
+            let outcomes: HashMap<Signature, TransferOutcome> = outcomes.into_iter().collect();
+            for record in records.iter_mut() {
+                if let TransferOutcome::Sent(signature) = record.outcome {
+                    record.outcome = outcomes[&signature].clone();
+                }
+            }
+        }
+        Err(err) => error!("Batch confirmation failed: {}", err),
     }
+}
 
-    for task in tasks {
-        let _ = task.await;
+// Один перевод от отправителя получателю вместе с проверкой статуса
+async fn send_transfer(
+    client: &RpcClient,
+    sender_ref: &SenderWallet,
+    receiver_ref: &ReceiverWallet,
+    amount: u64,
+    options: &SendOptions,
+) -> TransferRecord {
+    let strategy = options.strategy;
+    let balance_before = if options.verify_balance && strategy == ConfirmStrategy::AwaitConfirmation
+    {
+        match client.get_balance(&receiver_ref.public_key) {
+            Ok(balance) => Some(balance),
+            Err(err) => {
+                warn!(
+                    "Failed to read balance of {}, transfer will not be verified: {}",
+                    receiver_ref.public_key, err
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let start_time = Instant::now();
+
+    let result = send_sol(
+        client,
+        &sender_ref.private_key,
+        &receiver_ref.public_key,
+        amount,
+        options,
+    )
+    .await;
+    let duration = start_time.elapsed();
+
+    let outcome = match result {
+        Ok(_) if strategy == ConfirmStrategy::SimulateOnly => TransferOutcome::Simulated,
+        Ok(signature)
+            if matches!(
+                strategy,
+                ConfirmStrategy::FireAndForget
+                    | ConfirmStrategy::BatchConfirm
+                    | ConfirmStrategy::Pipelined
+            ) =>
+        {
+            TransferOutcome::Sent(signature)
+        }
+        Ok(signature) => {
+            // Повторяем проверку только при временных сбоях
+            let mut status =
+                check_transaction_status(client, &signature, options.search_history).await;
+            for _ in 1..MAX_SEND_ATTEMPTS {
+                match &status {
+                    Err(err) if err.class == ErrorClass::Transient => {
+                        warn!("Status check for {} failed, retrying: {}", signature, err);
+                        status =
+                            check_transaction_status(client, &signature, options.search_history)
+                                .await;
+                    }
+                    _ => break,
+                }
+            }
+
+            match status {
+                Ok(SignatureState::Confirmed) if options.min_confirmations > 0 => {
+                    await_confirmation_depth(client, signature, options.min_confirmations).await
+                }
+                Ok(SignatureState::Confirmed) => TransferOutcome::Confirmed(signature),
+                Ok(SignatureState::NotFound) => {
+                    error!("Signature {} not found in history", signature);
+                    TransferOutcome::Failed(
+                        "Signature not found".to_string(),
+                        FailureCategory::Other,
+                    )
+                }
+                Err(err) => {
+                    error!(
+                        "Error sending from wallet {} to wallet {}: {:?}",
+                        &sender_ref.public_key, &receiver_ref.public_key, err
+                    );
+                    TransferOutcome::Failed(
+                        err.to_string(),
+                        FailureCategory::from_client_error(&err.error),
+                    )
+                }
+            }
+        }
+        Err(e) => {
+            error!(
+                "Error sending from wallet {}: {:?}",
+                &sender_ref.public_key, e
+            );
+            TransferOutcome::Failed(e.to_string(), FailureCategory::from_error(e.as_ref()))
+        }
+    };
+
+    let outcome = match (outcome, balance_before) {
+        (TransferOutcome::Confirmed(signature), Some(before)) => {
+            verify_balance_delta(client, &receiver_ref.public_key, before, amount, signature)
+        }
+        (outcome, _) => outcome,
+    };
+
+    TransferRecord {
+        sender: sender_ref.public_key,
+        receiver: receiver_ref.public_key,
+        amount,
+        duration,
+        outcome,
+    }
+}
+
+// Перевод уже подтвержден, остается дождаться нужной глубины
+async fn await_confirmation_depth(
+    client: &RpcClient,
+    signature: Signature,
+    min_confirmations: usize,
+) -> TransferOutcome {
+    match confirm_signatures(
+        client,
+        &[signature],
+        BATCH_CONFIRM_TIMEOUT,
+        default_expiry_blocks(),
+        min_confirmations,
+        &ProgressBar::hidden(),
+    )
+    .await
+    {
+        Ok(mut outcomes) => outcomes
+            .pop()
+            .map_or(TransferOutcome::Confirmed(signature), |(_, outcome)| {
+                outcome
+            }),
+        Err(err) => {
+            error!("Failed to confirm {}: {}", signature, err);
+            TransferOutcome::Failed(err.to_string(), FailureCategory::from_client_error(&err))
+        }
+    }
+}
+
+// Подтвержденный перевод, после которого баланс получателя изменился не на amount,
+// считается неудачным
+fn verify_balance_delta(
+    client: &RpcClient,
+    receiver: &Pubkey,
+    before: u64,
+    amount: u64,
+    signature: Signature,
+) -> TransferOutcome {
+    match client.get_balance(receiver) {
+        Ok(after) if after.checked_sub(before) == Some(amount) => {
+            TransferOutcome::Confirmed(signature)
+        }
+        Ok(after) => {
+            let delta = after as i128 - before as i128;
+            error!(
+                "Transfer {} changed balance of {} by {} lamports, expected {}",
+                signature, receiver, delta, amount
+            );
+            TransferOutcome::Failed(
+                format!("Balance changed by {} lamports, expected {}", delta, amount),
+                FailureCategory::Other,
+            )
+        }
+        Err(err) => {
+            warn!("Failed to verify transfer {}: {}", signature, err);
+            TransferOutcome::Confirmed(signature)
+        }
     }
 }
 
 #[tokio::main]
 async fn main() {
     let config_content = fs::read_to_string(CONFIG_PATH).expect("Unable to read config file");
-    let config: Config = serde_yaml::from_str(&config_content).expect("Unable to parse config");
+    let mut config: Config = serde_yaml::from_str(&config_content).expect("Unable to parse config");
+    config.config_digest = config_digest(&config_content);
+    init_logging(config.log_format);
+    check_duplicate_receivers(&mut config);
+    if std::env::args().any(|arg| arg == "--dry-run") {
+        config.dry_run = true;
+    }
     let client = RpcClient::new(config.rpc_url.clone());
     let client_ref = Arc::new(client);
 
-    send_transactions(&config, client_ref).await;
+    // reconfirm [report.csv]: перепроверить подписи из сохраненного отчета
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("reconfirm") {
+        let path = args.get(1).or(config.latency_report_path.as_ref());
+        match path {
+            Some(path) => reconfirm_report(&client_ref, path).await,
+            None => error!("Usage: reconfirm <report.csv> or set latency_report_path"),
+        }
+        return;
+    }
+
+    // Sweep переводит токены на treasury, получатели ему не нужны
+    if let Err(err) = validate_wallets(&config, config.token_sweep_treasury.is_none()) {
+        error!("{}", err);
+        return;
+    }
+
+    if let Err(err) = apply_shard(&mut config) {
+        error!("{}", err);
+        return;
+    }
+
+    if let Some(lamports) = config.airdrop_lamports {
+        fund_senders(&config, Arc::clone(&client_ref), lamports).await;
+    }
+
+    if let Some(treasury) = &config.token_sweep_treasury {
+        sweep_all_tokens(&config, &client_ref, &get_public_key(treasury)).await;
+        return;
+    }
+
+    let plan = match plan_transfers(&config, &mut rand::thread_rng()) {
+        Ok(plan) => plan,
+        Err(err) => {
+            error!("{}", err);
+            return;
+        }
+    };
+    // Умноженные суммы легко превышают балансы, проверяем до первой отправки
+    if config.amount_multiplier.is_some() && !config.dry_run {
+        if let Err(err) = check_sender_balances(&client_ref, &plan, config.transfer_fee_lamports) {
+            error!("{}", err);
+            return;
+        }
+    }
+    let sink = Arc::new(StdoutSink {
+        cluster: config
+            .explorer_cluster
+            .unwrap_or_else(|| Cluster::from_rpc_url(&config.rpc_url)),
+    });
+    let records = send_transactions(&config, &plan, client_ref, sink).await;
+
+    if let Some(path) = &config.latency_report_path {
+        match write_latency_report(path, &records) {
+            Ok(()) => info!("Latency report written to {}", path),
+            Err(err) => error!("Failed to write latency report to {}: {}", path, err),
+        }
+    }
+}
+
+async fn fund_senders(config: &Config, client: Arc<RpcClient>, lamports: u64) {
+    let (senders, _) = process_wallets(config);
+    let wallets: Vec<Pubkey> = senders.iter().map(|sender| sender.public_key).collect();
+    let limits = FundingLimits::default();
+
+    let results = match &config.faucet_private_key {
+        Some(private_key) => {
+            let faucet = match parse_bytes_from_string(private_key)
+                .and_then(|bytes| Keypair::from_bytes(&bytes).map_err(|err| err.to_string()))
+            {
+                Ok(faucet) => faucet,
+                Err(err) => {
+                    error!("Invalid faucet private key: {}", err);
+                    return;
+                }
+            };
+            let funder = Arc::new(FaucetFunder { client, faucet });
+            fund_wallets(funder, &wallets, lamports, &limits).await
+        }
+        None => {
+            let funder = Arc::new(AirdropFunder {
+                client,
+                retry: AirdropRetry::default(),
+            });
+            fund_wallets(funder, &wallets, lamports, &limits).await
+        }
+    };
+
+    for (wallet, result) in results {
+        match result {
+            Ok(balance) => info!("{} funded, balance {} lamports", wallet, balance),
+            Err(err) => error!("{}", err),
+        }
+    }
+}
+
+async fn reconfirm_report(client: &RpcClient, path: &str) {
+    let report = match fs::read_to_string(path) {
+        Ok(report) => report,
+        Err(err) => {
+            error!("Failed to read report {}: {}", path, err);
+            return;
+        }
+    };
+
+    let signatures = read_report_signatures(&report);
+    match reconfirm_signatures(client, &signatures).await {
+        Ok(results) => println!("{}", format_reconfirm(&results)),
+        Err(err) => error!("Failed to fetch signature statuses: {}", err),
+    }
+}
+
+async fn sweep_all_tokens(config: &Config, client: &RpcClient, treasury: &Pubkey) {
+    let (senders, _) = process_wallets(config);
+
+    for sender in &senders {
+        match sweep_tokens(client, &sender.private_key, treasury).await {
+            Ok(swept) => {
+                for (balance, signature) in &swept {
+                    info!(
+                        "Swept {} of mint {} from wallet {}: {}",
+                        balance.amount, balance.mint, sender.public_key, signature
+                    );
+                }
+                info!(
+                    "Swept {} token accounts of wallet {}",
+                    swept.len(),
+                    sender.public_key
+                );
+            }
+            Err(err) => error!(
+                "Failed to list token accounts of wallet {}: {}",
+                sender.public_key, err
+            ),
+        }
+    }
+}
+
+// Без отправителей или получателей запуск ничего бы не сделал, сообщаем об этом сразу
+fn validate_wallets(config: &Config, require_receivers: bool) -> Result<(), String> {
+    if config.wallets.is_empty() {
+        return Err(format!(
+            "No sender wallets configured, add at least one entry to wallets in {}",
+            CONFIG_PATH
+        ));
+    }
+
+    if require_receivers && config.receivers.is_empty() {
+        return Err(format!(
+            "No receivers configured, add at least one address to receivers in {}",
+            CONFIG_PATH
+        ));
+    }
+
+    Ok(())
+}
+
+// Оставляет в конфиге только получателей шарда этого процесса
+fn apply_shard(config: &mut Config) -> Result<(), String> {
+    if config.shard_count == 1 && config.shard_index == 0 {
+        return Ok(());
+    }
+
+    let total = config.receivers.len();
+    config.receivers = shard_receivers(&config.receivers, config.shard_index, config.shard_count)?;
+    info!(
+        "Shard {}/{}: {} of {} receivers",
+        config.shard_index + 1,
+        config.shard_count,
+        config.receivers.len(),
+        total
+    );
+    Ok(())
+}
+
+// Один и тот же получатель в списке означает повторный перевод ему же
+// Возвращает количество найденных дубликатов
+fn check_duplicate_receivers(config: &mut Config) -> usize {
+    let mut seen = HashSet::new();
+    let duplicates: Vec<String> = config
+        .receivers
+        .iter()
+        .filter(|receiver| !seen.insert(receiver.trim().to_string()))
+        .cloned()
+        .collect();
+
+    if duplicates.is_empty() {
+        return 0;
+    }
+
+    if config.dedupe_receivers {
+        config.removed_receivers = duplicates.clone();
+        let mut seen = HashSet::new();
+        config
+            .receivers
+            .retain(|receiver| seen.insert(receiver.trim().to_string()));
+        warn!(
+            "Removed {} duplicate receivers: {}",
+            duplicates.len(),
+            duplicates.join(", ")
+        );
+    } else {
+        warn!(
+            "Found {} duplicate receivers, they will receive multiple transfers: {}",
+            duplicates.len(),
+            duplicates.join(", ")
+        );
+    }
+
+    duplicates.len()
 }
 
 fn process_wallets(config: &Config) -> (Vec<SenderWallet>, Vec<ReceiverWallet>) {
-    return (
+    (
         config
             .wallets
             .iter()
@@ -166,10 +1509,10 @@ fn process_wallets(config: &Config) -> (Vec<SenderWallet>, Vec<ReceiverWallet>)
                 let sender_keypair =
                     Keypair::from_bytes(&bytes).expect("Failed to parse private key");
 
-                return SenderWallet {
+                SenderWallet {
                     public_key: sender_public_key,
                     private_key: sender_keypair,
-                };
+                }
             })
             .collect(),
         config
@@ -178,17 +1521,17 @@ fn process_wallets(config: &Config) -> (Vec<SenderWallet>, Vec<ReceiverWallet>)
             .map(|public_key| {
                 let receiver_public_key: Pubkey = get_public_key(public_key);
 
-                return ReceiverWallet {
+                ReceiverWallet {
                     public_key: receiver_public_key,
-                };
+                }
             })
             .collect(),
-    );
+    )
 }
 
 #[inline(always)]
 fn get_public_key(public_key: &str) -> Pubkey {
-    return Pubkey::from_str(&public_key).expect("Failed to parse public key");
+    Pubkey::from_str(public_key).expect("Failed to parse public key")
 }
 
 #[inline(always)]
@@ -196,13 +1539,23 @@ fn parse_bytes_from_string(input: &str) -> Result<Vec<u8>, String> {
     let trimmed = input.trim_matches(['[', ']'].as_ref());
     let result: Result<Vec<u8>, _> = trimmed
         .split(',')
-        .map(|s| {
-            s.trim()
+        .enumerate()
+        .map(|(index, s)| {
+            let token = s.trim();
+            token
                 .parse::<u16>()
-                .map_err(|e| format!("Failed to parse number: {}", e))
+                .map_err(|e| {
+                    format!(
+                        "Failed to parse number at index {} ({:?}): {}",
+                        index, token, e
+                    )
+                })
                 .and_then(|num| {
                     if num > 255 {
-                        Err(format!("Number {} out of byte range", num))
+                        Err(format!(
+                            "Number {} at index {} out of byte range",
+                            num, index
+                        ))
                     } else {
                         Ok(num as u8)
                     }
@@ -212,3 +1565,1051 @@ fn parse_bytes_from_string(input: &str) -> Result<Vec<u8>, String> {
 
     result
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use mock::{balance_response, timeout_error, MockRpc};
+    use output::{OutputEvent, VecSink};
+    use plan::PlannedTransfer;
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+
+    fn plan_for(config: &Config) -> TransferPlan {
+        plan_transfers(config, &mut rand::thread_rng()).unwrap()
+    }
+
+    fn test_config(senders: &[Keypair], receivers: &[Pubkey]) -> Config {
+        Config {
+            wallets: senders
+                .iter()
+                .map(|keypair| Wallet {
+                    private_key: format!("{:?}", keypair.to_bytes()),
+                    public_key: keypair.pubkey().to_string(),
+                })
+                .collect(),
+            receivers: receivers.iter().map(Pubkey::to_string).collect(),
+            rpc_url: "mock".to_string(),
+            log_format: LogFormat::default(),
+            latency_report_path: None,
+            confirm_strategy: ConfirmStrategy::default(),
+            transaction_expiry_blocks: default_expiry_blocks(),
+            repetitions: default_repetitions(),
+            dedupe_receivers: false,
+            removed_receivers: Vec::new(),
+            dry_run: false,
+            memo: None,
+            skip_preflight: false,
+            preflight_commitment: None,
+            verify_transfers: false,
+            search_transaction_history: default_search_history(),
+            max_in_flight_per_sender: None,
+            send_concurrency: None,
+            confirm_concurrency: None,
+            airdrop_lamports: None,
+            faucet_private_key: None,
+            token_sweep_treasury: None,
+            shard_index: 0,
+            shard_count: default_shard_count(),
+            distribute_pot: None,
+            min_confirmations: 0,
+            pairing_strategy: PairingStrategy::default(),
+            per_transfer_timeout_ms: None,
+            reconcile_balances: false,
+            transfer_fee_lamports: default_transfer_fee(),
+            per_sender_summary: false,
+            blockhash_refresh_ms: default_blockhash_refresh_ms(),
+            max_send_attempts: None,
+            dead_letter_path: None,
+            compute_unit_margin: None,
+            amount_multiplier: None,
+            explorer_cluster: None,
+            manifest_path: None,
+            config_digest: String::new(),
+            max_failure_rate: None,
+            min_sample: default_min_sample(),
+        }
+    }
+
+    #[test]
+    fn test_planner_covers_full_cross_product() {
+        let senders = [Keypair::new(), Keypair::new()];
+        let receivers = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        let mut config = test_config(&senders, &receivers);
+        config.repetitions = 2;
+
+        let plan = plan_for(&config);
+
+        assert_eq!(plan.items.len(), senders.len() * receivers.len());
+        for sender in &senders {
+            let planned: Vec<Pubkey> = plan
+                .items
+                .iter()
+                .filter(|item| item.sender == sender.pubkey())
+                .map(|item| item.receiver)
+                .collect();
+            assert_eq!(planned, receivers);
+        }
+        assert!(plan.items.iter().all(|item| item.amount == LAMPORTS));
+        assert_eq!(plan.transfers, 12);
+        assert!(plan.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_planner_splits_pot_per_item_and_skips_self_send() {
+        let sender = Keypair::new();
+        let receivers = [Pubkey::new_unique(), sender.pubkey(), Pubkey::new_unique()];
+        let mut config = test_config(&[sender.insecure_clone()], &receivers);
+        config.distribute_pot = Some(1_001);
+        config.removed_receivers = vec![Pubkey::new_unique().to_string()];
+
+        let plan = plan_for(&config);
+
+        assert_eq!(
+            plan.items,
+            vec![
+                PlannedTransfer {
+                    sender: sender.pubkey(),
+                    receiver: receivers[0],
+                    amount: 501,
+                },
+                PlannedTransfer {
+                    sender: sender.pubkey(),
+                    receiver: receivers[2],
+                    amount: 500,
+                },
+            ]
+        );
+        assert_eq!(
+            plan.skipped
+                .iter()
+                .map(|skip| skip.reason)
+                .collect::<Vec<_>>(),
+            vec![SkipReason::SelfSend, SkipReason::DuplicateReceiver]
+        );
+    }
+
+    #[test]
+    fn test_empty_senders_fail_early() {
+        let config = test_config(&[], &[Pubkey::new_unique()]);
+
+        assert_eq!(
+            validate_wallets(&config, true),
+            Err(
+                "No sender wallets configured, add at least one entry to wallets in config.yaml"
+                    .to_string()
+            )
+        );
+
+        let config = test_config(&[Keypair::new()], &[]);
+        assert!(validate_wallets(&config, true).is_err());
+        assert_eq!(validate_wallets(&config, false), Ok(()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_dry_run_does_not_touch_network() {
+        let rpc = MockRpc::new();
+        let mut config = test_config(&[Keypair::new()], &[Pubkey::new_unique()]);
+        config.dry_run = true;
+
+        let records = send_transactions(
+            &config,
+            &plan_for(&config),
+            Arc::new(rpc.client()),
+            Arc::new(VecSink::default()),
+        )
+        .await;
+
+        assert!(records.is_empty());
+        assert_eq!(rpc.calls(RpcRequest::GetLatestBlockhash), 0);
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_output_sink_receives_plan_transfers_and_summary() {
+        let rpc = MockRpc::new();
+        let config = test_config(
+            &[Keypair::new()],
+            &[Pubkey::new_unique(), Pubkey::new_unique()],
+        );
+        let sink = Arc::new(VecSink::default());
+
+        let records = send_transactions(
+            &config,
+            &plan_for(&config),
+            Arc::new(rpc.client()),
+            sink.clone(),
+        )
+        .await;
+        let events = sink.events();
+
+        assert_eq!(events.len(), 4);
+        assert!(matches!(&events[0], OutputEvent::Progress(plan) if plan.contains("Grand total")));
+        // Пары идут параллельно, порядок событий совпадает с порядком завершения
+        for record in &records {
+            assert!(events[1..3].contains(&OutputEvent::Transfer(record.outcome.clone())));
+            assert!(matches!(record.outcome, TransferOutcome::Confirmed(_)));
+        }
+        assert_eq!(
+            events[3],
+            OutputEvent::Summary(RunSummary {
+                attempted: 2,
+                failed: 0,
+                dropped: 0,
+                timed_out: 0,
+                skipped: Default::default(),
+                senders: Default::default(),
+                dead_letters: Vec::new(),
+                failures: Default::default(),
+                aborted: None,
+            })
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_summary_tallies_skipped_transfers() {
+        let rpc = MockRpc::new();
+        let sender = Keypair::new();
+        let (receiver, duplicate) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut config = test_config(
+            &[sender.insecure_clone()],
+            &[sender.pubkey(), receiver, duplicate, duplicate],
+        );
+        config.dedupe_receivers = true;
+        check_duplicate_receivers(&mut config);
+        let sink = Arc::new(VecSink::default());
+
+        let records = send_transactions(
+            &config,
+            &plan_for(&config),
+            Arc::new(rpc.client()),
+            sink.clone(),
+        )
+        .await;
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            sink.events().last(),
+            Some(&OutputEvent::Summary(RunSummary {
+                attempted: 2,
+                failed: 0,
+                dropped: 0,
+                timed_out: 0,
+                skipped: [
+                    (SkipReason::SelfSend, 1),
+                    (SkipReason::DuplicateReceiver, 1)
+                ]
+                .into_iter()
+                .collect(),
+                senders: Default::default(),
+                dead_letters: Vec::new(),
+                failures: Default::default(),
+                aborted: None,
+            }))
+        );
+    }
+
+    async fn verified_transfer(balance_after: u64) -> (MockRpc, TransferRecord) {
+        let rpc = MockRpc::new();
+        rpc.push(RpcRequest::GetBalance, Ok(balance_response(1_000)))
+            .push(RpcRequest::GetBalance, Ok(balance_response(balance_after)));
+        let sender = Keypair::new();
+        let record = send_transfer(
+            &rpc.client(),
+            &SenderWallet {
+                public_key: sender.pubkey(),
+                private_key: sender,
+            },
+            &ReceiverWallet {
+                public_key: Pubkey::new_unique(),
+            },
+            LAMPORTS,
+            &SendOptions {
+                verify_balance: true,
+                ..SendOptions::default()
+            },
+        )
+        .await;
+        (rpc, record)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_verified_transfer_matches_balance_delta() {
+        let (rpc, record) = verified_transfer(1_000 + LAMPORTS).await;
+
+        assert!(matches!(record.outcome, TransferOutcome::Confirmed(_)));
+        assert_eq!(rpc.calls(RpcRequest::GetBalance), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_balance_delta_mismatch_fails_transfer() {
+        let (_, record) = verified_transfer(1_000).await;
+
+        assert_eq!(
+            record.outcome,
+            TransferOutcome::Failed(
+                format!("Balance changed by 0 lamports, expected {}", LAMPORTS),
+                FailureCategory::Other
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_bytes_reports_bad_element_index() {
+        let mut elements: Vec<String> = (0..64).map(|i| i.to_string()).collect();
+        elements[30] = "3o".to_string();
+        let input = format!("[{}]", elements.join(", "));
+
+        let err = parse_bytes_from_string(&input).unwrap_err();
+
+        assert!(err.contains("index 30"), "unexpected error: {}", err);
+        assert!(err.contains("\"3o\""), "unexpected error: {}", err);
+        assert_eq!(parse_bytes_from_string("[1, 2, 255]"), Ok(vec![1, 2, 255]));
+    }
+
+    #[test]
+    fn test_duplicate_receivers_are_reported() {
+        let receiver = Pubkey::new_unique();
+        let mut config = test_config(&[], &[receiver, Pubkey::new_unique(), receiver]);
+
+        let output = logging::capture_logs(LogFormat::Plain, || {
+            assert_eq!(check_duplicate_receivers(&mut config), 1);
+        });
+
+        assert_eq!(config.receivers.len(), 3);
+        assert!(output.contains("WARN"), "no warning in {}", output);
+        assert!(output.contains(&receiver.to_string()));
+    }
+
+    #[test]
+    fn test_duplicate_receivers_are_removed_when_dedupe_enabled() {
+        let receiver = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let mut config = test_config(&[], &[receiver, other, receiver]);
+        config.dedupe_receivers = true;
+
+        let output = logging::capture_logs(LogFormat::Plain, || {
+            assert_eq!(check_duplicate_receivers(&mut config), 1);
+        });
+
+        assert_eq!(
+            config.receivers,
+            vec![receiver.to_string(), other.to_string()]
+        );
+        assert!(output.contains("Removed 1 duplicate receivers"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_send_sol_skips_resubmit_when_signature_confirmed() {
+        let rpc = MockRpc::new();
+        rpc.push(RpcRequest::SendTransaction, Err(timeout_error()));
+        let client = rpc.client();
+        let sender = Keypair::new();
+
+        let signature = send_sol(
+            &client,
+            &sender,
+            &Pubkey::new_unique(),
+            LAMPORTS,
+            &SendOptions::default(),
+        )
+        .await
+        .expect("Confirmed signature should count as sent");
+
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 1);
+        assert_eq!(
+            rpc.params(RpcRequest::GetSignatureStatuses)[0][0],
+            json!([signature.to_string()])
+        );
+    }
+
+    // Signer, который подписывает через Keypair и запоминает подписанные сообщения
+    struct RecordingSigner {
+        keypair: Keypair,
+        messages: std::sync::Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl Signer for RecordingSigner {
+        fn try_pubkey(&self) -> Result<Pubkey, solana_sdk::signer::SignerError> {
+            Ok(self.keypair.pubkey())
+        }
+
+        fn try_sign_message(
+            &self,
+            message: &[u8],
+        ) -> Result<Signature, solana_sdk::signer::SignerError> {
+            self.messages.lock().unwrap().push(message.to_vec());
+            self.keypair.try_sign_message(message)
+        }
+
+        fn is_interactive(&self) -> bool {
+            false
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_send_sol_signs_through_signer_trait() {
+        let rpc = MockRpc::new();
+        let signer = RecordingSigner {
+            keypair: Keypair::new(),
+            messages: Default::default(),
+        };
+
+        let signature = send_sol(
+            &rpc.client(),
+            &signer,
+            &Pubkey::new_unique(),
+            LAMPORTS,
+            &SendOptions {
+                strategy: ConfirmStrategy::FireAndForget,
+                ..SendOptions::default()
+            },
+        )
+        .await
+        .expect("Transfer should be sent");
+
+        let messages = signer.messages.lock().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(signature.verify(signer.pubkey().as_ref(), &messages[0]));
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_transfers_within_interval_share_blockhash() {
+        let rpc = MockRpc::new();
+        let client = Arc::new(rpc.client());
+        let options = Arc::new(SendOptions {
+            strategy: ConfirmStrategy::FireAndForget,
+            blockhash_cache: Arc::new(BlockhashCache::new(Duration::from_secs(60))),
+            ..SendOptions::default()
+        });
+
+        let tasks: Vec<_> = (0..5)
+            .map(|_| {
+                let (client, options) = (Arc::clone(&client), Arc::clone(&options));
+                tokio::spawn(async move {
+                    send_sol(
+                        &client,
+                        &Keypair::new(),
+                        &Pubkey::new_unique(),
+                        LAMPORTS,
+                        &options,
+                    )
+                    .await
+                    .is_ok()
+                })
+            })
+            .collect();
+        for task in tasks {
+            assert!(task.await.unwrap());
+        }
+
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 5);
+        assert_eq!(rpc.calls(RpcRequest::GetLatestBlockhash), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_send_sol_resubmits_unknown_signature() {
+        let rpc = MockRpc::new();
+        rpc.push(RpcRequest::SendTransaction, Err(timeout_error()));
+        rpc.push(
+            RpcRequest::GetSignatureStatuses,
+            Ok(json!({ "context": { "slot": 1 }, "value": [null] })),
+        );
+        let client = rpc.client();
+        let sender = Keypair::new();
+
+        send_sol(
+            &client,
+            &sender,
+            &Pubkey::new_unique(),
+            LAMPORTS,
+            &SendOptions::default(),
+        )
+        .await
+        .expect("Second attempt should succeed");
+
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_exhausted_transfer_lands_in_dead_letters() {
+        let rpc = MockRpc::new();
+        for _ in 0..3 {
+            rpc.push(RpcRequest::SendTransaction, Err(timeout_error()));
+        }
+        for _ in 0..2 {
+            rpc.push(
+                RpcRequest::GetSignatureStatuses,
+                Ok(json!({ "context": { "slot": 1 }, "value": [null, null] })),
+            );
+        }
+        let sender = Keypair::new();
+        let receiver = Pubkey::new_unique();
+        let mut config = test_config(&[sender.insecure_clone()], &[receiver]);
+        config.max_send_attempts = Some(2);
+        let path = std::env::temp_dir().join(format!("dead-letters-{}.csv", receiver));
+        config.dead_letter_path = Some(path.to_str().unwrap().to_string());
+        let sink = Arc::new(VecSink::default());
+
+        send_transactions(
+            &config,
+            &plan_for(&config),
+            Arc::new(rpc.client()),
+            sink.clone(),
+        )
+        .await;
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // Третья ошибка в очереди осталась невостребованной: бюджет - две попытки
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 2);
+        let Some(OutputEvent::Summary(summary)) = sink.events().pop() else {
+            panic!("Summary should be the last event");
+        };
+        assert_eq!(summary.dead_letters.len(), 1);
+        let dead_letter = &summary.dead_letters[0];
+        assert_eq!(
+            (dead_letter.sender, dead_letter.receiver, dead_letter.amount),
+            (sender.pubkey(), receiver, LAMPORTS)
+        );
+        assert!(
+            dead_letter.error.contains("timed out"),
+            "{}",
+            dead_letter.error
+        );
+
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with(&format!("{},{},{},", sender.pubkey(), receiver, LAMPORTS)));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_campaign_aborts_once_failure_rate_exceeds_threshold() {
+        let rpc = MockRpc::new();
+        for _ in 0..10 {
+            rpc.push(RpcRequest::SendTransaction, Err(timeout_error()));
+            rpc.push(
+                RpcRequest::GetSignatureStatuses,
+                Ok(json!({ "context": { "slot": 1 }, "value": [null] })),
+            );
+        }
+        let mut config = test_config(&[Keypair::new()], &[Pubkey::new_unique()]);
+        config.repetitions = 10;
+        config.max_send_attempts = Some(1);
+        config.max_failure_rate = Some(0.5);
+        config.min_sample = 3;
+        let sink = Arc::new(VecSink::default());
+
+        let records = send_transactions(
+            &config,
+            &plan_for(&config),
+            Arc::new(rpc.client()),
+            sink.clone(),
+        )
+        .await;
+
+        // Повторы одной пары идут по очереди, поэтому порог срабатывает ровно после min_sample
+        assert_eq!(records.len(), 3);
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 3);
+        let Some(OutputEvent::Summary(summary)) = sink.events().pop() else {
+            panic!("Summary should be the last event");
+        };
+        assert_eq!(
+            summary.aborted,
+            Some(AbortedDueToFailures {
+                sampled: 3,
+                failed: 3
+            })
+        );
+        assert_eq!((summary.attempted, summary.failed), (3, 3));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_manifest_records_digest_totals_and_senders() {
+        let rpc = MockRpc::new();
+        let senders = [Keypair::new(), Keypair::new()];
+        let mut config = test_config(&senders, &[Pubkey::new_unique()]);
+        config.config_digest = config_digest("rpc_url: mock");
+        config.latency_report_path = Some("latency.csv".to_string());
+        let path = std::env::temp_dir().join(format!("manifest-{}.json", Pubkey::new_unique()));
+        config.manifest_path = Some(path.to_str().unwrap().to_string());
+
+        send_transactions(
+            &config,
+            &plan_for(&config),
+            Arc::new(rpc.client()),
+            Arc::new(VecSink::default()),
+        )
+        .await;
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let manifest: serde_json::Value = serde_json::from_str(&content).unwrap();
+        let mut fields: Vec<&str> = manifest
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        fields.sort();
+        assert_eq!(
+            fields,
+            [
+                "config_digest",
+                "finished_at_unix_ms",
+                "report_path",
+                "senders",
+                "started_at_unix_ms",
+                "totals"
+            ]
+        );
+        assert_eq!(manifest["config_digest"], config_digest("rpc_url: mock"));
+        assert_ne!(manifest["config_digest"], config_digest("rpc_url: other"));
+        assert_eq!(manifest["report_path"], "latency.csv");
+        assert!(
+            manifest["started_at_unix_ms"].as_u64().unwrap()
+                <= manifest["finished_at_unix_ms"].as_u64().unwrap()
+        );
+        assert_eq!(
+            manifest["totals"],
+            json!({
+                "attempted": 2,
+                "succeeded": 2,
+                "failed": 0,
+                "dropped": 0,
+                "timed_out": 0,
+                "skipped": 0,
+                "lamports": 2 * LAMPORTS,
+            })
+        );
+        for sender in &senders {
+            let rollup = &manifest["senders"][sender.pubkey().to_string()];
+            assert_eq!(
+                (rollup["transfers"].as_u64(), rollup["lamports"].as_u64()),
+                (Some(1), Some(LAMPORTS))
+            );
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_latency_report_has_line_per_transfer() {
+        let rpc = MockRpc::new();
+        let config = test_config(
+            &[Keypair::new(), Keypair::new()],
+            &[Pubkey::new_unique(), Pubkey::new_unique()],
+        );
+
+        let records = send_transactions(
+            &config,
+            &plan_for(&config),
+            Arc::new(rpc.client()),
+            Arc::new(VecSink::default()),
+        )
+        .await;
+
+        let path = std::env::temp_dir().join(format!("latency-{}.csv", Pubkey::new_unique()));
+        let path = path.to_str().unwrap();
+        write_latency_report(path, &records).unwrap();
+        let content = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let lines: Vec<&str> = content.lines().skip(1).collect();
+        assert_eq!(lines.len(), 4);
+        for line in lines {
+            let fields: Vec<&str> = line.split(',').collect();
+            assert_eq!(fields.len(), 5);
+            assert!(fields[2].parse::<f64>().is_ok(), "bad duration in {}", line);
+            assert!(fields[3] == "confirmed" || fields[3] == "failed");
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_repetitions_send_each_pair_multiple_times() {
+        let rpc = MockRpc::new();
+        let mut config = test_config(
+            &[Keypair::new()],
+            &[Pubkey::new_unique(), Pubkey::new_unique()],
+        );
+        config.repetitions = 3;
+
+        let records = send_transactions(
+            &config,
+            &plan_for(&config),
+            Arc::new(rpc.client()),
+            Arc::new(VecSink::default()),
+        )
+        .await;
+
+        assert_eq!(records.len(), 6);
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 6);
+        for receiver in &config.receivers {
+            let receiver = get_public_key(receiver);
+            assert_eq!(records.iter().filter(|r| r.receiver == receiver).count(), 3);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_repetitions_sign_distinct_transactions() {
+        let rpc = MockRpc::new();
+        let mut config = test_config(&[Keypair::new()], &[Pubkey::new_unique()]);
+        config.repetitions = 3;
+        config.blockhash_refresh_ms = 60_000;
+
+        let records = send_transactions(
+            &config,
+            &plan_for(&config),
+            Arc::new(rpc.client()),
+            Arc::new(VecSink::default()),
+        )
+        .await;
+
+        let signatures: HashSet<Signature> = records
+            .iter()
+            .map(|record| match record.outcome {
+                TransferOutcome::Confirmed(signature) | TransferOutcome::Sent(signature) => {
+                    signature
+                }
+                ref other => panic!("Unexpected outcome {:?}", other),
+            })
+            .collect();
+        assert_eq!(signatures.len(), 3);
+        assert_eq!(rpc.calls(RpcRequest::GetLatestBlockhash), 1);
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 3);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_batch_confirm_polls_statuses_once_for_all_transfers() {
+        let rpc = MockRpc::new();
+        let mut config = test_config(
+            &[Keypair::new(), Keypair::new()],
+            &[Pubkey::new_unique(), Pubkey::new_unique()],
+        );
+        config.confirm_strategy = ConfirmStrategy::BatchConfirm;
+
+        let records = send_transactions(
+            &config,
+            &plan_for(&config),
+            Arc::new(rpc.client()),
+            Arc::new(VecSink::default()),
+        )
+        .await;
+
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 4);
+        assert_eq!(rpc.calls(RpcRequest::GetSignatureStatuses), 1);
+        assert!(records
+            .iter()
+            .all(|record| matches!(record.outcome, TransferOutcome::Confirmed(_))));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_pipelined_confirms_every_transfer() {
+        let rpc = MockRpc::new();
+        let mut config = test_config(
+            &[Keypair::new(), Keypair::new()],
+            &[Pubkey::new_unique(), Pubkey::new_unique()],
+        );
+        config.confirm_strategy = ConfirmStrategy::Pipelined;
+        config.repetitions = 2;
+        config.send_concurrency = Some(2);
+        config.confirm_concurrency = Some(1);
+
+        let records = send_transactions(
+            &config,
+            &plan_for(&config),
+            Arc::new(rpc.client()),
+            Arc::new(VecSink::default()),
+        )
+        .await;
+
+        assert_eq!(records.len(), 8);
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 8);
+        assert!(records
+            .iter()
+            .all(|record| matches!(record.outcome, TransferOutcome::Confirmed(_))));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_unconfirmed_transfer_is_marked_timed_out() {
+        let rpc = MockRpc::new();
+        for _ in 0..100 {
+            rpc.push(
+                RpcRequest::GetSignatureStatuses,
+                Ok(json!({ "context": { "slot": 1 }, "value": [null] })),
+            );
+        }
+        let mut config = test_config(&[Keypair::new()], &[Pubkey::new_unique()]);
+        config.confirm_strategy = ConfirmStrategy::Pipelined;
+        config.per_transfer_timeout_ms = Some(300);
+        let sink = Arc::new(VecSink::default());
+
+        let started = Instant::now();
+        let records = send_transactions(
+            &config,
+            &plan_for(&config),
+            Arc::new(rpc.client()),
+            sink.clone(),
+        )
+        .await;
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].outcome, TransferOutcome::TimedOut);
+        assert_eq!(RunSummary::new(&records, &[]).timed_out, 1);
+    }
+
+    #[test]
+    fn test_transfer_includes_configured_memo() {
+        let sender = Pubkey::new_unique();
+        let receiver = Pubkey::new_unique();
+
+        let instructions = transfer_instructions(&sender, &receiver, LAMPORTS, Some("campaign-42"));
+
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(instructions[0].program_id, spl_memo::id());
+        assert_eq!(instructions[0].data, b"campaign-42");
+        assert!(instructions[0]
+            .accounts
+            .iter()
+            .any(|meta| meta.pubkey == sender && meta.is_signer));
+        assert_eq!(instructions[1].program_id, solana_sdk::system_program::id());
+        assert_eq!(
+            transfer_instructions(&sender, &receiver, LAMPORTS, None).len(),
+            1
+        );
+    }
+
+    async fn send_with_strategy(strategy: ConfirmStrategy) -> MockRpc {
+        let rpc = MockRpc::new();
+        let client = rpc.client();
+
+        send_sol(
+            &client,
+            &Keypair::new(),
+            &Pubkey::new_unique(),
+            LAMPORTS,
+            &SendOptions {
+                strategy,
+                ..SendOptions::default()
+            },
+        )
+        .await
+        .expect("Transfer should succeed");
+
+        rpc
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_skip_preflight_is_passed_to_send_transaction() {
+        let rpc = MockRpc::new();
+        let client = rpc.client();
+
+        send_sol(
+            &client,
+            &Keypair::new(),
+            &Pubkey::new_unique(),
+            LAMPORTS,
+            &SendOptions {
+                skip_preflight: true,
+                preflight_commitment: Some(CommitmentLevel::Processed),
+                ..SendOptions::default()
+            },
+        )
+        .await
+        .expect("Transfer should succeed");
+
+        let params = rpc.params(RpcRequest::SendTransaction);
+        assert_eq!(params.len(), 1);
+        assert_eq!(params[0][1]["skipPreflight"], json!(true));
+        assert_eq!(params[0][1]["preflightCommitment"], json!("processed"));
+        assert!(rpc.calls(RpcRequest::GetSignatureStatuses) >= 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_compute_unit_limit_is_simulated_usage_plus_margin() {
+        let rpc = MockRpc::new();
+        rpc.push(
+            RpcRequest::SimulateTransaction,
+            Ok(json!({
+                "context": { "slot": 1 },
+                "value": { "err": null, "logs": [], "unitsConsumed": 450 }
+            })),
+        );
+        let client = rpc.client();
+
+        send_sol(
+            &client,
+            &Keypair::new(),
+            &Pubkey::new_unique(),
+            LAMPORTS,
+            &SendOptions {
+                compute_unit_margin: Some(1_000),
+                ..SendOptions::default()
+            },
+        )
+        .await
+        .expect("Transfer should succeed");
+
+        let params = rpc.params(RpcRequest::SendTransaction);
+        assert_eq!(params.len(), 1);
+        let data = STANDARD.decode(params[0][0].as_str().unwrap()).unwrap();
+        let transaction: Transaction = bincode::deserialize(&data).unwrap();
+        let limit = &transaction.message.instructions[0];
+        let expected = ComputeBudgetInstruction::set_compute_unit_limit(450 + 1_000);
+        assert_eq!(
+            transaction.message.account_keys[limit.program_id_index as usize],
+            expected.program_id
+        );
+        assert_eq!(limit.data, expected.data);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_await_confirmation_sends_and_polls_status() {
+        let rpc = send_with_strategy(ConfirmStrategy::AwaitConfirmation).await;
+
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 1);
+        assert!(rpc.calls(RpcRequest::GetSignatureStatuses) >= 1);
+        assert_eq!(rpc.calls(RpcRequest::SimulateTransaction), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fire_and_forget_only_sends() {
+        let rpc = send_with_strategy(ConfirmStrategy::FireAndForget).await;
+
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 1);
+        assert_eq!(rpc.calls(RpcRequest::GetSignatureStatuses), 0);
+        assert_eq!(rpc.calls(RpcRequest::SimulateTransaction), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_simulate_only_never_sends() {
+        let rpc = send_with_strategy(ConfirmStrategy::SimulateOnly).await;
+
+        assert_eq!(rpc.calls(RpcRequest::SimulateTransaction), 1);
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 0);
+        assert_eq!(rpc.calls(RpcRequest::GetSignatureStatuses), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_status_falls_back_to_transaction_history() {
+        let rpc = MockRpc::new();
+        rpc.push(
+            RpcRequest::GetSignatureStatuses,
+            Ok(json!({ "context": { "slot": 1 }, "value": [null] })),
+        );
+        let client = rpc.client();
+
+        let state = check_transaction_status(&client, &Signature::new_unique(), true)
+            .await
+            .unwrap();
+
+        assert_eq!(state, SignatureState::Confirmed);
+        assert_eq!(rpc.calls(RpcRequest::GetTransaction), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_status_timeout_is_transient() {
+        let rpc = MockRpc::new();
+        rpc.push(RpcRequest::GetSignatureStatuses, Err(timeout_error()));
+        let client = rpc.client();
+
+        let err = check_transaction_status(&client, &Signature::new_unique(), true)
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.class, ErrorClass::Transient);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_status_transaction_error_is_permanent() {
+        let rpc = MockRpc::new();
+        rpc.push(
+            RpcRequest::GetSignatureStatuses,
+            Ok(json!({
+                "context": { "slot": 1 },
+                "value": [{
+                    "slot": 1,
+                    "confirmations": null,
+                    "status": { "Err": "AccountInUse" },
+                    "err": "AccountInUse",
+                    "confirmationStatus": "finalized"
+                }]
+            })),
+        );
+        let client = rpc.client();
+
+        let err = check_transaction_status(&client, &Signature::new_unique(), true)
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.class, ErrorClass::Permanent);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_status_not_found_in_history() {
+        let rpc = MockRpc::new();
+        rpc.push(
+            RpcRequest::GetSignatureStatuses,
+            Ok(json!({ "context": { "slot": 1 }, "value": [null] })),
+        );
+        rpc.push(RpcRequest::GetTransaction, Ok(serde_json::Value::Null));
+        let client = rpc.client();
+
+        let state = check_transaction_status(&client, &Signature::new_unique(), true)
+            .await
+            .unwrap();
+
+        assert_eq!(state, SignatureState::NotFound);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_old_signature_resolves_only_with_history() {
+        // Статус подписи остался только в истории ноды
+        let rpc = MockRpc::new();
+        rpc.push(
+            RpcRequest::GetSignatureStatuses,
+            Ok(json!({
+                "context": { "slot": 1 },
+                "value": [{
+                    "slot": 1,
+                    "confirmations": null,
+                    "status": { "Ok": null },
+                    "err": null,
+                    "confirmationStatus": "finalized"
+                }]
+            })),
+        );
+        let client = rpc.client();
+
+        let state = check_transaction_status(&client, &Signature::new_unique(), true)
+            .await
+            .unwrap();
+
+        assert_eq!(state, SignatureState::Confirmed);
+        let params = rpc.params(RpcRequest::GetSignatureStatuses);
+        assert_eq!(params[0][1]["searchTransactionHistory"], json!(true));
+        assert_eq!(rpc.calls(RpcRequest::GetTransaction), 0);
+
+        // Без истории кэш недавних статусов подпись уже не знает
+        let rpc = MockRpc::new();
+        rpc.push(
+            RpcRequest::GetSignatureStatuses,
+            Ok(json!({ "context": { "slot": 1 }, "value": [null] })),
+        )
+        .push(RpcRequest::GetTransaction, Ok(serde_json::Value::Null));
+        let client = rpc.client();
+
+        let state = check_transaction_status(&client, &Signature::new_unique(), false)
+            .await
+            .unwrap();
+
+        assert_eq!(state, SignatureState::NotFound);
+        let params = rpc.params(RpcRequest::GetSignatureStatuses);
+        assert!(params[0].get(1).is_none());
+    }
+}
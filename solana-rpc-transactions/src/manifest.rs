@@ -0,0 +1,103 @@
+use crate::output::{sender_rollups, RunSummary};
+use crate::report::TransferRecord;
+use serde::Serialize;
+use solana_sdk::hash::hash;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Итоги кампании для аудита: по config_digest видно, какой конфиг ее запустил
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunManifest {
+    pub config_digest: String,
+    pub started_at_unix_ms: u64,
+    pub finished_at_unix_ms: u64,
+    pub totals: ManifestTotals,
+    pub senders: BTreeMap<String, ManifestSender>,
+    // Куда пишется отчет о задержках, если он включен
+    pub report_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ManifestTotals {
+    pub attempted: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub dropped: usize,
+    pub timed_out: usize,
+    pub skipped: usize,
+    // Сумма успешных переводов
+    pub lamports: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ManifestSender {
+    pub transfers: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub lamports: u64,
+    pub average_latency_ms: f64,
+}
+
+// Хэш содержимого конфига, не зависит от того, лежит ли он в git
+pub fn config_digest(content: &str) -> String {
+    hash(content.as_bytes()).to_string()
+}
+
+fn unix_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+impl RunManifest {
+    // Итоги по отправителям считаются всегда, независимо от per_sender_summary
+    pub fn new(
+        config_digest: &str,
+        started_at: SystemTime,
+        finished_at: SystemTime,
+        summary: &RunSummary,
+        records: &[TransferRecord],
+        report_path: Option<&str>,
+    ) -> Self {
+        let rollups = sender_rollups(records);
+        let totals = ManifestTotals {
+            attempted: summary.attempted,
+            succeeded: rollups.values().map(|rollup| rollup.succeeded).sum(),
+            failed: summary.failed,
+            dropped: summary.dropped,
+            timed_out: summary.timed_out,
+            skipped: summary.skipped.values().sum(),
+            lamports: rollups.values().map(|rollup| rollup.lamports).sum(),
+        };
+        let senders = rollups
+            .into_iter()
+            .map(|(sender, rollup)| {
+                let sender_manifest = ManifestSender {
+                    transfers: rollup.transfers,
+                    succeeded: rollup.succeeded,
+                    failed: rollup.failed,
+                    lamports: rollup.lamports,
+                    average_latency_ms: rollup.average_latency.as_secs_f64() * 1000.0,
+                };
+                (sender.to_string(), sender_manifest)
+            })
+            .collect();
+
+        RunManifest {
+            config_digest: config_digest.to_string(),
+            started_at_unix_ms: unix_ms(started_at),
+            finished_at_unix_ms: unix_ms(finished_at),
+            totals,
+            senders,
+            report_path: report_path.map(str::to_string),
+        }
+    }
+}
+
+pub fn write_manifest(path: &str, manifest: &RunManifest) -> io::Result<()> {
+    let file = File::create(path)?;
+    serde_json::to_writer_pretty(file, manifest).map_err(io::Error::from)
+}
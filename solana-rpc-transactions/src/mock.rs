@@ -0,0 +1,116 @@
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use solana_client::{
+    client_error::{ClientError, Result as ClientResult},
+    rpc_client::{RpcClient, RpcClientConfig},
+    rpc_request::RpcRequest,
+    rpc_sender::{RpcSender, RpcTransportStats},
+};
+use solana_rpc_client::mock_sender::MockSender;
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    sync::{Arc, Mutex},
+};
+
+// Ответы, заданные тестом, отдаются по очереди; остальные запросы обслуживает MockSender
+#[derive(Default)]
+struct MockState {
+    responses: Mutex<HashMap<RpcRequest, VecDeque<ClientResult<Value>>>>,
+    calls: Mutex<Vec<(RpcRequest, Value)>>,
+}
+
+#[derive(Clone, Default)]
+pub struct MockRpc {
+    state: Arc<MockState>,
+}
+
+impl MockRpc {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, request: RpcRequest, response: ClientResult<Value>) -> &Self {
+        self.state
+            .responses
+            .lock()
+            .unwrap()
+            .entry(request)
+            .or_default()
+            .push_back(response);
+        self
+    }
+
+    pub fn client(&self) -> RpcClient {
+        RpcClient::new_sender(
+            MockRpcSender {
+                state: Arc::clone(&self.state),
+                fallback: MockSender::new("succeeds"),
+            },
+            RpcClientConfig::with_commitment(CommitmentConfig::confirmed()),
+        )
+    }
+
+    pub fn calls(&self, request: RpcRequest) -> usize {
+        self.params(request).len()
+    }
+
+    pub fn params(&self, request: RpcRequest) -> Vec<Value> {
+        self.state
+            .calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(r, _)| *r == request)
+            .map(|(_, params)| params.clone())
+            .collect()
+    }
+}
+
+// Модуль подключают и крейты, которые баланс не запрашивают
+#[allow(dead_code)]
+pub fn balance_response(lamports: u64) -> Value {
+    json!({ "context": { "slot": 1 }, "value": lamports })
+}
+
+pub fn timeout_error() -> ClientError {
+    io::Error::new(io::ErrorKind::TimedOut, "operation timed out").into()
+}
+
+struct MockRpcSender {
+    state: Arc<MockState>,
+    fallback: MockSender,
+}
+
+#[async_trait]
+impl RpcSender for MockRpcSender {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        self.state
+            .calls
+            .lock()
+            .unwrap()
+            .push((request, params.clone()));
+
+        let scripted = self
+            .state
+            .responses
+            .lock()
+            .unwrap()
+            .get_mut(&request)
+            .and_then(|queue| queue.pop_front());
+
+        match scripted {
+            Some(response) => response,
+            None => self.fallback.send(request, params).await,
+        }
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        RpcTransportStats::default()
+    }
+
+    fn url(&self) -> String {
+        "mock".to_string()
+    }
+}
@@ -0,0 +1,313 @@
+use crate::explorer::{explorer_url, Cluster};
+use crate::failure::{failure_histogram, FailureCategory};
+use crate::report::{
+    dead_letters, DeadLetter, SkipReason, SkippedTransfer, TransferOutcome, TransferRecord,
+};
+use solana_sdk::pubkey::Pubkey;
+#[cfg(test)]
+use std::sync::Mutex;
+use std::{collections::BTreeMap, time::Duration};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunSummary {
+    pub attempted: usize,
+    pub failed: usize,
+    pub dropped: usize,
+    pub timed_out: usize,
+    // Количество пропущенных переводов по каждой причине
+    pub skipped: BTreeMap<SkipReason, usize>,
+    // Итоги по отправителям, заполняются только при per_sender_summary
+    pub senders: BTreeMap<Pubkey, SenderRollup>,
+    // Переводы, исчерпавшие все попытки
+    pub dead_letters: Vec<DeadLetter>,
+    // Неудачные переводы по первопричине
+    pub failures: BTreeMap<FailureCategory, usize>,
+    // Заполняется, если кампанию остановил max_failure_rate
+    pub aborted: Option<AbortedDueToFailures>,
+}
+
+// Кампания остановлена: из sampled завершенных переводов failed неудачны,
+// оставшиеся переводы не отправлялись
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbortedDueToFailures {
+    pub sampled: usize,
+    pub failed: usize,
+}
+
+// Итоги одного отправителя; lamports - сумма успешных переводов
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SenderRollup {
+    pub transfers: usize,
+    pub lamports: u64,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub average_latency: Duration,
+}
+
+// Успешными считаются подтвержденные, отправленные и просимулированные переводы
+pub fn sender_rollups(records: &[TransferRecord]) -> BTreeMap<Pubkey, SenderRollup> {
+    let mut rollups: BTreeMap<Pubkey, (SenderRollup, Duration)> = BTreeMap::new();
+    for record in records {
+        let (rollup, total_latency) = rollups.entry(record.sender).or_default();
+        rollup.transfers += 1;
+        *total_latency += record.duration;
+        match record.outcome {
+            TransferOutcome::Confirmed(_) | TransferOutcome::Sent(_) => {
+                rollup.succeeded += 1;
+                rollup.lamports += record.amount;
+            }
+            TransferOutcome::Simulated => rollup.succeeded += 1,
+            TransferOutcome::Failed(..)
+            | TransferOutcome::Dropped(_)
+            | TransferOutcome::TimedOut => rollup.failed += 1,
+        }
+    }
+
+    rollups
+        .into_iter()
+        .map(|(sender, (mut rollup, total_latency))| {
+            rollup.average_latency = total_latency / rollup.transfers as u32;
+            (sender, rollup)
+        })
+        .collect()
+}
+
+impl RunSummary {
+    pub fn new(records: &[TransferRecord], skipped: &[SkippedTransfer]) -> Self {
+        let mut counts = BTreeMap::new();
+        for skip in skipped {
+            *counts.entry(skip.reason).or_insert(0) += 1;
+        }
+
+        RunSummary {
+            attempted: records.len(),
+            failed: records
+                .iter()
+                .filter(|record| matches!(record.outcome, TransferOutcome::Failed(..)))
+                .count(),
+            dropped: records
+                .iter()
+                .filter(|record| matches!(record.outcome, TransferOutcome::Dropped(_)))
+                .count(),
+            timed_out: records
+                .iter()
+                .filter(|record| record.outcome == TransferOutcome::TimedOut)
+                .count(),
+            skipped: counts,
+            senders: BTreeMap::new(),
+            dead_letters: dead_letters(records),
+            failures: failure_histogram(records),
+            aborted: None,
+        }
+    }
+}
+
+// Куда отправлять результаты работы: по умолчанию stdout,
+// при встраивании в другое приложение - любая своя реализация
+pub trait OutputSink: Send + Sync {
+    fn progress(&self, message: &str);
+    fn transfer(&self, record: &TransferRecord);
+    fn summary(&self, summary: &RunSummary);
+}
+
+// Подписи печатаются вместе со ссылкой на explorer этого кластера
+pub struct StdoutSink {
+    pub cluster: Cluster,
+}
+
+impl OutputSink for StdoutSink {
+    fn progress(&self, message: &str) {
+        println!("{}", message);
+    }
+
+    fn transfer(&self, record: &TransferRecord) {
+        match &record.outcome {
+            TransferOutcome::Confirmed(signature) | TransferOutcome::Sent(signature) => {
+                println!(
+                    "Transaction Hash: {:?}, Time: {:?}, Explorer: {}",
+                    signature,
+                    record.duration,
+                    explorer_url(signature, self.cluster)
+                )
+            }
+            TransferOutcome::Simulated => println!(
+                "Simulated transfer from {} to {}, Time: {:?}",
+                record.sender, record.receiver, record.duration
+            ),
+            TransferOutcome::Failed(err, _) => println!(
+                "Transfer from {} to {} failed: {}, Time: {:?}",
+                record.sender, record.receiver, err, record.duration
+            ),
+            TransferOutcome::Dropped(signature) => println!(
+                "Transfer from {} to {} dropped, blockhash expired: {:?}, Time: {:?}",
+                record.sender, record.receiver, signature, record.duration
+            ),
+            TransferOutcome::TimedOut => println!(
+                "Transfer from {} to {} timed out, Time: {:?}",
+                record.sender, record.receiver, record.duration
+            ),
+        }
+    }
+
+    fn summary(&self, summary: &RunSummary) {
+        println!(
+            "Attempted {} transfers, {} failed",
+            summary.attempted, summary.failed
+        );
+        if let Some(aborted) = summary.aborted {
+            println!(
+                "Aborted due to failures: {} of {} transfers failed, remaining transfers were not sent",
+                aborted.failed, aborted.sampled
+            );
+        }
+        if summary.dropped > 0 {
+            println!("{} transfers dropped and can be retried", summary.dropped);
+        }
+        if summary.timed_out > 0 {
+            println!("{} transfers timed out", summary.timed_out);
+        }
+        if !summary.failures.is_empty() {
+            println!("Failures by cause:");
+            for (category, count) in &summary.failures {
+                println!("  {}: {}", category, count);
+            }
+        }
+        for dead_letter in &summary.dead_letters {
+            println!(
+                "Dead letter: {} lamports from {} to {}: {}",
+                dead_letter.amount, dead_letter.sender, dead_letter.receiver, dead_letter.error
+            );
+        }
+
+        if !summary.skipped.is_empty() {
+            println!(
+                "Skipped {} transfers:",
+                summary.skipped.values().sum::<usize>()
+            );
+            for (reason, count) in &summary.skipped {
+                println!("  {}: {}", reason, count);
+            }
+        }
+
+        for (sender, rollup) in &summary.senders {
+            println!(
+                "Sender {}: {} transfers, {} lamports, {} succeeded, {} failed, average latency {:?}",
+                sender,
+                rollup.transfers,
+                rollup.lamports,
+                rollup.succeeded,
+                rollup.failed,
+                rollup.average_latency
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputEvent {
+    Progress(String),
+    Transfer(TransferOutcome),
+    Summary(RunSummary),
+}
+
+// Запоминает все события, чтобы тесты могли их проверить
+#[cfg(test)]
+#[derive(Default)]
+pub struct VecSink {
+    events: Mutex<Vec<OutputEvent>>,
+}
+
+#[cfg(test)]
+impl VecSink {
+    pub fn events(&self) -> Vec<OutputEvent> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl OutputSink for VecSink {
+    fn progress(&self, message: &str) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(OutputEvent::Progress(message.to_string()));
+    }
+
+    fn transfer(&self, record: &TransferRecord) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(OutputEvent::Transfer(record.outcome.clone()));
+    }
+
+    fn summary(&self, summary: &RunSummary) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(OutputEvent::Summary(summary.clone()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_sdk::signature::Signature;
+
+    fn record(
+        sender: Pubkey,
+        amount: u64,
+        millis: u64,
+        outcome: TransferOutcome,
+    ) -> TransferRecord {
+        TransferRecord {
+            sender,
+            receiver: Pubkey::new_unique(),
+            amount,
+            duration: Duration::from_millis(millis),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn test_rollup_is_computed_per_sender() {
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let confirmed = || TransferOutcome::Confirmed(Signature::new_unique());
+        let records = vec![
+            record(first, 1_000, 100, confirmed()),
+            record(
+                second,
+                500,
+                40,
+                TransferOutcome::Failed("boom".to_string(), FailureCategory::Other),
+            ),
+            record(first, 2_000, 300, confirmed()),
+            record(second, 500, 20, confirmed()),
+            record(first, 4_000, 200, TransferOutcome::TimedOut),
+        ];
+
+        let rollups = sender_rollups(&records);
+
+        assert_eq!(rollups.len(), 2);
+        assert_eq!(
+            rollups[&first],
+            SenderRollup {
+                transfers: 3,
+                lamports: 3_000,
+                succeeded: 2,
+                failed: 1,
+                average_latency: Duration::from_millis(200),
+            }
+        );
+        assert_eq!(
+            rollups[&second],
+            SenderRollup {
+                transfers: 2,
+                lamports: 500,
+                succeeded: 1,
+                failed: 1,
+                average_latency: Duration::from_millis(30),
+            }
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::{future::Future, sync::Arc};
+use tokio::sync::{mpsc, Semaphore};
+use tracing::error;
+
+// Лимиты одновременных задач для каждой стадии, None - без ограничения
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StageLimits {
+    pub send: Option<usize>,
+    pub confirm: Option<usize>,
+}
+
+fn stage_semaphore(limit: Option<usize>) -> Arc<Semaphore> {
+    Arc::new(Semaphore::new(
+        limit.map_or(Semaphore::MAX_PERMITS, |limit| limit.max(1)),
+    ))
+}
+
+// Send-стадия обрабатывает элементы и передает результаты через канал confirm-стадии,
+// которая начинает работу, не дожидаясь конца отправки. Канал не ограничен,
+// поэтому медленное подтверждение не тормозит отправку, и наоборот
+pub async fn run_stages<T, U, R, S, SF, C, CF>(
+    items: Vec<T>,
+    limits: StageLimits,
+    send: S,
+    confirm: C,
+) -> Vec<R>
+where
+    T: Send + 'static,
+    U: Send + 'static,
+    R: Send + 'static,
+    S: Fn(T) -> SF + Send + Sync + 'static,
+    SF: Future<Output = U> + Send,
+    C: Fn(U) -> CF + Send + Sync + 'static,
+    CF: Future<Output = R> + Send,
+{
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let send = Arc::new(send);
+    let send_permits = stage_semaphore(limits.send);
+
+    for item in items {
+        let (tx, send, send_permits) = (tx.clone(), Arc::clone(&send), Arc::clone(&send_permits));
+
+        tokio::spawn(async move {
+            let _permit = send_permits
+                .acquire_owned()
+                .await
+                .expect("Send semaphore is never closed");
+            let sent = send(item).await;
+            // Получатель живет, пока не закрыты все отправители
+            let _ = tx.send(sent);
+        });
+    }
+    drop(tx);
+
+    let confirm = Arc::new(confirm);
+    let confirm_permits = stage_semaphore(limits.confirm);
+    let mut confirmations = Vec::new();
+
+    while let Some(sent) = rx.recv().await {
+        let (confirm, confirm_permits) = (Arc::clone(&confirm), Arc::clone(&confirm_permits));
+
+        confirmations.push(tokio::spawn(async move {
+            let _permit = confirm_permits
+                .acquire_owned()
+                .await
+                .expect("Confirm semaphore is never closed");
+            confirm(sent).await
+        }));
+    }
+
+    let mut results = Vec::with_capacity(confirmations.len());
+    for confirmation in confirmations {
+        match confirmation.await {
+            Ok(result) => results.push(result),
+            Err(err) => error!("Confirm task failed: {}", err),
+        }
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::time::{sleep, Duration};
+
+    #[derive(Default)]
+    struct InFlight {
+        current: AtomicUsize,
+        max: AtomicUsize,
+    }
+
+    impl InFlight {
+        async fn run(&self, duration: Duration) {
+            let current = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max.fetch_max(current, Ordering::SeqCst);
+            sleep(duration).await;
+            self.current.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stages_respect_their_own_limits() {
+        let (sending, confirming) = (Arc::new(InFlight::default()), Arc::new(InFlight::default()));
+        let limits = StageLimits {
+            send: Some(3),
+            confirm: Some(1),
+        };
+
+        let send_stage = Arc::clone(&sending);
+        let confirm_stage = Arc::clone(&confirming);
+        let mut results = run_stages(
+            (0..6).collect(),
+            limits,
+            move |item: usize| {
+                let sending = Arc::clone(&send_stage);
+                async move {
+                    sending.run(Duration::from_millis(100)).await;
+                    item
+                }
+            },
+            move |item: usize| {
+                let confirming = Arc::clone(&confirm_stage);
+                async move {
+                    confirming.run(Duration::from_millis(50)).await;
+                    item * 10
+                }
+            },
+        )
+        .await;
+
+        results.sort();
+        assert_eq!(results, vec![0, 10, 20, 30, 40, 50]);
+        assert_eq!(sending.max.load(Ordering::SeqCst), 3);
+        assert_eq!(confirming.max.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,481 @@
+use crate::report::{SkipReason, SkippedTransfer};
+use rand::Rng;
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+
+// Как отправители распределяются по получателям
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PairingStrategy {
+    // Каждый отправитель переводит каждому получателю
+    #[default]
+    CrossProduct,
+    // Каждый получатель получает один перевод, отправители идут по кругу
+    RoundRobin,
+    // Каждый получатель получает один перевод от случайного отправителя
+    Random,
+    // i-й отправитель переводит i-му получателю, лишние с любой стороны не участвуют
+    Zipped,
+}
+
+// Номера получателей для каждого отправителя: assignments[s] - получатели отправителя s
+pub fn assign_receivers<R: Rng + ?Sized>(
+    senders: usize,
+    receivers: usize,
+    strategy: PairingStrategy,
+    rng: &mut R,
+) -> Vec<Vec<usize>> {
+    let mut assignments = vec![Vec::new(); senders];
+    if senders == 0 {
+        return assignments;
+    }
+
+    match strategy {
+        PairingStrategy::CrossProduct => {
+            for assigned in assignments.iter_mut() {
+                assigned.extend(0..receivers);
+            }
+        }
+        PairingStrategy::RoundRobin => {
+            for receiver in 0..receivers {
+                assignments[receiver % senders].push(receiver);
+            }
+        }
+        PairingStrategy::Random => {
+            for receiver in 0..receivers {
+                assignments[rng.gen_range(0..senders)].push(receiver);
+            }
+        }
+        PairingStrategy::Zipped => {
+            for (receiver, assigned) in assignments.iter_mut().take(receivers).enumerate() {
+                assigned.push(receiver);
+            }
+        }
+    }
+
+    assignments
+}
+
+// Сколько получает каждый получатель за один повтор
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferAmount {
+    // Одна и та же сумма каждому
+    Fixed(u64),
+    // Сумма отправителя делится поровну между его получателями
+    Pot(u64),
+}
+
+impl TransferAmount {
+    pub fn split(&self, receivers: usize) -> Vec<u64> {
+        match *self {
+            TransferAmount::Fixed(amount) => vec![amount; receivers],
+            TransferAmount::Pot(total) => distribute_evenly(total, receivers),
+        }
+    }
+}
+
+// Делит total на n частей: остаток от деления достается первым получателям
+// по одному лампорту, поэтому части отличаются не больше чем на 1, а сумма равна total
+pub fn distribute_evenly(total: u64, n: usize) -> Vec<u64> {
+    if n == 0 {
+        return Vec::new();
+    }
+
+    let (share, remainder) = (total / n as u64, total % n as u64);
+    (0..n as u64)
+        .map(|index| share + u64::from(index < remainder))
+        .collect()
+}
+
+// Переводы одного отправителя в рамках кампании
+#[derive(Debug, Clone, PartialEq)]
+pub struct SenderPlan {
+    pub sender: Pubkey,
+    // Получатели без самого отправителя, amounts[i] - сумма для receivers[i]
+    pub receivers: Vec<Pubkey>,
+    pub amounts: Vec<u64>,
+    pub transfers: usize,
+    pub total: u64,
+}
+
+// Один перевод плана, при repetitions выполняется столько раз подряд
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlannedTransfer {
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+}
+
+// План всех переводов, составленный без обращения к сети
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferPlan {
+    pub repetitions: usize,
+    pub senders: Vec<SenderPlan>,
+    // Все пары в порядке отправителей, их и выполняет send_transactions
+    pub items: Vec<PlannedTransfer>,
+    // Пары, не вошедшие в план, и почему
+    pub skipped: Vec<SkippedTransfer>,
+    pub transfers: usize,
+    pub total: u64,
+}
+
+impl TransferPlan {
+    // assignments[s] - получатели отправителя senders[s]
+    pub fn new(
+        senders: &[Pubkey],
+        assignments: &[Vec<Pubkey>],
+        amount: TransferAmount,
+        repetitions: usize,
+    ) -> Self {
+        let mut skipped = Vec::new();
+        let senders: Vec<SenderPlan> = senders
+            .iter()
+            .zip(assignments)
+            .map(|(sender, receivers)| {
+                // Перевод самому себе пропускается, в план и в делёж он не входит
+                let (receivers, own): (Vec<Pubkey>, Vec<Pubkey>) =
+                    receivers.iter().partition(|receiver| *receiver != sender);
+                skipped.extend(own.into_iter().map(|receiver| SkippedTransfer {
+                    sender: *sender,
+                    receiver,
+                    reason: SkipReason::SelfSend,
+                }));
+                let amounts = amount.split(receivers.len());
+
+                SenderPlan {
+                    sender: *sender,
+                    transfers: receivers.len() * repetitions,
+                    total: amounts.iter().sum::<u64>() * repetitions as u64,
+                    receivers,
+                    amounts,
+                }
+            })
+            .collect();
+
+        Self::from_senders(repetitions, senders, skipped)
+    }
+
+    // Все суммы плана умножаются на multiplier и округляются до целых лампортов.
+    // Сумма, округлившаяся до нуля, - ошибка: такой перевод ничего не проверяет
+    pub fn scaled(self, multiplier: f64) -> Result<Self, String> {
+        if !multiplier.is_finite() || multiplier <= 0.0 {
+            return Err(format!(
+                "amount_multiplier must be a positive number, got {}",
+                multiplier
+            ));
+        }
+
+        let senders = self
+            .senders
+            .into_iter()
+            .map(|plan| {
+                let amounts = plan
+                    .amounts
+                    .iter()
+                    .map(|&amount| scale_amount(amount, multiplier))
+                    .collect::<Result<Vec<u64>, String>>()?;
+                Ok(SenderPlan {
+                    total: amounts.iter().sum::<u64>() * self.repetitions as u64,
+                    amounts,
+                    ..plan
+                })
+            })
+            .collect::<Result<Vec<SenderPlan>, String>>()?;
+
+        Ok(Self::from_senders(self.repetitions, senders, self.skipped))
+    }
+
+    fn from_senders(
+        repetitions: usize,
+        senders: Vec<SenderPlan>,
+        skipped: Vec<SkippedTransfer>,
+    ) -> Self {
+        let items = senders
+            .iter()
+            .flat_map(|plan| {
+                plan.receivers
+                    .iter()
+                    .zip(&plan.amounts)
+                    .map(|(receiver, amount)| PlannedTransfer {
+                        sender: plan.sender,
+                        receiver: *receiver,
+                        amount: *amount,
+                    })
+            })
+            .collect();
+
+        TransferPlan {
+            repetitions,
+            transfers: senders.iter().map(|plan| plan.transfers).sum(),
+            total: senders.iter().map(|plan| plan.total).sum(),
+            senders,
+            items,
+            skipped,
+        }
+    }
+}
+
+fn scale_amount(amount: u64, multiplier: f64) -> Result<u64, String> {
+    let scaled = (amount as f64 * multiplier).round();
+    if scaled < 1.0 {
+        return Err(format!(
+            "Amount of {} lamports scaled by {} rounds to zero",
+            amount, multiplier
+        ));
+    }
+    if scaled >= u64::MAX as f64 {
+        return Err(format!(
+            "Amount of {} lamports scaled by {} does not fit in u64",
+            amount, multiplier
+        ));
+    }
+
+    Ok(scaled as u64)
+}
+
+impl fmt::Display for TransferPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Transfer plan:")?;
+
+        for plan in &self.senders {
+            writeln!(f, "Sender {}", plan.sender)?;
+            for (receiver, amount) in plan.receivers.iter().zip(&plan.amounts) {
+                writeln!(
+                    f,
+                    "  -> {} x{}: {} lamports each",
+                    receiver, self.repetitions, amount
+                )?;
+            }
+            writeln!(
+                f,
+                "  Total: {} lamports in {} transfers",
+                plan.total, plan.transfers
+            )?;
+        }
+
+        write!(
+            f,
+            "Grand total: {} lamports in {} transfers",
+            self.total, self.transfers
+        )
+    }
+}
+
+// Доля получателей для одного из shard_count воркеров: получатель с номером i
+// достается воркеру i % shard_count, поэтому при одном и том же списке шарды
+// не пересекаются и вместе покрывают его целиком
+pub fn shard_receivers<T: Clone>(
+    receivers: &[T],
+    shard_index: usize,
+    shard_count: usize,
+) -> Result<Vec<T>, String> {
+    if shard_count == 0 {
+        return Err("shard_count must be at least 1".to_string());
+    }
+    if shard_index >= shard_count {
+        return Err(format!(
+            "shard_index {} is out of range for shard_count {}",
+            shard_index, shard_count
+        ));
+    }
+
+    Ok(receivers
+        .iter()
+        .enumerate()
+        .filter(|(position, _)| position % shard_count == shard_index)
+        .map(|(_, receiver)| receiver.clone())
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_plan_totals_match_pairs_and_amounts() {
+        let senders = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let receivers = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+
+        let plan = TransferPlan::new(
+            &senders,
+            &[receivers.to_vec(), receivers.to_vec()],
+            TransferAmount::Fixed(2_000_000),
+            2,
+        );
+
+        assert_eq!(plan.senders.len(), 2);
+        for sender_plan in &plan.senders {
+            assert_eq!(sender_plan.transfers, 6);
+            assert_eq!(sender_plan.total, 12_000_000);
+        }
+        assert_eq!(plan.transfers, 12);
+        assert_eq!(plan.total, 24_000_000);
+
+        let table = plan.to_string();
+        assert_eq!(table.matches("  -> ").count(), 6);
+        assert!(table.ends_with("Grand total: 24000000 lamports in 12 transfers"));
+    }
+
+    #[test]
+    fn test_distribute_evenly_spreads_remainder() {
+        for (total, n) in [(10, 3), (2_000_000, 7), (5, 8), (0, 4), (u64::MAX, 10)] {
+            let amounts = distribute_evenly(total, n);
+
+            assert_eq!(amounts.len(), n);
+            assert_eq!(
+                amounts.iter().map(|&a| a as u128).sum::<u128>(),
+                total as u128
+            );
+            let (min, max) = (amounts.iter().min(), amounts.iter().max());
+            assert!(max.unwrap() - min.unwrap() <= 1, "{:?}", amounts);
+        }
+        assert_eq!(distribute_evenly(10, 3), vec![4, 3, 3]);
+        assert!(distribute_evenly(10, 0).is_empty());
+    }
+
+    #[test]
+    fn test_pot_plan_skips_sender_and_keeps_total() {
+        let sender = Pubkey::new_unique();
+        let receivers = [Pubkey::new_unique(), sender, Pubkey::new_unique()];
+
+        let plan = TransferPlan::new(
+            &[sender],
+            &[receivers.to_vec()],
+            TransferAmount::Pot(1_001),
+            1,
+        );
+
+        assert_eq!(plan.senders[0].receivers, vec![receivers[0], receivers[2]]);
+        assert_eq!(plan.senders[0].amounts, vec![501, 500]);
+        assert_eq!(plan.total, 1_001);
+        assert_eq!(
+            plan.items
+                .iter()
+                .map(|item| item.amount)
+                .collect::<Vec<_>>(),
+            vec![501, 500]
+        );
+        assert_eq!(
+            plan.skipped,
+            vec![SkippedTransfer {
+                sender,
+                receiver: sender,
+                reason: SkipReason::SelfSend,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_half_multiplier_halves_and_rounds_amounts() {
+        let sender = Pubkey::new_unique();
+        let receivers = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+
+        let plan = TransferPlan::new(
+            &[sender],
+            &[receivers.to_vec()],
+            TransferAmount::Pot(1_003),
+            2,
+        )
+        .scaled(0.5)
+        .unwrap();
+
+        // 335 / 2 = 167.5 округляется вверх, 334 / 2 = 167 без остатка
+        assert_eq!(plan.senders[0].amounts, vec![168, 167, 167]);
+        assert_eq!(
+            plan.items
+                .iter()
+                .map(|item| item.amount)
+                .collect::<Vec<_>>(),
+            vec![168, 167, 167]
+        );
+        assert_eq!(plan.senders[0].total, 1_004);
+        assert_eq!(plan.total, 1_004);
+        assert_eq!(plan.transfers, 6);
+
+        let fixed = TransferPlan::new(
+            &[sender],
+            &[receivers.to_vec()],
+            TransferAmount::Fixed(2_000_000),
+            1,
+        )
+        .scaled(0.5)
+        .unwrap();
+        assert!(fixed.items.iter().all(|item| item.amount == 1_000_000));
+
+        let tiny = TransferPlan::new(
+            &[sender],
+            &[receivers.to_vec()],
+            TransferAmount::Fixed(1),
+            1,
+        );
+        assert!(tiny.clone().scaled(0.4).is_err());
+        assert!(tiny.clone().scaled(0.0).is_err());
+        assert!(tiny.scaled(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_pairing_strategies_produce_expected_pairs() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let assign = |strategy, rng: &mut StdRng| assign_receivers(3, 4, strategy, rng);
+
+        assert_eq!(
+            assign(PairingStrategy::CrossProduct, &mut rng),
+            vec![vec![0, 1, 2, 3], vec![0, 1, 2, 3], vec![0, 1, 2, 3]]
+        );
+        assert_eq!(
+            assign(PairingStrategy::RoundRobin, &mut rng),
+            vec![vec![0, 3], vec![1], vec![2]]
+        );
+        assert_eq!(
+            assign(PairingStrategy::Zipped, &mut rng),
+            vec![vec![0], vec![1], vec![2]]
+        );
+        assert_eq!(
+            assign_receivers(3, 2, PairingStrategy::Zipped, &mut rng),
+            vec![vec![0], vec![1], vec![]]
+        );
+
+        // Случайное распределение: каждый получатель ровно один раз
+        let random = assign(PairingStrategy::Random, &mut rng);
+        assert_eq!(random.len(), 3);
+        let mut receivers = random.concat();
+        receivers.sort();
+        assert_eq!(receivers, vec![0, 1, 2, 3]);
+
+        assert!(assign_receivers(0, 4, PairingStrategy::RoundRobin, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn test_shards_are_disjoint_and_cover_all_receivers() {
+        let receivers: Vec<Pubkey> = (0..10).map(|_| Pubkey::new_unique()).collect();
+
+        let shards: Vec<Vec<Pubkey>> = (0..3)
+            .map(|index| shard_receivers(&receivers, index, 3).unwrap())
+            .collect();
+
+        assert_eq!(
+            shards.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![4, 3, 3]
+        );
+        let mut union: Vec<Pubkey> = shards.concat();
+        assert_eq!(union.len(), receivers.len());
+        union.sort();
+        let mut expected = receivers.clone();
+        expected.sort();
+        assert_eq!(union, expected);
+
+        assert_eq!(shard_receivers(&receivers, 0, 3).unwrap(), shards[0]);
+        assert!(shard_receivers(&receivers, 3, 3).is_err());
+        assert!(shard_receivers(&receivers, 0, 0).is_err());
+    }
+}
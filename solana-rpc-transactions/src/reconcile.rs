@@ -0,0 +1,150 @@
+use crate::report::{TransferOutcome, TransferRecord};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::BTreeMap;
+use tracing::warn;
+
+// Итоговый баланс получателя не совпал с ожидаемым, actual = None - баланс не прочитали
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceDiscrepancy {
+    pub receiver: Pubkey,
+    pub expected: i128,
+    pub actual: Option<u64>,
+}
+
+// Балансы получателей до кампании; нечитаемые не попадают в сверку
+pub fn fetch_balances(client: &RpcClient, receivers: &[Pubkey]) -> BTreeMap<Pubkey, u64> {
+    let mut balances = BTreeMap::new();
+    for receiver in receivers {
+        match client.get_balance(receiver) {
+            Ok(balance) => {
+                balances.insert(*receiver, balance);
+            }
+            Err(err) => warn!(
+                "Failed to read balance of {}, it will not be reconciled: {}",
+                receiver, err
+            ),
+        }
+    }
+    balances
+}
+
+// Ожидаемые балансы после кампании: учитываются только подтвержденные переводы.
+// Получатель, который сам был отправителем, теряет отправленное и комиссию за каждый перевод
+pub fn expected_balances(
+    initial: &BTreeMap<Pubkey, u64>,
+    records: &[TransferRecord],
+    fee_lamports: u64,
+) -> BTreeMap<Pubkey, i128> {
+    let mut expected: BTreeMap<Pubkey, i128> = initial
+        .iter()
+        .map(|(receiver, balance)| (*receiver, *balance as i128))
+        .collect();
+
+    for record in records {
+        if !matches!(record.outcome, TransferOutcome::Confirmed(_)) {
+            continue;
+        }
+        let amount = record.amount;
+
+        if let Some(balance) = expected.get_mut(&record.receiver) {
+            *balance += amount as i128;
+        }
+        if let Some(balance) = expected.get_mut(&record.sender) {
+            *balance -= amount as i128 + fee_lamports as i128;
+        }
+    }
+
+    expected
+}
+
+// Сравнивает итоговые балансы с ожидаемыми и возвращает все расхождения
+pub fn reconcile_balances(
+    client: &RpcClient,
+    expected: &BTreeMap<Pubkey, i128>,
+) -> Vec<BalanceDiscrepancy> {
+    expected
+        .iter()
+        .filter_map(|(receiver, &expected)| {
+            let actual = match client.get_balance(receiver) {
+                Ok(actual) if actual as i128 == expected => return None,
+                Ok(actual) => Some(actual),
+                Err(err) => {
+                    warn!("Failed to read final balance of {}: {}", receiver, err);
+                    None
+                }
+            };
+            Some(BalanceDiscrepancy {
+                receiver: *receiver,
+                expected,
+                actual,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{balance_response, MockRpc};
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::signature::Signature;
+    use std::time::Duration;
+
+    fn confirmed(sender: Pubkey, receiver: Pubkey) -> TransferRecord {
+        TransferRecord {
+            sender,
+            receiver,
+            amount: 1_000,
+            duration: Duration::ZERO,
+            outcome: TransferOutcome::Confirmed(Signature::new_unique()),
+        }
+    }
+
+    #[test]
+    fn test_reconciliation_flags_injected_discrepancy() {
+        // a - отправитель и получатель одновременно, b только отправляет
+        let (a, b, c) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let records = vec![confirmed(a, c), confirmed(b, a), confirmed(b, c)];
+
+        let rpc = MockRpc::new();
+        rpc.push(RpcRequest::GetBalance, Ok(balance_response(50_000)))
+            .push(RpcRequest::GetBalance, Ok(balance_response(0)));
+        let client = rpc.client();
+        let initial = fetch_balances(&client, &[a, c]);
+        let expected = expected_balances(&initial, &records, 5_000);
+
+        assert_eq!(expected[&a], 50_000 + 1_000 - 1_000 - 5_000);
+        assert_eq!(expected[&c], 2_000);
+
+        for balance in expected.values() {
+            rpc.push(
+                RpcRequest::GetBalance,
+                Ok(balance_response(*balance as u64)),
+            );
+        }
+        assert!(reconcile_balances(&client, &expected).is_empty());
+
+        // Получатель c недополучил один перевод
+        for (receiver, balance) in &expected {
+            let actual = if *receiver == c {
+                1_000
+            } else {
+                *balance as u64
+            };
+            rpc.push(RpcRequest::GetBalance, Ok(balance_response(actual)));
+        }
+        assert_eq!(
+            reconcile_balances(&client, &expected),
+            vec![BalanceDiscrepancy {
+                receiver: c,
+                expected: 2_000,
+                actual: Some(1_000),
+            }]
+        );
+    }
+}
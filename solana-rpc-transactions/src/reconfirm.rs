@@ -0,0 +1,170 @@
+use solana_client::{
+    client_error::ClientError, rpc_client::RpcClient,
+    rpc_request::MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS,
+};
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signature};
+use std::{fmt::Write, str::FromStr};
+use tracing::warn;
+
+// Текущее состояние подписи из ранее сохраненного отчета
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FinalStatus {
+    Finalized,
+    // Транзакция найдена, но еще не финализирована
+    Pending,
+    Failed(String),
+    // Ноде неизвестна подпись даже с поиском по истории
+    Dropped,
+}
+
+impl FinalStatus {
+    fn label(&self) -> &'static str {
+        match self {
+            FinalStatus::Finalized => "finalized",
+            FinalStatus::Pending => "pending",
+            FinalStatus::Failed(_) => "failed",
+            FinalStatus::Dropped => "dropped",
+        }
+    }
+}
+
+// Подписи переводов из CSV, записанного write_latency_report
+// Строки без подписи (simulated, failed) пропускаются
+pub fn read_report_signatures(report: &str) -> Vec<Signature> {
+    report
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let columns: Vec<&str> = line.split(',').collect();
+            match columns.as_slice() {
+                [_, _, _, "confirmed" | "sent", detail] => match Signature::from_str(detail) {
+                    Ok(signature) => Some(signature),
+                    Err(err) => {
+                        warn!(
+                            "Skipping report line with bad signature {}: {}",
+                            detail, err
+                        );
+                        None
+                    }
+                },
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+// Статусы запрашиваются с поиском по истории, чтобы найти и давно подтвержденные подписи
+pub async fn reconfirm_signatures(
+    client: &RpcClient,
+    signatures: &[Signature],
+) -> Result<Vec<(Signature, FinalStatus)>, ClientError> {
+    let mut results = Vec::with_capacity(signatures.len());
+
+    for chunk in signatures.chunks(MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS) {
+        let statuses = client.get_signature_statuses_with_history(chunk)?.value;
+
+        for (signature, status) in chunk.iter().zip(statuses) {
+            let status = match status {
+                Some(status) => match &status.err {
+                    Some(err) => FinalStatus::Failed(err.to_string()),
+                    None if status.satisfies_commitment(CommitmentConfig::finalized()) => {
+                        FinalStatus::Finalized
+                    }
+                    None => FinalStatus::Pending,
+                },
+                None => FinalStatus::Dropped,
+            };
+            results.push((*signature, status));
+        }
+    }
+
+    Ok(results)
+}
+
+pub fn format_reconfirm(results: &[(Signature, FinalStatus)]) -> String {
+    let mut output = String::new();
+    let count = |label: &str| {
+        results
+            .iter()
+            .filter(|(_, status)| status.label() == label)
+            .count()
+    };
+
+    for (signature, status) in results {
+        match status {
+            FinalStatus::Failed(err) => writeln!(output, "{}: failed ({})", signature, err),
+            _ => writeln!(output, "{}: {}", signature, status.label()),
+        }
+        .expect("Writing to a String never fails");
+    }
+    write!(
+        output,
+        "Finalized: {}, pending: {}, failed: {}, dropped: {}",
+        count("finalized"),
+        count("pending"),
+        count("failed"),
+        count("dropped")
+    )
+    .expect("Writing to a String never fails");
+
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockRpc;
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_reconfirm_classifies_report_signatures() {
+        let signatures: Vec<Signature> = (0..4).map(|_| Signature::new_unique()).collect();
+        let report = format!(
+            "sender,receiver,duration_ms,outcome,detail\n\
+             a,b,10.000,confirmed,{}\n\
+             a,b,10.000,sent,{}\n\
+             a,b,10.000,failed,Confirmation timed out\n\
+             a,b,10.000,sent,{}\n\
+             a,b,10.000,simulated,\n\
+             a,b,10.000,sent,{}\n",
+            signatures[0], signatures[1], signatures[2], signatures[3]
+        );
+        let rpc = MockRpc::new();
+        rpc.push(
+            RpcRequest::GetSignatureStatuses,
+            Ok(json!({
+                "context": { "slot": 1 },
+                "value": [
+                    { "slot": 1, "confirmations": null, "status": { "Ok": null }, "err": null, "confirmationStatus": "finalized" },
+                    { "slot": 1, "confirmations": null, "status": { "Err": "AccountNotFound" }, "err": "AccountNotFound", "confirmationStatus": "finalized" },
+                    null,
+                    { "slot": 1, "confirmations": 3, "status": { "Ok": null }, "err": null, "confirmationStatus": "confirmed" }
+                ]
+            })),
+        );
+        let client = rpc.client();
+
+        let parsed = read_report_signatures(&report);
+        assert_eq!(parsed, signatures);
+
+        let results = reconfirm_signatures(&client, &parsed).await.unwrap();
+        let output = format_reconfirm(&results);
+
+        assert_eq!(
+            rpc.params(RpcRequest::GetSignatureStatuses)[0][1]["searchTransactionHistory"],
+            json!(true)
+        );
+        assert_eq!(
+            output,
+            format!(
+                "{}: finalized\n\
+                 {}: failed (Attempt to debit an account but found no record of a prior credit.)\n\
+                 {}: dropped\n\
+                 {}: pending\n\
+                 Finalized: 1, pending: 1, failed: 1, dropped: 1",
+                signatures[0], signatures[1], signatures[2], signatures[3]
+            )
+        );
+    }
+}
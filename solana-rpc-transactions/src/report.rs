@@ -0,0 +1,123 @@
+use crate::failure::FailureCategory;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::{fmt, fs::File, io, io::Write, time::Duration};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransferOutcome {
+    Confirmed(Signature),
+    // Отправлена без ожидания подтверждения
+    Sent(Signature),
+    Simulated,
+    // Текст ошибки и ее первопричина
+    Failed(String, FailureCategory),
+    // Не подтвердилась до истечения блокхеша, перевод можно безопасно повторить
+    Dropped(Signature),
+    // Отправка и подтверждение не уложились в per_transfer_timeout
+    TimedOut,
+}
+
+// Результат одного перевода от отправителя получателю
+#[derive(Debug, Clone)]
+pub struct TransferRecord {
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+    pub duration: Duration,
+    pub outcome: TransferOutcome,
+}
+
+// Почему пара отправитель-получатель не получила перевод
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SkipReason {
+    SelfSend,
+    // Получатель повторялся в списке и был удален dedupe_receivers
+    DuplicateReceiver,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkipReason::SelfSend => write!(f, "self-send"),
+            SkipReason::DuplicateReceiver => write!(f, "duplicate receiver"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkippedTransfer {
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    pub reason: SkipReason,
+}
+
+// Перевод, не прошедший и после всех попыток, вместе с последней ошибкой
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadLetter {
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+    pub error: String,
+}
+
+pub fn dead_letters(records: &[TransferRecord]) -> Vec<DeadLetter> {
+    records
+        .iter()
+        .filter_map(|record| match &record.outcome {
+            TransferOutcome::Failed(error, _) => Some(DeadLetter {
+                sender: record.sender,
+                receiver: record.receiver,
+                amount: record.amount,
+                error: error.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+// CSV для ручного разбора: из него видно, какие пары отправить заново
+pub fn write_dead_letters(path: &str, dead_letters: &[DeadLetter]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "sender,receiver,amount,error")?;
+
+    for dead_letter in dead_letters {
+        writeln!(
+            file,
+            "{},{},{},{}",
+            dead_letter.sender,
+            dead_letter.receiver,
+            dead_letter.amount,
+            dead_letter.error.replace([',', '\n'], " ")
+        )?;
+    }
+
+    Ok(())
+}
+
+// CSV с задержкой каждого перевода для последующего анализа перцентилей
+pub fn write_latency_report(path: &str, records: &[TransferRecord]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "sender,receiver,duration_ms,outcome,detail")?;
+
+    for record in records {
+        let (outcome, detail) = match &record.outcome {
+            TransferOutcome::Confirmed(signature) => ("confirmed", signature.to_string()),
+            TransferOutcome::Sent(signature) => ("sent", signature.to_string()),
+            TransferOutcome::Simulated => ("simulated", String::new()),
+            TransferOutcome::Failed(err, _) => ("failed", err.replace([',', '\n'], " ")),
+            TransferOutcome::Dropped(signature) => ("dropped", signature.to_string()),
+            TransferOutcome::TimedOut => ("timed_out", String::new()),
+        };
+
+        writeln!(
+            file,
+            "{},{},{:.3},{},{}",
+            record.sender,
+            record.receiver,
+            record.duration.as_secs_f64() * 1000.0,
+            outcome,
+            detail
+        )?;
+    }
+
+    Ok(())
+}
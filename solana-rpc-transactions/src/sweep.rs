@@ -0,0 +1,323 @@
+use solana_account_decoder::{parse_token::UiTokenAccount, UiAccountData};
+use solana_client::{
+    client_error::ClientError, rpc_client::RpcClient, rpc_request::TokenAccountsFilter,
+};
+use solana_sdk::{
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    transaction::Transaction,
+};
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+use std::{collections::HashSet, str::FromStr};
+use tracing::{error, warn};
+
+// Токен-аккаунт владельца и его баланс в минимальных единицах
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenBalance {
+    pub account: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub decimals: u8,
+}
+
+// Все SPL-токен аккаунты владельца, нераспознанные аккаунты пропускаются
+pub async fn find_token_accounts(
+    client: &RpcClient,
+    owner: &Pubkey,
+) -> Result<Vec<TokenBalance>, ClientError> {
+    let accounts = client
+        .get_token_accounts_by_owner(owner, TokenAccountsFilter::ProgramId(spl_token::id()))?;
+
+    Ok(accounts
+        .into_iter()
+        .filter_map(|keyed| {
+            let balance = parse_token_balance(&keyed.pubkey, &keyed.account.data);
+            if balance.is_none() {
+                warn!("Skipping unparsable token account {}", keyed.pubkey);
+            }
+            balance
+        })
+        .collect())
+}
+
+fn parse_token_balance(pubkey: &str, data: &UiAccountData) -> Option<TokenBalance> {
+    let UiAccountData::Json(account) = data else {
+        return None;
+    };
+    let info: UiTokenAccount = serde_json::from_value(account.parsed.get("info")?.clone()).ok()?;
+
+    Some(TokenBalance {
+        account: Pubkey::from_str(pubkey).ok()?,
+        mint: Pubkey::from_str(&info.mint).ok()?,
+        amount: info.token_amount.amount.parse().ok()?,
+        decimals: info.token_amount.decimals,
+    })
+}
+
+// Перевод всего остатка на ATA казначейства; если ATA еще нет, перед переводом
+// его создает payer. Инструкция идемпотентна, поэтому гонка с другим создателем не страшна
+pub fn sweep_instructions(
+    payer: &Pubkey,
+    owner: &Pubkey,
+    treasury: &Pubkey,
+    balance: &TokenBalance,
+    create_destination: bool,
+) -> Vec<Instruction> {
+    let destination = get_associated_token_address(treasury, &balance.mint);
+    let mut instructions = Vec::with_capacity(2);
+
+    if create_destination {
+        instructions.push(create_associated_token_account_idempotent(
+            payer,
+            treasury,
+            &balance.mint,
+            &spl_token::id(),
+        ));
+    }
+    instructions.push(
+        spl_token::instruction::transfer_checked(
+            &spl_token::id(),
+            &balance.account,
+            &balance.mint,
+            &destination,
+            owner,
+            &[],
+            balance.amount,
+            balance.decimals,
+        )
+        .expect("Token program id is valid"),
+    );
+
+    instructions
+}
+
+async fn account_exists(client: &RpcClient, address: &Pubkey) -> Result<bool, ClientError> {
+    Ok(client
+        .get_account_with_commitment(address, client.commitment())?
+        .value
+        .is_some())
+}
+
+// Переводит каждый ненулевой баланс отдельной транзакцией, комиссии и создание ATA
+// оплачивает отправитель. Ошибка одного аккаунта не останавливает перевод остальных
+pub async fn sweep_tokens(
+    client: &RpcClient,
+    sender: &Keypair,
+    treasury: &Pubkey,
+) -> Result<Vec<(TokenBalance, Signature)>, ClientError> {
+    let balances = find_token_accounts(client, &sender.pubkey()).await?;
+    let mut existing: HashSet<Pubkey> = HashSet::new();
+    let mut swept = Vec::new();
+
+    for balance in balances.into_iter().filter(|balance| balance.amount > 0) {
+        let destination = get_associated_token_address(treasury, &balance.mint);
+        let create_destination = !existing.contains(&destination)
+            && match account_exists(client, &destination).await {
+                Ok(exists) => !exists,
+                Err(err) => {
+                    warn!(
+                        "Failed to check token account {}, creating it anyway: {}",
+                        destination, err
+                    );
+                    true
+                }
+            };
+        let instructions = sweep_instructions(
+            &sender.pubkey(),
+            &sender.pubkey(),
+            treasury,
+            &balance,
+            create_destination,
+        );
+        let recent_blockhash = client.get_latest_blockhash()?;
+        let transaction = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&sender.pubkey()),
+            &[sender],
+            recent_blockhash,
+        );
+
+        match client.send_and_confirm_transaction(&transaction) {
+            Ok(signature) => {
+                existing.insert(destination);
+                swept.push((balance, signature));
+            }
+            Err(err) => error!(
+                "Failed to sweep token account {} of wallet {}: {}",
+                balance.account,
+                sender.pubkey(),
+                err
+            ),
+        }
+    }
+
+    Ok(swept)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockRpc;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde_json::{json, Value};
+    use solana_client::rpc_request::RpcRequest;
+    use spl_token::instruction::TokenInstruction;
+
+    fn token_account(owner: &Pubkey, mint: &Pubkey, amount: u64) -> (Pubkey, Value) {
+        let account = Pubkey::new_unique();
+        let value = json!({
+            "pubkey": account.to_string(),
+            "account": {
+                "lamports": 2_039_280,
+                "data": {
+                    "program": "spl-token",
+                    "parsed": {
+                        "type": "account",
+                        "info": {
+                            "mint": mint.to_string(),
+                            "owner": owner.to_string(),
+                            "tokenAmount": {
+                                "uiAmount": amount as f64 / 1_000_000.0,
+                                "decimals": 6,
+                                "amount": amount.to_string(),
+                                "uiAmountString": (amount as f64 / 1_000_000.0).to_string()
+                            },
+                            "state": "initialized",
+                            "isNative": false
+                        }
+                    },
+                    "space": 165
+                },
+                "owner": spl_token::id().to_string(),
+                "executable": false,
+                "rentEpoch": 0,
+                "space": 165
+            }
+        });
+        (account, value)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_sweep_moves_nonzero_token_balances() {
+        let rpc = MockRpc::new();
+        let client = rpc.client();
+        let sender = Keypair::new();
+        let treasury = Pubkey::new_unique();
+        let (usdc, empty_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (usdc_account, usdc_value) = token_account(&sender.pubkey(), &usdc, 1_500_000);
+        let (_, empty_value) = token_account(&sender.pubkey(), &empty_mint, 0);
+        let response = json!({
+            "context": { "slot": 1 },
+            "value": [usdc_value, empty_value]
+        });
+        rpc.push(RpcRequest::GetTokenAccountsByOwner, Ok(response.clone()))
+            .push(RpcRequest::GetTokenAccountsByOwner, Ok(response));
+
+        let balances = find_token_accounts(&client, &sender.pubkey())
+            .await
+            .expect("Token accounts should be listed");
+        assert_eq!(balances.len(), 2);
+        assert_eq!(balances[0].account, usdc_account);
+        assert_eq!(balances[0].mint, usdc);
+        assert_eq!(balances[0].amount, 1_500_000);
+        assert_eq!(balances[1].amount, 0);
+
+        let instructions = sweep_instructions(
+            &sender.pubkey(),
+            &sender.pubkey(),
+            &treasury,
+            &balances[0],
+            true,
+        );
+        assert_eq!(
+            instructions[0].program_id,
+            spl_associated_token_account::id()
+        );
+        assert_eq!(
+            TokenInstruction::unpack(&instructions[1].data).unwrap(),
+            TokenInstruction::TransferChecked {
+                amount: 1_500_000,
+                decimals: 6
+            }
+        );
+        assert_eq!(
+            instructions[1].accounts[2].pubkey,
+            get_associated_token_address(&treasury, &usdc)
+        );
+
+        let swept = sweep_tokens(&client, &sender, &treasury)
+            .await
+            .expect("Sweep should succeed");
+
+        assert_eq!(swept.len(), 1);
+        assert_eq!(swept[0].0.mint, usdc);
+        assert_eq!(swept[0].0.amount, 1_500_000);
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_treasury_ata_is_created_only_when_absent() {
+        let rpc = MockRpc::new();
+        let client = rpc.client();
+        let sender = Keypair::new();
+        let treasury = Pubkey::new_unique();
+        let (existing_mint, missing_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (_, existing_value) = token_account(&sender.pubkey(), &existing_mint, 10);
+        let (_, missing_value) = token_account(&sender.pubkey(), &missing_mint, 20);
+        rpc.push(
+            RpcRequest::GetTokenAccountsByOwner,
+            Ok(json!({
+                "context": { "slot": 1 },
+                "value": [existing_value, missing_value]
+            })),
+        )
+        // ATA казначейства для первого mint уже есть, для второго ответит fallback с null
+        .push(
+            RpcRequest::GetAccountInfo,
+            Ok(json!({
+                "context": { "slot": 1 },
+                "value": {
+                    "data": ["", "base64"],
+                    "executable": false,
+                    "lamports": 2_039_280,
+                    "owner": spl_token::id().to_string(),
+                    "rentEpoch": 0,
+                    "space": 0
+                }
+            })),
+        );
+
+        let swept = sweep_tokens(&client, &sender, &treasury)
+            .await
+            .expect("Sweep should succeed");
+        assert_eq!(swept.len(), 2);
+
+        let checked: Vec<Value> = rpc
+            .params(RpcRequest::GetAccountInfo)
+            .iter()
+            .map(|params| params[0].clone())
+            .collect();
+        assert_eq!(
+            checked,
+            vec![
+                json!(get_associated_token_address(&treasury, &existing_mint).to_string()),
+                json!(get_associated_token_address(&treasury, &missing_mint).to_string()),
+            ]
+        );
+
+        let sent: Vec<usize> = rpc
+            .params(RpcRequest::SendTransaction)
+            .iter()
+            .map(|params| {
+                let data = STANDARD.decode(params[0].as_str().unwrap()).unwrap();
+                let transaction: Transaction = bincode::deserialize(&data).unwrap();
+                transaction.message.instructions.len()
+            })
+            .collect();
+        assert_eq!(sent, vec![1, 2]);
+    }
+}
@@ -0,0 +1,37 @@
+use solana_program::program_error::ProgramError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepositError {
+    // Сумма балансов после перевода не сходится с исходной
+    InvariantViolation,
+    // Комиссия больше 100%
+    InvalidFeeBps,
+    // Передан не тот treasury, что записан в депозите
+    InvalidTreasury,
+    // Сумма депозита меньше минимальной
+    BelowMinimum,
+    // Неизвестная версия формата данных депозита
+    UnsupportedVersion,
+    // Состояние депозита изменилось с момента, когда клиент его прочитал
+    StaleState,
+    // Текущий слот больше deadline_slot из инструкции
+    DeadlineExceeded,
+    // Сумма выводов за окно слотов превысила withdraw_cap
+    DailyLimitExceeded,
+    // Заморозкой управляют только владелец депозита и authority
+    Unauthorized,
+    // Депозит заморожен, выводы запрещены до разморозки
+    Frozen,
+    // Аккаунт реферала совпадает с депозитом или плательщиком либо недоступен для записи
+    InvalidReferrer,
+    // У плательщика не хватает лампортов на rent-exempt минимум нового депозита
+    InsufficientFundsForRent,
+    // Белый список задан, а получателя вывода в нем нет
+    DestinationNotAllowed,
+}
+
+impl From<DepositError> for ProgramError {
+    fn from(err: DepositError) -> Self {
+        ProgramError::Custom(err as u32)
+    }
+}
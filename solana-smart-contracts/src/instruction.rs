@@ -0,0 +1,613 @@
+use crate::{
+    find_deposit_address,
+    lamports::Lamports,
+    state::{DepositPurpose, MAX_WHITELIST},
+    CreateDepositArgs, DepositInstruction,
+};
+use solana_program::{
+    hash::Hash,
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+};
+use solana_sdk::{
+    message::Message,
+    signer::{Signer, SignerError},
+    transaction::Transaction,
+};
+use std::collections::HashMap;
+
+// Инструкция вместе с ключами, которые обязаны ее подписать
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuiltInstruction {
+    pub instruction: Instruction,
+    pub signers: Vec<Pubkey>,
+}
+
+impl BuiltInstruction {
+    // Подписантов берем из аккаунтов инструкции, чтобы список не расходился с ними
+    fn new(instruction: Instruction) -> Self {
+        let signers = instruction
+            .accounts
+            .iter()
+            .filter(|meta| meta.is_signer)
+            .map(|meta| meta.pubkey)
+            .collect();
+
+        BuiltInstruction {
+            instruction,
+            signers,
+        }
+    }
+}
+
+// Собирает транзакцию из инструкций и подписывает ее ключами из signers;
+// payer и все подписанты инструкций должны быть в signers
+pub fn signed_transaction(
+    instructions: &[BuiltInstruction],
+    payer: &Pubkey,
+    signers: &HashMap<Pubkey, &dyn Signer>,
+    recent_blockhash: Hash,
+) -> Result<Transaction, SignerError> {
+    let mut required = vec![*payer];
+    for signer in instructions.iter().flat_map(|built| &built.signers) {
+        if !required.contains(signer) {
+            required.push(*signer);
+        }
+    }
+
+    let keypairs = required
+        .iter()
+        .map(|pubkey| signers.get(pubkey).copied())
+        .collect::<Option<Vec<&dyn Signer>>>()
+        .ok_or(SignerError::NotEnoughSigners)?;
+
+    let instructions: Vec<Instruction> = instructions
+        .iter()
+        .map(|built| built.instruction.clone())
+        .collect();
+    let mut transaction = Transaction::new_unsigned(Message::new(&instructions, Some(payer)));
+    transaction.try_sign(&keypairs, recent_blockhash)?;
+    Ok(transaction)
+}
+
+// Ожидаемая длина данных инструкции: байт варианта и его поля
+pub fn expected_data_len(instruction: &DepositInstruction) -> usize {
+    match instruction {
+        DepositInstruction::ProcessCreateDeposit { .. } => 1 + CreateDepositArgs::LEN,
+        DepositInstruction::ProcessDepositTranfer { deadline_slot, .. } => {
+            1 + 8 + 1 + deadline_slot.map_or(0, |_| 8)
+        }
+        DepositInstruction::ProcessWithdraw { state_hash, .. } => {
+            1 + 8 + 1 + state_hash.map_or(0, |_| 32)
+        }
+        DepositInstruction::ProcessBalance => 1,
+        DepositInstruction::ProcessCreatePdaDeposit { .. } => 1 + 1 + CreateDepositArgs::LEN,
+        DepositInstruction::ProcessRealloc { .. } => 1 + 8,
+        DepositInstruction::ProcessMigrate => 1,
+        DepositInstruction::ProcessBalanceLenient => 1,
+        DepositInstruction::ProcessSetAuthority { .. } => 1 + 32,
+        DepositInstruction::ProcessFreeze | DepositInstruction::ProcessThaw => 1,
+        DepositInstruction::ProcessClose => 1,
+        DepositInstruction::ProcessSetWhitelist { .. } => 1 + 32 * MAX_WHITELIST,
+        DepositInstruction::ProcessTaggedDeposit { .. } => 1 + 8 + 1,
+        DepositInstruction::ProcessGetState => 1,
+    }
+}
+
+// Сериализуем инструкцию и проверяем, что программа сможет ее разобрать
+fn pack(instruction: &DepositInstruction) -> Result<Vec<u8>, ProgramError> {
+    let data = instruction.pack();
+    let expected = expected_data_len(instruction);
+
+    if data.len() != expected {
+        msg!(
+            "Instruction data has {} bytes, expected {}.",
+            data.len(),
+            expected
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    Ok(data)
+}
+
+// Создание депозита на новом аккаунте, deposit и user подписывают транзакцию
+pub fn create_deposit(
+    program_id: &Pubkey,
+    deposit: &Pubkey,
+    user: &Pubkey,
+    args: CreateDepositArgs,
+) -> Result<BuiltInstruction, ProgramError> {
+    let data = pack(&DepositInstruction::ProcessCreateDeposit { args })?;
+
+    Ok(BuiltInstruction::new(Instruction::new_with_bytes(
+        *program_id,
+        &data,
+        vec![
+            AccountMeta::new(*deposit, true),
+            AccountMeta::new(*user, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )))
+}
+
+// Создание депозита, где rent платит payer, а владельцем записывается owner;
+// подписывают deposit, payer и owner
+pub fn create_deposit_for_owner(
+    program_id: &Pubkey,
+    deposit: &Pubkey,
+    payer: &Pubkey,
+    owner: &Pubkey,
+    args: CreateDepositArgs,
+) -> Result<BuiltInstruction, ProgramError> {
+    let mut instruction = create_deposit(program_id, deposit, payer, args)?.instruction;
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(*owner, true));
+    Ok(BuiltInstruction::new(instruction))
+}
+
+// Создание депозита на PDA пользователя
+pub fn create_pda_deposit(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    bump: u8,
+    args: CreateDepositArgs,
+) -> Result<BuiltInstruction, ProgramError> {
+    let data = pack(&DepositInstruction::ProcessCreatePdaDeposit { bump, args })?;
+    let (deposit, _) = find_deposit_address(program_id, user);
+
+    Ok(BuiltInstruction::new(Instruction::new_with_bytes(
+        *program_id,
+        &data,
+        vec![
+            AccountMeta::new(deposit, false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )))
+}
+
+// deadline_slot - последний слот, в котором депозит еще может выполниться
+// referrer получает referral_bps депозита, если она задана при создании
+pub fn deposit(
+    program_id: &Pubkey,
+    deposit: &Pubkey,
+    user: &Pubkey,
+    referrer: Option<&Pubkey>,
+    amount: Lamports,
+    deadline_slot: Option<u64>,
+) -> Result<BuiltInstruction, ProgramError> {
+    let data = pack(&DepositInstruction::ProcessDepositTranfer {
+        amount: amount.into(),
+        deadline_slot,
+    })?;
+
+    let mut accounts = vec![
+        AccountMeta::new(*deposit, false),
+        AccountMeta::new(*user, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if let Some(referrer) = referrer {
+        accounts.push(AccountMeta::new(*referrer, false));
+    }
+
+    Ok(BuiltInstruction::new(Instruction::new_with_bytes(
+        *program_id,
+        &data,
+        accounts,
+    )))
+}
+
+// Депозит с назначением, которое программа запомнит в состоянии
+pub fn tagged_deposit(
+    program_id: &Pubkey,
+    deposit: &Pubkey,
+    user: &Pubkey,
+    amount: Lamports,
+    purpose: DepositPurpose,
+) -> Result<BuiltInstruction, ProgramError> {
+    let data = pack(&DepositInstruction::ProcessTaggedDeposit {
+        amount: amount.into(),
+        purpose,
+    })?;
+
+    Ok(BuiltInstruction::new(Instruction::new_with_bytes(
+        *program_id,
+        &data,
+        vec![
+            AccountMeta::new(*deposit, false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )))
+}
+
+// treasury обязателен, если у депозита задана комиссия
+// state_hash из deposit_state_hash отклоняет вывод, если состояние успело измениться
+pub fn withdraw(
+    program_id: &Pubkey,
+    deposit: &Pubkey,
+    user: &Pubkey,
+    treasury: Option<&Pubkey>,
+    amount: Lamports,
+    state_hash: Option<[u8; 32]>,
+) -> Result<BuiltInstruction, ProgramError> {
+    let data = pack(&DepositInstruction::ProcessWithdraw {
+        amount: amount.into(),
+        state_hash,
+    })?;
+
+    let mut accounts = vec![
+        AccountMeta::new(*deposit, false),
+        AccountMeta::new(*user, true),
+    ];
+    if let Some(treasury) = treasury {
+        accounts.push(AccountMeta::new(*treasury, false));
+    }
+
+    Ok(BuiltInstruction::new(Instruction::new_with_bytes(
+        *program_id,
+        &data,
+        accounts,
+    )))
+}
+
+// Вывод на чужой адрес: user получает средства, owner подписывает вывод
+pub fn withdraw_for_owner(
+    program_id: &Pubkey,
+    deposit: &Pubkey,
+    owner: &Pubkey,
+    user: &Pubkey,
+    treasury: Option<&Pubkey>,
+    amount: Lamports,
+    state_hash: Option<[u8; 32]>,
+) -> Result<BuiltInstruction, ProgramError> {
+    let mut instruction =
+        withdraw(program_id, deposit, user, treasury, amount, state_hash)?.instruction;
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(*owner, true));
+    Ok(BuiltInstruction::new(instruction))
+}
+
+// Увеличение данных депозита, payer доплачивает rent
+pub fn realloc(
+    program_id: &Pubkey,
+    deposit: &Pubkey,
+    payer: &Pubkey,
+    new_size: u64,
+) -> Result<BuiltInstruction, ProgramError> {
+    let data = pack(&DepositInstruction::ProcessRealloc { new_size })?;
+
+    Ok(BuiltInstruction::new(Instruction::new_with_bytes(
+        *program_id,
+        &data,
+        vec![
+            AccountMeta::new(*deposit, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )))
+}
+
+// Миграция депозита на текущую версию формата
+pub fn migrate(
+    program_id: &Pubkey,
+    deposit: &Pubkey,
+    payer: &Pubkey,
+) -> Result<BuiltInstruction, ProgramError> {
+    let data = pack(&DepositInstruction::ProcessMigrate)?;
+
+    Ok(BuiltInstruction::new(Instruction::new_with_bytes(
+        *program_id,
+        &data,
+        vec![
+            AccountMeta::new(*deposit, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )))
+}
+
+pub fn balance(program_id: &Pubkey, deposit: &Pubkey) -> Result<BuiltInstruction, ProgramError> {
+    let data = pack(&DepositInstruction::ProcessBalance)?;
+
+    Ok(BuiltInstruction::new(Instruction::new_with_bytes(
+        *program_id,
+        &data,
+        vec![AccountMeta::new_readonly(*deposit, false)],
+    )))
+}
+
+// Состояние депозита приходит в return data транзакции
+pub fn get_state(program_id: &Pubkey, deposit: &Pubkey) -> Result<BuiltInstruction, ProgramError> {
+    let data = pack(&DepositInstruction::ProcessGetState)?;
+
+    Ok(BuiltInstruction::new(Instruction::new_with_bytes(
+        *program_id,
+        &data,
+        vec![AccountMeta::new_readonly(*deposit, false)],
+    )))
+}
+
+// Баланс без проверки, что аккаунт принадлежит программе
+pub fn balance_lenient(
+    program_id: &Pubkey,
+    account: &Pubkey,
+) -> Result<BuiltInstruction, ProgramError> {
+    let data = pack(&DepositInstruction::ProcessBalanceLenient)?;
+
+    Ok(BuiltInstruction::new(Instruction::new_with_bytes(
+        *program_id,
+        &data,
+        vec![AccountMeta::new_readonly(*account, false)],
+    )))
+}
+
+// Назначение authority, подписывает владелец депозита
+pub fn set_authority(
+    program_id: &Pubkey,
+    deposit: &Pubkey,
+    owner: &Pubkey,
+    authority: &Pubkey,
+) -> Result<BuiltInstruction, ProgramError> {
+    let data = pack(&DepositInstruction::ProcessSetAuthority {
+        authority: *authority,
+    })?;
+
+    Ok(BuiltInstruction::new(Instruction::new_with_bytes(
+        *program_id,
+        &data,
+        vec![
+            AccountMeta::new(*deposit, false),
+            AccountMeta::new_readonly(*owner, true),
+        ],
+    )))
+}
+
+// Заморозка депозита, signer - владелец или authority
+pub fn freeze(
+    program_id: &Pubkey,
+    deposit: &Pubkey,
+    signer: &Pubkey,
+) -> Result<BuiltInstruction, ProgramError> {
+    set_frozen(
+        program_id,
+        deposit,
+        signer,
+        DepositInstruction::ProcessFreeze,
+    )
+}
+
+pub fn thaw(
+    program_id: &Pubkey,
+    deposit: &Pubkey,
+    signer: &Pubkey,
+) -> Result<BuiltInstruction, ProgramError> {
+    set_frozen(program_id, deposit, signer, DepositInstruction::ProcessThaw)
+}
+
+fn set_frozen(
+    program_id: &Pubkey,
+    deposit: &Pubkey,
+    signer: &Pubkey,
+    instruction: DepositInstruction,
+) -> Result<BuiltInstruction, ProgramError> {
+    let data = pack(&instruction)?;
+
+    Ok(BuiltInstruction::new(Instruction::new_with_bytes(
+        *program_id,
+        &data,
+        vec![
+            AccountMeta::new(*deposit, false),
+            AccountMeta::new_readonly(*signer, true),
+        ],
+    )))
+}
+
+// Закрытие депозита владельцем, лампорты уходят на destination
+pub fn close(
+    program_id: &Pubkey,
+    deposit: &Pubkey,
+    owner: &Pubkey,
+    destination: &Pubkey,
+) -> Result<BuiltInstruction, ProgramError> {
+    let data = pack(&DepositInstruction::ProcessClose)?;
+
+    Ok(BuiltInstruction::new(Instruction::new_with_bytes(
+        *program_id,
+        &data,
+        vec![
+            AccountMeta::new(*deposit, false),
+            AccountMeta::new_readonly(*owner, true),
+            AccountMeta::new(*destination, false),
+        ],
+    )))
+}
+
+// Белый список получателей вывода, подписывает владелец. Пустой список снимает ограничение
+pub fn set_whitelist(
+    program_id: &Pubkey,
+    deposit: &Pubkey,
+    owner: &Pubkey,
+    destinations: &[Pubkey],
+) -> Result<BuiltInstruction, ProgramError> {
+    if destinations.len() > MAX_WHITELIST {
+        msg!(
+            "Whitelist holds at most {} destinations, got {}.",
+            MAX_WHITELIST,
+            destinations.len()
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let mut whitelist = [Pubkey::default(); MAX_WHITELIST];
+    whitelist[..destinations.len()].copy_from_slice(destinations);
+    let data = pack(&DepositInstruction::ProcessSetWhitelist {
+        destinations: whitelist,
+    })?;
+
+    Ok(BuiltInstruction::new(Instruction::new_with_bytes(
+        *program_id,
+        &data,
+        vec![
+            AccountMeta::new(*deposit, false),
+            AccountMeta::new_readonly(*owner, true),
+        ],
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_sdk::signature::Keypair;
+
+    #[test]
+    fn test_builders_produce_expected_data_len() {
+        let program_id = Pubkey::new_unique();
+        let deposit_key = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        let args = CreateDepositArgs::default();
+
+        let cases = [
+            (
+                create_deposit(&program_id, &deposit_key, &user, args.clone()).unwrap(),
+                61,
+            ),
+            (
+                create_pda_deposit(&program_id, &user, 255, args).unwrap(),
+                62,
+            ),
+            (
+                deposit(
+                    &program_id,
+                    &deposit_key,
+                    &user,
+                    None,
+                    Lamports(1_000),
+                    None,
+                )
+                .unwrap(),
+                10,
+            ),
+            (
+                deposit(
+                    &program_id,
+                    &deposit_key,
+                    &user,
+                    Some(&user),
+                    Lamports(1_000),
+                    Some(500),
+                )
+                .unwrap(),
+                18,
+            ),
+            (
+                withdraw(
+                    &program_id,
+                    &deposit_key,
+                    &user,
+                    None,
+                    Lamports(1_000),
+                    None,
+                )
+                .unwrap(),
+                10,
+            ),
+            (
+                withdraw(
+                    &program_id,
+                    &deposit_key,
+                    &user,
+                    None,
+                    Lamports(1_000),
+                    Some([1; 32]),
+                )
+                .unwrap(),
+                42,
+            ),
+            (balance(&program_id, &deposit_key).unwrap(), 1),
+            (balance_lenient(&program_id, &user).unwrap(), 1),
+            (
+                tagged_deposit(
+                    &program_id,
+                    &deposit_key,
+                    &user,
+                    Lamports(1_000),
+                    DepositPurpose::Escrow,
+                )
+                .unwrap(),
+                10,
+            ),
+            (get_state(&program_id, &deposit_key).unwrap(), 1),
+            (realloc(&program_id, &deposit_key, &user, 128).unwrap(), 9),
+            (migrate(&program_id, &deposit_key, &user).unwrap(), 1),
+            (
+                set_authority(&program_id, &deposit_key, &user, &program_id).unwrap(),
+                33,
+            ),
+            (freeze(&program_id, &deposit_key, &user).unwrap(), 1),
+            (thaw(&program_id, &deposit_key, &user).unwrap(), 1),
+            (
+                close(&program_id, &deposit_key, &user, &program_id).unwrap(),
+                1,
+            ),
+            (
+                set_whitelist(&program_id, &deposit_key, &user, &[user]).unwrap(),
+                129,
+            ),
+        ];
+
+        for (built, len) in cases {
+            assert_eq!(built.instruction.data.len(), len);
+            DepositInstruction::unpack(&built.instruction.data)
+                .expect("Program should parse builder data");
+        }
+
+        assert_eq!(
+            set_whitelist(&program_id, &deposit_key, &user, &[user; MAX_WHITELIST + 1]),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_create_reports_both_signers_and_signs() {
+        let program_id = Pubkey::new_unique();
+        let (payer, deposit_account) = (Keypair::new(), Keypair::new());
+
+        let create = create_deposit(
+            &program_id,
+            &deposit_account.pubkey(),
+            &payer.pubkey(),
+            CreateDepositArgs::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            create.signers,
+            vec![deposit_account.pubkey(), payer.pubkey()]
+        );
+
+        let mut signers: HashMap<Pubkey, &dyn Signer> = HashMap::new();
+        signers.insert(payer.pubkey(), &payer);
+        assert_eq!(
+            signed_transaction(
+                std::slice::from_ref(&create),
+                &payer.pubkey(),
+                &signers,
+                Hash::default()
+            )
+            .unwrap_err(),
+            SignerError::NotEnoughSigners
+        );
+
+        signers.insert(deposit_account.pubkey(), &deposit_account);
+        let transaction =
+            signed_transaction(&[create], &payer.pubkey(), &signers, Hash::default()).unwrap();
+        assert_eq!(transaction.signatures.len(), 2);
+        assert!(transaction.verify().is_ok());
+    }
+}
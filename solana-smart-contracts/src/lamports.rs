@@ -0,0 +1,91 @@
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use std::fmt;
+
+// Сумма в лампортах; отдельный тип, чтобы не перепутать ее с SOL или другим u64
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Lamports(pub u64);
+
+impl Lamports {
+    pub const ZERO: Lamports = Lamports(0);
+
+    // Дробные лампорты округляются; отрицательная, бесконечная или слишком большая сумма - None
+    pub fn from_sol(sol: f64) -> Option<Self> {
+        let lamports = (sol * LAMPORTS_PER_SOL as f64).round();
+        if !lamports.is_finite() || lamports < 0.0 || lamports >= u64::MAX as f64 {
+            return None;
+        }
+        Some(Lamports(lamports as u64))
+    }
+
+    pub fn to_sol(self) -> f64 {
+        self.0 as f64 / LAMPORTS_PER_SOL as f64
+    }
+
+    pub fn checked_add(self, other: Lamports) -> Option<Self> {
+        self.0.checked_add(other.0).map(Lamports)
+    }
+
+    pub fn checked_sub(self, other: Lamports) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Lamports)
+    }
+
+    pub fn checked_mul(self, factor: u64) -> Option<Self> {
+        self.0.checked_mul(factor).map(Lamports)
+    }
+}
+
+impl From<u64> for Lamports {
+    fn from(lamports: u64) -> Self {
+        Lamports(lamports)
+    }
+}
+
+impl From<Lamports> for u64 {
+    fn from(lamports: Lamports) -> Self {
+        lamports.0
+    }
+}
+
+impl fmt::Display for Lamports {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} lamports", self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sol_conversions() {
+        assert_eq!(Lamports::from_sol(1.0), Some(Lamports(LAMPORTS_PER_SOL)));
+        assert_eq!(Lamports::from_sol(0.001), Some(Lamports(1_000_000)));
+        // 0.1 не представим точно в f64, округление спасает от 99_999_999
+        assert_eq!(Lamports::from_sol(0.1), Some(Lamports(100_000_000)));
+        assert_eq!(Lamports(2_500_000_000).to_sol(), 2.5);
+        assert_eq!(
+            Lamports::from_sol(Lamports(123_456_789).to_sol()),
+            Some(Lamports(123_456_789))
+        );
+
+        assert_eq!(Lamports::from_sol(-0.5), None);
+        assert_eq!(Lamports::from_sol(f64::NAN), None);
+        assert_eq!(Lamports::from_sol(f64::INFINITY), None);
+        assert_eq!(Lamports::from_sol(u64::MAX as f64), None);
+    }
+
+    #[test]
+    fn test_checked_arithmetic_reports_overflow() {
+        let max = Lamports(u64::MAX);
+
+        assert_eq!(Lamports(1).checked_add(Lamports(2)), Some(Lamports(3)));
+        assert_eq!(max.checked_add(Lamports(1)), None);
+        assert_eq!(Lamports(5).checked_sub(Lamports(5)), Some(Lamports::ZERO));
+        assert_eq!(Lamports(5).checked_sub(Lamports(6)), None);
+        assert_eq!(Lamports(7).checked_mul(3), Some(Lamports(21)));
+        assert_eq!(max.checked_mul(2), None);
+
+        assert_eq!(u64::from(Lamports::from(42)), 42);
+        assert_eq!(Lamports(42).to_string(), "42 lamports");
+    }
+}
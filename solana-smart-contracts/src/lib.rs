@@ -1,15 +1,112 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    clock::Clock,
     entrypoint,
     entrypoint::ProgramResult,
     msg,
     pubkey::Pubkey,
-    system_instruction,
+    system_instruction, system_program,
 };
-use solana_sdk::{program::invoke, program_error::ProgramError, rent::Rent, sysvar::Sysvar};
+use solana_sdk::{
+    program::{invoke, invoke_signed, set_return_data},
+    program_error::ProgramError,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+
+pub mod error;
+pub mod instruction;
+pub mod lamports;
+pub mod state;
+
+use error::DepositError;
+use state::{DepositPurpose, DepositState, CURRENT_VERSION, MAX_FEE_BPS, MAX_WHITELIST};
+
+pub const DEPOSIT_SEED: &[u8] = b"deposit";
+
+// Адрес депозита пользователя, выводимый из program id
+pub fn find_deposit_address(program_id: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[DEPOSIT_SEED, user.as_ref()], program_id)
+}
+
+fn validate_create_args(args: &CreateDepositArgs) -> ProgramResult {
+    if args.fee_bps > MAX_FEE_BPS {
+        msg!("Fee {} bps exceeds {} bps.", args.fee_bps, MAX_FEE_BPS);
+        return Err(DepositError::InvalidFeeBps.into());
+    }
+
+    if args.referral_bps > MAX_FEE_BPS {
+        msg!(
+            "Referral share {} bps exceeds {} bps.",
+            args.referral_bps,
+            MAX_FEE_BPS
+        );
+        return Err(DepositError::InvalidFeeBps.into());
+    }
+
+    if args.withdraw_cap > 0 && args.cap_window_slots == 0 {
+        msg!("Withdraw cap requires a non-empty slot window.");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    Ok(())
+}
+
+fn init_deposit_state(
+    deposit_account: &AccountInfo,
+    args: &CreateDepositArgs,
+    owner: &Pubkey,
+) -> ProgramResult {
+    DepositState {
+        version: CURRENT_VERSION,
+        fee_bps: args.fee_bps,
+        treasury: args.treasury,
+        min_deposit: args.min_deposit,
+        seq: 0,
+        owner: *owner,
+        withdraw_cap: args.withdraw_cap,
+        cap_window_slots: args.cap_window_slots,
+        referral_bps: args.referral_bps,
+        ..DepositState::default()
+    }
+    .save(deposit_account)
+}
+
+// Следующий аккаунт должен быть настоящим System Program: иначе клиент может
+// подставить свою программу вместо той, через которую идут переводы
+fn next_system_program<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    iter: &mut I,
+) -> Result<I::Item, ProgramError> {
+    let account = next_account_info(iter)?;
+    if !system_program::check_id(account.key) {
+        msg!("Account {} is not the system program.", account.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(account)
+}
+
+// Без этой проверки CPI create_account падает с общей ошибкой System Program
+fn ensure_covers_rent(payer: &AccountInfo, required_lamports: u64) -> ProgramResult {
+    if payer.lamports() < required_lamports {
+        msg!(
+            "Payer {} has {} lamports, the deposit account needs {} to be rent-exempt.",
+            payer.key,
+            payer.lamports(),
+            required_lamports
+        );
+        return Err(DepositError::InsufficientFundsForRent.into());
+    }
+    Ok(())
+}
+
+fn process_create_deposit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    args: CreateDepositArgs,
+) -> ProgramResult {
+    validate_create_args(&args)?;
 
-fn process_create_deposit(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
     msg!(
         "Missing required signature for user account. {} ",
@@ -18,16 +115,28 @@ fn process_create_deposit(program_id: &Pubkey, accounts: &[AccountInfo]) -> Prog
 
     let deposit_account = next_account_info(accounts_iter)?;
     let user_account = next_account_info(accounts_iter)?;
-    let system_program = next_account_info(accounts_iter)?;
+    let system_program = next_system_program(accounts_iter)?;
+    // Необязательный владелец: без него депозит принадлежит тому, кто платит rent
+    let owner_account = next_account_info(accounts_iter).ok();
 
     if !user_account.is_signer {
         msg!("Missing required signature for user account.");
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let account_space = 0;
+    let owner = match owner_account {
+        Some(owner_account) if !owner_account.is_signer => {
+            msg!("Missing required signature for owner account.");
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Some(owner_account) => owner_account.key,
+        None => user_account.key,
+    };
+
+    let account_space = DepositState::LEN;
     let rent = Rent::get()?;
     let required_lamports = rent.minimum_balance(account_space);
+    ensure_covers_rent(user_account, required_lamports)?;
 
     msg!(
         "Creating deposit account with {} lamports",
@@ -49,7 +158,75 @@ fn process_create_deposit(program_id: &Pubkey, accounts: &[AccountInfo]) -> Prog
         ],
     )?;
 
-    msg!("Deposit account created successfully.");
+    init_deposit_state(deposit_account, &args, owner)?;
+
+    msg!(
+        "Deposit account created successfully, owner {}, rent paid by {}.",
+        owner,
+        user_account.key
+    );
+    Ok(())
+}
+
+// bump передает клиент, поэтому программе не нужно перебирать его через find_program_address
+fn process_create_pda_deposit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    bump: u8,
+    args: CreateDepositArgs,
+) -> ProgramResult {
+    validate_create_args(&args)?;
+
+    let accounts_iter = &mut accounts.iter();
+
+    let deposit_account = next_account_info(accounts_iter)?;
+    let user_account = next_account_info(accounts_iter)?;
+    let system_program = next_system_program(accounts_iter)?;
+
+    if !user_account.is_signer {
+        msg!("Missing required signature for user account.");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let seeds: &[&[u8]] = &[DEPOSIT_SEED, user_account.key.as_ref(), &[bump]];
+    let expected_address = Pubkey::create_program_address(seeds, program_id)
+        .map_err(|_| ProgramError::InvalidSeeds)?;
+
+    if expected_address != *deposit_account.key {
+        msg!("Deposit address does not match the provided bump {}.", bump);
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let account_space = DepositState::LEN;
+    let rent = Rent::get()?;
+    let required_lamports = rent.minimum_balance(account_space);
+    ensure_covers_rent(user_account, required_lamports)?;
+
+    msg!(
+        "Creating deposit PDA {} with {} lamports",
+        deposit_account.key,
+        required_lamports
+    );
+
+    invoke_signed(
+        &system_instruction::create_account(
+            user_account.key,
+            deposit_account.key,
+            required_lamports,
+            account_space as u64,
+            program_id,
+        ),
+        &[
+            user_account.clone(),
+            deposit_account.clone(),
+            system_program.clone(),
+        ],
+        &[seeds],
+    )?;
+
+    init_deposit_state(deposit_account, &args, user_account.key)?;
+
+    msg!("Deposit PDA created successfully.");
     Ok(())
 }
 
@@ -70,11 +247,42 @@ fn process_balance(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResu
     Ok(())
 }
 
-fn process_deposit(program_id: &Pubkey, accounts: &[AccountInfo], lamports: u64) -> ProgramResult {
+// Баланс любого аккаунта, чужой владелец только отмечается предупреждением
+fn process_balance_lenient(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let account = next_account_info(accounts_iter)?;
+
+    if account.owner != program_id {
+        msg!(
+            "Warning: account {} is owned by {}, not by the program.",
+            account.key,
+            account.owner
+        );
+    }
+
+    msg!(
+        "Deposit account {} has balance: {} lamports",
+        account.key,
+        **account.lamports.borrow()
+    );
+
+    Ok(())
+}
+
+// deadline_slot не дает выполниться депозиту, который слишком долго шел до сети
+fn process_deposit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    lamports: u64,
+    deadline_slot: Option<u64>,
+) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
 
     let deposit_account = next_account_info(accounts_iter)?;
     let user_account = next_account_info(accounts_iter)?;
+    let system_program = next_system_program(accounts_iter)?;
+    // Необязательный реферал получает referral_bps от суммы депозита
+    let referrer_account = next_account_info(accounts_iter).ok();
 
     if !user_account.is_signer {
         msg!("Missing required signature for user account.");
@@ -85,6 +293,28 @@ fn process_deposit(program_id: &Pubkey, accounts: &[AccountInfo], lamports: u64)
         return Err(ProgramError::IncorrectProgramId);
     }
 
+    if let Some(deadline_slot) = deadline_slot {
+        let current_slot = Clock::get()?.slot;
+        if current_slot > deadline_slot {
+            msg!(
+                "Current slot {} is past the deadline slot {}.",
+                current_slot,
+                deadline_slot
+            );
+            return Err(DepositError::DeadlineExceeded.into());
+        }
+    }
+
+    let mut state = DepositState::load(deposit_account)?;
+    if lamports < state.min_deposit {
+        msg!(
+            "Deposit of {} lamports is below the minimum of {}.",
+            lamports,
+            state.min_deposit
+        );
+        return Err(DepositError::BelowMinimum.into());
+    }
+
     msg!(
         "on on Lamports {} user lamprots {}",
         lamports,
@@ -96,18 +326,129 @@ fn process_deposit(program_id: &Pubkey, accounts: &[AccountInfo], lamports: u64)
         return Err(ProgramError::InsufficientFunds);
     }
 
-    **user_account.try_borrow_mut_lamports()? -= lamports;
-    **deposit_account.try_borrow_mut_lamports()? += lamports;
+    let referral = match referrer_account {
+        Some(referrer_account) if state.referral_bps > 0 => {
+            if referrer_account.key == deposit_account.key
+                || referrer_account.key == user_account.key
+                || !referrer_account.is_writable
+            {
+                msg!("Referrer account {} is not valid.", referrer_account.key);
+                return Err(DepositError::InvalidReferrer.into());
+            }
+            if state.needs_migration() {
+                msg!("Deposit account must be migrated before paying referrals.");
+                return Err(DepositError::UnsupportedVersion.into());
+            }
+            Some((referrer_account, state.referral_share(lamports)))
+        }
+        _ => None,
+    };
+    let referral_share = referral.map_or(0, |(_, share)| share);
+
+    // Кошелек пользователя принадлежит system program, списать с него можно только через CPI
+    invoke(
+        &system_instruction::transfer(
+            user_account.key,
+            deposit_account.key,
+            lamports - referral_share,
+        ),
+        &[
+            user_account.clone(),
+            deposit_account.clone(),
+            system_program.clone(),
+        ],
+    )?;
+
+    if let Some((referrer_account, share)) = referral {
+        invoke(
+            &system_instruction::transfer(user_account.key, referrer_account.key, share),
+            &[
+                user_account.clone(),
+                referrer_account.clone(),
+                system_program.clone(),
+            ],
+        )?;
+
+        state.referral_payouts = state
+            .referral_payouts
+            .checked_add(share)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        state.save(deposit_account)?;
+        msg!(
+            "Referral share {} lamports sent to {}",
+            share,
+            referrer_account.key
+        );
+    }
 
     msg!(
         "Deposited {} lamports into {}",
-        lamports,
+        lamports - referral_share,
         deposit_account.key
     );
     Ok(())
 }
 
-fn process_withdraw(program_id: &Pubkey, accounts: &[AccountInfo], lamports: u64) -> ProgramResult {
+// Обычный депозит, после которого в состоянии запоминается его назначение
+fn process_tagged_deposit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    lamports: u64,
+    purpose: DepositPurpose,
+) -> ProgramResult {
+    process_deposit(program_id, accounts, lamports, None)?;
+
+    let deposit_account = next_account_info(&mut accounts.iter())?;
+    let mut state = DepositState::load(deposit_account)?;
+    if state.needs_migration() {
+        msg!("Deposit account must be migrated before tagging deposits.");
+        return Err(DepositError::UnsupportedVersion.into());
+    }
+
+    state.last_purpose = Some(purpose);
+    state.save(deposit_account)?;
+
+    msg!(
+        "Deposit into {} tagged as {:?}",
+        deposit_account.key,
+        purpose
+    );
+    Ok(())
+}
+
+// Состояние депозита в return data, в Borsh-кодировке DepositState
+fn process_get_state(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let deposit_account = next_account_info(accounts_iter)?;
+
+    if deposit_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let state = DepositState::load(deposit_account)?;
+    let data = borsh::to_vec(&state).map_err(|_| ProgramError::InvalidAccountData)?;
+    set_return_data(&data);
+
+    msg!(
+        "Deposit account {} is at version {}, last purpose {:?}",
+        deposit_account.key,
+        state.version,
+        state.last_purpose
+    );
+    Ok(())
+}
+
+// Хэш состояния депозита по данным аккаунта, чтобы клиент мог передать его в withdraw
+pub fn deposit_state_hash(data: &[u8]) -> Result<[u8; 32], ProgramError> {
+    Ok(DepositState::unpack(data)?.hash())
+}
+
+fn process_withdraw(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    lamports: u64,
+    state_hash: Option<[u8; 32]>,
+) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
 
     let deposit_account = next_account_info(accounts_iter)?;
@@ -139,8 +480,120 @@ fn process_withdraw(program_id: &Pubkey, accounts: &[AccountInfo], lamports: u64
         user_account.lamports.borrow()
     );
 
-    **deposit_account.try_borrow_mut_lamports()? -= lamports;
-    **user_account.try_borrow_mut_lamports()? += lamports;
+    // При ненулевой комиссии третьим аккаунтом обязан идти treasury из состояния депозита
+    let mut state = DepositState::load(deposit_account)?;
+    if state.needs_migration() {
+        msg!("Deposit account must be migrated before withdrawing.");
+        return Err(DepositError::UnsupportedVersion.into());
+    }
+    if state.frozen {
+        msg!("Deposit account {} is frozen.", deposit_account.key);
+        return Err(DepositError::Frozen.into());
+    }
+    if state_hash.is_some_and(|expected| expected != state.hash()) {
+        msg!("Deposit state changed since the client read it.");
+        return Err(DepositError::StaleState.into());
+    }
+    if !state.allows_destination(user_account.key) {
+        msg!(
+            "Withdraw destination {} is not on the whitelist.",
+            user_account.key
+        );
+        return Err(DepositError::DestinationNotAllowed.into());
+    }
+
+    if state.withdraw_cap > 0 {
+        let current_slot = Clock::get()?.slot;
+        if let Err(err) = state.record_withdrawal(lamports, current_slot) {
+            msg!(
+                "Withdrawal of {} lamports exceeds the cap of {} per {} slots.",
+                lamports,
+                state.withdraw_cap,
+                state.cap_window_slots
+            );
+            return Err(err.into());
+        }
+    }
+
+    let treasury_account = if state.fee_bps > 0 {
+        let treasury_account = next_account_info(accounts_iter)?;
+        if *treasury_account.key != state.treasury {
+            msg!("Treasury account does not match deposit state.");
+            return Err(DepositError::InvalidTreasury.into());
+        }
+        Some(treasury_account)
+    } else {
+        None
+    };
+
+    // Выводит только владелец: либо он сам получатель, либо подписывает последним аккаунтом
+    let authorized = match next_account_info(accounts_iter) {
+        Ok(owner_account) => owner_account.is_signer && *owner_account.key == state.owner,
+        Err(_) => *user_account.key == state.owner,
+    };
+    if !authorized {
+        msg!("Only the deposit owner can withdraw.");
+        return Err(DepositError::Unauthorized.into());
+    }
+
+    let fee = state.withdraw_fee(lamports);
+    let payout = lamports - fee;
+
+    let deposit_before = deposit_account.lamports();
+    let user_before = user_account.lamports();
+    let treasury_before = treasury_account.map_or(0, |account| account.lamports());
+
+    // Новые балансы считаем заранее, чтобы при ошибке не изменить ни один из счетов
+    let deposit_after = deposit_before
+        .checked_sub(lamports)
+        .ok_or(ProgramError::InsufficientFunds)?;
+    let user_after = user_before
+        .checked_add(payout)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    let treasury_after = treasury_before
+        .checked_add(fee)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+
+    {
+        let mut deposit_lamports = deposit_account.try_borrow_mut_lamports()?;
+        let mut user_lamports = user_account.try_borrow_mut_lamports()?;
+        let mut treasury_lamports = treasury_account
+            .map(|account| account.try_borrow_mut_lamports())
+            .transpose()?;
+        **deposit_lamports = deposit_after;
+        **user_lamports = user_after;
+        if let Some(treasury_lamports) = treasury_lamports.as_mut() {
+            ***treasury_lamports = treasury_after;
+        }
+    }
+
+    if let Err(err) = check_withdraw_invariant(
+        &[deposit_before, user_before, treasury_before],
+        &[
+            deposit_account.lamports(),
+            user_account.lamports(),
+            treasury_account.map_or(0, |account| account.lamports()),
+        ],
+        lamports,
+    ) {
+        msg!("Withdraw invariant violated for {}", deposit_account.key);
+        return Err(err.into());
+    }
+
+    if fee > 0 {
+        msg!("Withdraw fee {} lamports sent to {}", fee, state.treasury);
+    }
+
+    state.seq += 1;
+    state.save(deposit_account)?;
+    msg!(
+        "EVENT withdraw seq={} deposit={} user={} amount={} fee={}",
+        state.seq,
+        deposit_account.key,
+        user_account.key,
+        lamports,
+        fee
+    );
 
     msg!(
         "Withdrew {} lamports from {} to {}",
@@ -148,82 +601,640 @@ fn process_withdraw(program_id: &Pubkey, accounts: &[AccountInfo], lamports: u64
         deposit_account.key,
         user_account.key
     );
+
+    // Остаток депозита после вывода, чтобы клиенту не нужен был отдельный ProcessBalance
+    set_return_data(&deposit_account.lamports().to_le_bytes());
     Ok(())
 }
 
-entrypoint!(process_instruction);
+// Сумма лампортов всех участников вывода должна сохраняться, а депозит (первый элемент)
+// уменьшаться ровно на lamports
+fn check_withdraw_invariant(
+    before: &[u64],
+    after: &[u64],
+    lamports: u64,
+) -> Result<(), DepositError> {
+    let total_before: u128 = before.iter().map(|&value| value as u128).sum();
+    let total_after: u128 = after.iter().map(|&value| value as u128).sum();
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
-pub enum DepositInstruction {
-    ProcessCreateDeposit,
-    ProcessDepositTranfer { amount: u64 },
-    ProcessWithdraw { amount: u64 },
-    ProcessBalance,
+    if total_before != total_after || before[0].checked_sub(after[0]) != Some(lamports) {
+        return Err(DepositError::InvariantViolation);
+    }
+
+    Ok(())
 }
-impl DepositInstruction {
-    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
-        let (&variant, rest) = input
-            .split_first()
-            .ok_or(ProgramError::InvalidInstructionData)?;
 
-        match variant {
-            0 => Ok(Self::ProcessCreateDeposit),
-            1 => {
-                let lamports = u64::from_le_bytes(
-                    rest.try_into()
-                        .map_err(|_| ProgramError::InvalidInstructionData)?,
-                );
-                Ok(Self::ProcessWithdraw { amount: lamports })
-            }
-            2 => Ok(Self::ProcessBalance),
-            3 => {
-                let lamports = u64::from_le_bytes(
-                    rest.try_into()
-                        .map_err(|_| ProgramError::InvalidInstructionData)?,
-                );
-                Ok(Self::ProcessDepositTranfer { amount: lamports })
-            }
-            _ => Err(ProgramError::InvalidInstructionData),
-        }
+// Увеличивает данные аккаунта депозита до new_size; новые байты обнуляются,
+// недостающую до rent-exempt сумму доплачивает payer. Размер меняет только владелец
+fn process_realloc(program_id: &Pubkey, accounts: &[AccountInfo], new_size: u64) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let deposit_account = next_account_info(accounts_iter)?;
+    let payer_account = next_account_info(accounts_iter)?;
+    let system_program = next_system_program(accounts_iter)?;
+
+    if !payer_account.is_signer {
+        msg!("Missing required signature for payer account.");
+        return Err(ProgramError::MissingRequiredSignature);
     }
-}
 
-pub fn process_instruction(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    instruction_data: &[u8],
-) -> ProgramResult {
-    let instruction_type = match DepositInstruction::unpack(&instruction_data) {
-        Ok(value) => value,
-        Err(_) => Err(solana_program::program_error::ProgramError::InvalidInstructionData)?,
-    };
+    if deposit_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
 
-    match instruction_type {
-        DepositInstruction::ProcessCreateDeposit => process_create_deposit(program_id, accounts),
-        DepositInstruction::ProcessWithdraw { amount } => {
-            process_withdraw(program_id, accounts, amount)
-        }
-        DepositInstruction::ProcessDepositTranfer { amount } => {
-            process_deposit(program_id, accounts, amount)
-        }
-        DepositInstruction::ProcessBalance => process_balance(program_id, accounts),
+    let state = DepositState::load(deposit_account)?;
+    if state.needs_migration() {
+        msg!("Deposit account must be migrated before resizing.");
+        return Err(DepositError::UnsupportedVersion.into());
+    }
+    if *payer_account.key != state.owner {
+        msg!("Only the deposit owner can resize the deposit account.");
+        return Err(DepositError::Unauthorized.into());
+    }
+
+    let new_size = usize::try_from(new_size).map_err(|_| ProgramError::InvalidRealloc)?;
+    if new_size < DepositState::LEN {
+        msg!(
+            "New size {} is smaller than the deposit state size {}.",
+            new_size,
+            DepositState::LEN
+        );
+        return Err(ProgramError::InvalidRealloc);
     }
+
+    top_up_rent(deposit_account, payer_account, system_program, new_size)?;
+    deposit_account.realloc(new_size, true)?;
+
+    msg!(
+        "Deposit account {} resized to {} bytes",
+        deposit_account.key,
+        new_size
+    );
+    Ok(())
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use solana_program::hash::Hash;
-    use solana_program_test::*;
-    use solana_sdk::{
-        instruction::{AccountMeta, Instruction},
-        signature::{Keypair, Signer},
-        system_program,
-        transaction::Transaction,
-        transport::TransportError,
-    };
+// Переводит аккаунт депозита старой версии на текущий формат. Вызвать может кто угодно:
+// меняется только формат данных, rent доплачивает сам payer, балансы и владелец те же.
+// Исключение - старые депозиты без владельца: payer становится владельцем, только если
+// подписал и сам аккаунт депозита, иначе вывести средства после миграции будет некому
+fn process_migrate(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
 
-    async fn fund_account(
+    let deposit_account = next_account_info(accounts_iter)?;
+    let payer_account = next_account_info(accounts_iter)?;
+    let system_program = next_system_program(accounts_iter)?;
+
+    if !payer_account.is_signer {
+        msg!("Missing required signature for payer account.");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if deposit_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut state = DepositState::load(deposit_account)?;
+    if !state.needs_migration() {
+        msg!("Deposit account is already at version {}.", state.version);
+        return Ok(());
+    }
+
+    if state.owner == Pubkey::default() {
+        if !deposit_account.is_signer {
+            msg!("Deposit account without an owner must sign its migration.");
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        state.owner = *payer_account.key;
+    }
+
+    top_up_rent(
+        deposit_account,
+        payer_account,
+        system_program,
+        DepositState::LEN,
+    )?;
+    deposit_account.realloc(DepositState::LEN, false)?;
+
+    msg!(
+        "Migrating deposit account {} from version {} to {}",
+        deposit_account.key,
+        state.version,
+        CURRENT_VERSION
+    );
+    state.version = CURRENT_VERSION;
+    state.save(deposit_account)
+}
+
+// Депозит текущей версии, которым управляет подписавший аккаунт
+fn load_managed_state(
+    program_id: &Pubkey,
+    deposit_account: &AccountInfo,
+    signer_account: &AccountInfo,
+) -> Result<DepositState, ProgramError> {
+    if !signer_account.is_signer {
+        msg!("Missing required signature for signer account.");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if deposit_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let state = DepositState::load(deposit_account)?;
+    if state.needs_migration() {
+        msg!("Deposit account must be migrated first.");
+        return Err(DepositError::UnsupportedVersion.into());
+    }
+
+    Ok(state)
+}
+
+// authority назначает только владелец; Pubkey::default() снимает ее
+fn process_set_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    authority: Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let deposit_account = next_account_info(accounts_iter)?;
+    let owner_account = next_account_info(accounts_iter)?;
+
+    let mut state = load_managed_state(program_id, deposit_account, owner_account)?;
+    if *owner_account.key != state.owner {
+        msg!("Only the deposit owner can set the authority.");
+        return Err(DepositError::Unauthorized.into());
+    }
+
+    state.authority = authority;
+    state.save(deposit_account)?;
+
+    msg!(
+        "Deposit account {} authority set to {}",
+        deposit_account.key,
+        authority
+    );
+    Ok(())
+}
+
+// Белый список получателей вывода, подписывает владелец
+fn process_set_whitelist(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    destinations: [Pubkey; MAX_WHITELIST],
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let deposit_account = next_account_info(accounts_iter)?;
+    let owner_account = next_account_info(accounts_iter)?;
+
+    let mut state = load_managed_state(program_id, deposit_account, owner_account)?;
+    if *owner_account.key != state.owner {
+        msg!("Only the deposit owner can set the withdraw whitelist.");
+        return Err(DepositError::Unauthorized.into());
+    }
+
+    state.withdraw_whitelist = destinations;
+    state.save(deposit_account)?;
+
+    for destination in destinations.iter().filter(|key| **key != Pubkey::default()) {
+        msg!(
+            "Deposit account {} allows withdrawals to {}",
+            deposit_account.key,
+            destination
+        );
+    }
+    Ok(())
+}
+
+// Заморозка и разморозка, подписывает владелец или authority
+fn process_set_frozen(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    frozen: bool,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let deposit_account = next_account_info(accounts_iter)?;
+    let signer_account = next_account_info(accounts_iter)?;
+
+    let mut state = load_managed_state(program_id, deposit_account, signer_account)?;
+    if !state.can_freeze(signer_account.key) {
+        msg!(
+            "{} is neither the owner nor the authority of the deposit.",
+            signer_account.key
+        );
+        return Err(DepositError::Unauthorized.into());
+    }
+
+    state.frozen = frozen;
+    state.save(deposit_account)?;
+
+    msg!(
+        "Deposit account {} {} by {}",
+        deposit_account.key,
+        if frozen { "frozen" } else { "thawed" },
+        signer_account.key
+    );
+    Ok(())
+}
+
+// Закрытие депозита: все лампорты уходят на destination, данные обнуляются,
+// и runtime удаляет опустевший аккаунт в конце транзакции
+fn process_close(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let deposit_account = next_account_info(accounts_iter)?;
+    let owner_account = next_account_info(accounts_iter)?;
+    let destination_account = next_account_info(accounts_iter)?;
+
+    let state = load_managed_state(program_id, deposit_account, owner_account)?;
+    if *owner_account.key != state.owner {
+        msg!("Only the deposit owner can close the deposit.");
+        return Err(DepositError::Unauthorized.into());
+    }
+    if state.frozen {
+        msg!("Deposit account {} is frozen.", deposit_account.key);
+        return Err(DepositError::Frozen.into());
+    }
+
+    let lamports = deposit_account.lamports();
+    let destination_after = destination_account
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    **destination_account.try_borrow_mut_lamports()? = destination_after;
+    **deposit_account.try_borrow_mut_lamports()? = 0;
+    deposit_account.try_borrow_mut_data()?.fill(0);
+
+    msg!(
+        "EVENT close seq={} deposit={} destination={} amount={}",
+        state.seq,
+        deposit_account.key,
+        destination_account.key,
+        lamports
+    );
+    Ok(())
+}
+
+// Доплачиваем с payer недостающие до rent-exempt лампорты для размера size
+fn top_up_rent<'a>(
+    account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    size: usize,
+) -> ProgramResult {
+    let required_lamports = Rent::get()?.minimum_balance(size);
+    let current_lamports = account.lamports();
+
+    if current_lamports < required_lamports {
+        invoke(
+            &system_instruction::transfer(
+                payer.key,
+                account.key,
+                required_lamports - current_lamports,
+            ),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+
+    Ok(())
+}
+
+entrypoint!(process_instruction);
+
+// Параметры депозита, задаваемые при создании
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
+pub struct CreateDepositArgs {
+    pub fee_bps: u16,
+    pub treasury: Pubkey,
+    pub min_deposit: u64,
+    // Лимит выводов за окно из cap_window_slots слотов, 0 - без лимита
+    pub withdraw_cap: u64,
+    pub cap_window_slots: u64,
+    // Доля депозита для реферала, если он передан в ProcessDepositTranfer
+    pub referral_bps: u16,
+}
+
+impl CreateDepositArgs {
+    pub const LEN: usize = 2 + 32 + 8 + 8 + 8 + 2;
+    // Параметры без лимита выводов
+    pub const LEGACY_LEN: usize = 2 + 32 + 8;
+    // Параметры без реферальной доли
+    pub const NO_REFERRAL_LEN: usize = 2 + 32 + 8 + 8 + 8;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub enum DepositInstruction {
+    ProcessCreateDeposit {
+        args: CreateDepositArgs,
+    },
+    ProcessDepositTranfer {
+        amount: u64,
+        deadline_slot: Option<u64>,
+    },
+    ProcessWithdraw {
+        amount: u64,
+        state_hash: Option<[u8; 32]>,
+    },
+    ProcessBalance,
+    ProcessCreatePdaDeposit {
+        bump: u8,
+        args: CreateDepositArgs,
+    },
+    ProcessRealloc {
+        new_size: u64,
+    },
+    ProcessMigrate,
+    // Как ProcessBalance, но без проверки владельца аккаунта
+    ProcessBalanceLenient,
+    // Владелец назначает authority, которая может замораживать депозит
+    ProcessSetAuthority {
+        authority: Pubkey,
+    },
+    ProcessFreeze,
+    ProcessThaw,
+    // Владелец закрывает депозит и забирает все лампорты, включая rent
+    ProcessClose,
+    // Владелец задает адреса, на которые разрешен вывод; все Pubkey::default() - без ограничений
+    ProcessSetWhitelist {
+        destinations: [Pubkey; MAX_WHITELIST],
+    },
+    // Депозит как ProcessDepositTranfer без deadline_slot, назначение сохраняется в состоянии
+    ProcessTaggedDeposit {
+        amount: u64,
+        purpose: DepositPurpose,
+    },
+    // Возвращает состояние депозита через return data
+    ProcessGetState,
+}
+impl DepositInstruction {
+    // Обратная к unpack: байт варианта и поля в little-endian. Необязательные поля
+    // пишутся с байтом наличия, даже когда unpack принял бы короткую форму
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(1 + CreateDepositArgs::LEN + 1);
+        match self {
+            Self::ProcessCreateDeposit { args } => {
+                data.push(0);
+                pack_create_args(args, &mut data);
+            }
+            Self::ProcessDepositTranfer {
+                amount,
+                deadline_slot,
+            } => {
+                data.push(1);
+                data.extend_from_slice(&amount.to_le_bytes());
+                pack_option(deadline_slot.map(u64::to_le_bytes).as_ref(), &mut data);
+            }
+            Self::ProcessWithdraw { amount, state_hash } => {
+                data.push(2);
+                data.extend_from_slice(&amount.to_le_bytes());
+                pack_option(state_hash.as_ref(), &mut data);
+            }
+            Self::ProcessBalance => data.push(3),
+            Self::ProcessCreatePdaDeposit { bump, args } => {
+                data.extend_from_slice(&[4, *bump]);
+                pack_create_args(args, &mut data);
+            }
+            Self::ProcessRealloc { new_size } => {
+                data.push(5);
+                data.extend_from_slice(&new_size.to_le_bytes());
+            }
+            Self::ProcessMigrate => data.push(6),
+            Self::ProcessBalanceLenient => data.push(7),
+            Self::ProcessSetAuthority { authority } => {
+                data.push(8);
+                data.extend_from_slice(authority.as_ref());
+            }
+            Self::ProcessFreeze => data.push(9),
+            Self::ProcessThaw => data.push(10),
+            Self::ProcessClose => data.push(11),
+            Self::ProcessSetWhitelist { destinations } => {
+                data.push(12);
+                for destination in destinations {
+                    data.extend_from_slice(destination.as_ref());
+                }
+            }
+            Self::ProcessTaggedDeposit { amount, purpose } => {
+                data.push(13);
+                data.extend_from_slice(&amount.to_le_bytes());
+                data.push(*purpose as u8);
+            }
+            Self::ProcessGetState => data.push(14),
+        }
+        data
+    }
+
+    // Дискриминанты совпадают с порядком вариантов в Borsh-кодировке
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let (&variant, rest) = input
+            .split_first()
+            .ok_or(ProgramError::InvalidInstructionData)?;
+
+        match variant {
+            0 => Ok(Self::ProcessCreateDeposit {
+                args: unpack_create_args(rest)?,
+            }),
+            1 => {
+                // Данные без deadline_slot (только сумма) тоже принимаем
+                if rest.len() == 8 {
+                    let lamports = u64::from_le_bytes(
+                        rest.try_into()
+                            .map_err(|_| ProgramError::InvalidInstructionData)?,
+                    );
+                    return Ok(Self::ProcessDepositTranfer {
+                        amount: lamports,
+                        deadline_slot: None,
+                    });
+                }
+
+                let (amount, deadline_slot) = <(u64, Option<u64>)>::try_from_slice(rest)
+                    .map_err(|_| ProgramError::InvalidInstructionData)?;
+                Ok(Self::ProcessDepositTranfer {
+                    amount,
+                    deadline_slot,
+                })
+            }
+            2 => {
+                // Данные без state_hash (только сумма) тоже принимаем
+                if rest.len() == 8 {
+                    let lamports = u64::from_le_bytes(
+                        rest.try_into()
+                            .map_err(|_| ProgramError::InvalidInstructionData)?,
+                    );
+                    return Ok(Self::ProcessWithdraw {
+                        amount: lamports,
+                        state_hash: None,
+                    });
+                }
+
+                let (amount, state_hash) = <(u64, Option<[u8; 32]>)>::try_from_slice(rest)
+                    .map_err(|_| ProgramError::InvalidInstructionData)?;
+                Ok(Self::ProcessWithdraw { amount, state_hash })
+            }
+            3 => Ok(Self::ProcessBalance),
+            4 => {
+                let (&bump, rest) = rest
+                    .split_first()
+                    .ok_or(ProgramError::InvalidInstructionData)?;
+                Ok(Self::ProcessCreatePdaDeposit {
+                    bump,
+                    args: unpack_create_args(rest)?,
+                })
+            }
+            5 => {
+                let new_size = u64::from_le_bytes(
+                    rest.try_into()
+                        .map_err(|_| ProgramError::InvalidInstructionData)?,
+                );
+                Ok(Self::ProcessRealloc { new_size })
+            }
+            6 => Ok(Self::ProcessMigrate),
+            7 => Ok(Self::ProcessBalanceLenient),
+            8 => Ok(Self::ProcessSetAuthority {
+                authority: Pubkey::try_from_slice(rest)
+                    .map_err(|_| ProgramError::InvalidInstructionData)?,
+            }),
+            9 => Ok(Self::ProcessFreeze),
+            10 => Ok(Self::ProcessThaw),
+            11 => Ok(Self::ProcessClose),
+            12 => Ok(Self::ProcessSetWhitelist {
+                destinations: <[Pubkey; MAX_WHITELIST]>::try_from_slice(rest)
+                    .map_err(|_| ProgramError::InvalidInstructionData)?,
+            }),
+            13 => {
+                // Неизвестный байт назначения Borsh не разбирает
+                let (amount, purpose) = <(u64, DepositPurpose)>::try_from_slice(rest)
+                    .map_err(|_| ProgramError::InvalidInstructionData)?;
+                Ok(Self::ProcessTaggedDeposit { amount, purpose })
+            }
+            14 => Ok(Self::ProcessGetState),
+            _ => Err(ProgramError::InvalidInstructionData),
+        }
+    }
+}
+
+// Полная форма параметров, как в Borsh
+fn pack_create_args(args: &CreateDepositArgs, data: &mut Vec<u8>) {
+    data.extend_from_slice(&args.fee_bps.to_le_bytes());
+    data.extend_from_slice(args.treasury.as_ref());
+    data.extend_from_slice(&args.min_deposit.to_le_bytes());
+    data.extend_from_slice(&args.withdraw_cap.to_le_bytes());
+    data.extend_from_slice(&args.cap_window_slots.to_le_bytes());
+    data.extend_from_slice(&args.referral_bps.to_le_bytes());
+}
+
+fn pack_option<const N: usize>(value: Option<&[u8; N]>, data: &mut Vec<u8>) {
+    match value {
+        Some(bytes) => {
+            data.push(1);
+            data.extend_from_slice(bytes);
+        }
+        None => data.push(0),
+    }
+}
+
+// Параметры без лимита выводов или без реферальной доли тоже принимаем,
+// недостающее тогда выключено
+fn unpack_create_args(input: &[u8]) -> Result<CreateDepositArgs, ProgramError> {
+    if input.len() == CreateDepositArgs::LEGACY_LEN {
+        let (fee_bps, treasury, min_deposit) = <(u16, Pubkey, u64)>::try_from_slice(input)
+            .map_err(|_| ProgramError::InvalidInstructionData)?;
+        return Ok(CreateDepositArgs {
+            fee_bps,
+            treasury,
+            min_deposit,
+            ..CreateDepositArgs::default()
+        });
+    }
+
+    if input.len() == CreateDepositArgs::NO_REFERRAL_LEN {
+        let (fee_bps, treasury, min_deposit, withdraw_cap, cap_window_slots) =
+            <(u16, Pubkey, u64, u64, u64)>::try_from_slice(input)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+        return Ok(CreateDepositArgs {
+            fee_bps,
+            treasury,
+            min_deposit,
+            withdraw_cap,
+            cap_window_slots,
+            ..CreateDepositArgs::default()
+        });
+    }
+
+    CreateDepositArgs::try_from_slice(input).map_err(|_| ProgramError::InvalidInstructionData)
+}
+
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let instruction_type = match DepositInstruction::unpack(instruction_data) {
+        Ok(value) => value,
+        Err(_) => Err(solana_program::program_error::ProgramError::InvalidInstructionData)?,
+    };
+
+    match instruction_type {
+        DepositInstruction::ProcessCreateDeposit { args } => {
+            process_create_deposit(program_id, accounts, args)
+        }
+        DepositInstruction::ProcessWithdraw { amount, state_hash } => {
+            process_withdraw(program_id, accounts, amount, state_hash)
+        }
+        DepositInstruction::ProcessDepositTranfer {
+            amount,
+            deadline_slot,
+        } => process_deposit(program_id, accounts, amount, deadline_slot),
+        DepositInstruction::ProcessBalance => process_balance(program_id, accounts),
+        DepositInstruction::ProcessCreatePdaDeposit { bump, args } => {
+            process_create_pda_deposit(program_id, accounts, bump, args)
+        }
+        DepositInstruction::ProcessRealloc { new_size } => {
+            process_realloc(program_id, accounts, new_size)
+        }
+        DepositInstruction::ProcessMigrate => process_migrate(program_id, accounts),
+        DepositInstruction::ProcessBalanceLenient => process_balance_lenient(program_id, accounts),
+        DepositInstruction::ProcessSetAuthority { authority } => {
+            process_set_authority(program_id, accounts, authority)
+        }
+        DepositInstruction::ProcessFreeze => process_set_frozen(program_id, accounts, true),
+        DepositInstruction::ProcessThaw => process_set_frozen(program_id, accounts, false),
+        DepositInstruction::ProcessClose => process_close(program_id, accounts),
+        DepositInstruction::ProcessSetWhitelist { destinations } => {
+            process_set_whitelist(program_id, accounts, destinations)
+        }
+        DepositInstruction::ProcessTaggedDeposit { amount, purpose } => {
+            process_tagged_deposit(program_id, accounts, amount, purpose)
+        }
+        DepositInstruction::ProcessGetState => process_get_state(program_id, accounts),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lamports::Lamports;
+    use solana_program::hash::Hash;
+    use solana_program_test::*;
+    use solana_sdk::{
+        account::Account,
+        instruction::{AccountMeta, Instruction, InstructionError},
+        signature::{Keypair, Signer},
+        system_program,
+        transaction::{Transaction, TransactionError},
+        transport::TransportError,
+    };
+
+    // Под processor! программа выполняется нативно, и compute units считаются только
+    // у CPI, а не у логики самой программы. Поэтому границы проверяют, сколько вызовов
+    // System Program делает инструкция (один стоит около 150 CU), а не ее стоимость
+    const CREATE_CPI_CU_BUDGET: u64 = 300;
+    const DEPOSIT_CPI_CU_BUDGET: u64 = 200;
+    const WITHDRAW_CPI_CU_BUDGET: u64 = 100;
+    const BALANCE_CPI_CU_BUDGET: u64 = 100;
+
+    async fn fund_account(
         banks_client: &mut BanksClient,
         payer: &Keypair,
         recipient: &Pubkey,
@@ -256,7 +1267,9 @@ mod test {
 
         let instruction = Instruction::new_with_borsh(
             program_id,
-            &DepositInstruction::ProcessCreateDeposit,
+            &DepositInstruction::ProcessCreateDeposit {
+                args: CreateDepositArgs::default(),
+            },
             vec![
                 AccountMeta::new(deposit_account.pubkey(), true),
                 AccountMeta::new(payer.pubkey(), true),
@@ -279,6 +1292,104 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_create_rejects_fake_system_program() -> Result<(), TransportError> {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "deposit_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+        let deposit_account = Keypair::new();
+
+        let mut instruction = crate::instruction::create_deposit(
+            &program_id,
+            &deposit_account.pubkey(),
+            &payer.pubkey(),
+            CreateDepositArgs::default(),
+        )
+        .unwrap()
+        .instruction;
+        instruction.accounts[2] = AccountMeta::new_readonly(Pubkey::new_unique(), false);
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&payer.pubkey()),
+            &[&payer, &deposit_account],
+            recent_blockhash,
+        );
+
+        let err = banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(0, InstructionError::IncorrectProgramId)
+        );
+        assert!(banks_client
+            .get_account(deposit_account.pubkey())
+            .await?
+            .is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_rejects_payer_without_rent() -> Result<(), TransportError> {
+        let program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new(
+            "deposit_program",
+            program_id,
+            processor!(process_instruction),
+        );
+        // Комиссию платит payer, а rent должен внести пользователь с почти пустым кошельком
+        let user = Keypair::new();
+        program_test.add_account(
+            user.pubkey(),
+            Account {
+                lamports: 1_000,
+                ..Account::default()
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+        let deposit_account = Keypair::new();
+
+        let instruction = crate::instruction::create_deposit(
+            &program_id,
+            &deposit_account.pubkey(),
+            &user.pubkey(),
+            CreateDepositArgs::default(),
+        )
+        .unwrap()
+        .instruction;
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&payer.pubkey()),
+            &[&payer, &user, &deposit_account],
+            recent_blockhash,
+        );
+
+        let err = banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(DepositError::InsufficientFundsForRent as u32)
+            )
+        );
+        assert!(banks_client
+            .get_account(deposit_account.pubkey())
+            .await?
+            .is_none());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_deposit_and_balance() -> Result<(), TransportError> {
         let program_id = Pubkey::new_unique();
@@ -295,7 +1406,9 @@ mod test {
 
         let create_instruction = Instruction::new_with_borsh(
             program_id,
-            &DepositInstruction::ProcessCreateDeposit,
+            &DepositInstruction::ProcessCreateDeposit {
+                args: CreateDepositArgs::default(),
+            },
             vec![
                 AccountMeta::new(deposit_account.pubkey(), true),
                 AccountMeta::new(payer.pubkey(), true),
@@ -312,25 +1425,34 @@ mod test {
             program_id,
             &DepositInstruction::ProcessDepositTranfer {
                 amount: deposit_amount,
+                deadline_slot: None,
             },
             vec![
                 AccountMeta::new(deposit_account.pubkey(), false),
                 AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
             ],
         );
         let mut deposit_transaction =
             Transaction::new_with_payer(&[deposit_instruction], Some(&payer.pubkey()));
-        deposit_transaction.sign(&[&deposit_account, &payer], recent_blockhash);
-        banks_client
-            .process_transaction(deposit_transaction)
-            .await?;
+        deposit_transaction.sign(&[&payer], recent_blockhash);
+        process_and_assert_cu(
+            &mut banks_client,
+            deposit_transaction,
+            DEPOSIT_CPI_CU_BUDGET,
+        )
+        .await?;
 
         let deposit_account_data = banks_client
             .get_account(deposit_account.pubkey())
             .await?
             .expect("Deposit account should exist");
 
-        assert_eq!(deposit_account_data.lamports, 890880);
+        let rent = banks_client
+            .get_rent()
+            .await?
+            .minimum_balance(DepositState::LEN);
+        assert_eq!(deposit_account_data.lamports, rent + deposit_amount);
         Ok(())
     }
 
@@ -351,7 +1473,9 @@ mod test {
 
         let create_instruction = Instruction::new_with_borsh(
             program_id,
-            &DepositInstruction::ProcessCreateDeposit,
+            &DepositInstruction::ProcessCreateDeposit {
+                args: CreateDepositArgs::default(),
+            },
             vec![
                 AccountMeta::new(deposit_account.pubkey(), true),
                 AccountMeta::new(payer.pubkey(), true),
@@ -374,24 +1498,30 @@ mod test {
             program_id,
             &DepositInstruction::ProcessDepositTranfer {
                 amount: deposit_amount,
+                deadline_slot: None,
             },
             vec![
                 AccountMeta::new(deposit_account.pubkey(), false),
                 AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
             ],
         );
 
         let mut deposit_transaction =
             Transaction::new_with_payer(&[deposit_instruction], Some(&payer.pubkey()));
-        deposit_transaction.sign(&[&deposit_account, &payer], recent_blockhash);
-        banks_client
-            .process_transaction(deposit_transaction)
-            .await?;
+        deposit_transaction.sign(&[&payer], recent_blockhash);
+        process_and_assert_cu(
+            &mut banks_client,
+            deposit_transaction,
+            DEPOSIT_CPI_CU_BUDGET,
+        )
+        .await?;
 
         let withdraw_instruction = Instruction::new_with_borsh(
             program_id,
             &DepositInstruction::ProcessWithdraw {
                 amount: withdraw_amount,
+                state_hash: None,
             },
             vec![
                 AccountMeta::new(deposit_account.pubkey(), false),
@@ -402,19 +1532,1873 @@ mod test {
         let mut withdraw_transaction =
             Transaction::new_with_payer(&[withdraw_instruction], Some(&payer.pubkey()));
         withdraw_transaction.sign(&[&payer], recent_blockhash);
-        banks_client
-            .process_transaction(withdraw_transaction)
-            .await?;
+        process_and_assert_cu(
+            &mut banks_client,
+            withdraw_transaction,
+            WITHDRAW_CPI_CU_BUDGET,
+        )
+        .await?;
 
         let deposit_account_data = banks_client
             .get_account(deposit_account.pubkey())
             .await?
             .expect("Deposit account should exist");
 
+        let rent = banks_client
+            .get_rent()
+            .await?
+            .minimum_balance(DepositState::LEN);
         assert_eq!(
             deposit_account_data.lamports,
-            deposit_amount - withdraw_amount
+            rent + deposit_amount - withdraw_amount
+        );
+
+        Ok(())
+    }
+
+    struct FundedDeposit {
+        banks_client: BanksClient,
+        payer: Keypair,
+        recent_blockhash: Hash,
+        program_id: Pubkey,
+        deposit_account: Keypair,
+        user: Keypair,
+    }
+
+    // Создает депозит с заданными параметрами и пополняет его с отдельного кошелька user
+    async fn setup_funded_deposit(
+        args: CreateDepositArgs,
+        deposit_amount: u64,
+    ) -> Result<FundedDeposit, TransportError> {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "deposit_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        let deposit_account = Keypair::new();
+        let user = Keypair::new();
+
+        fund_account(
+            &mut banks_client,
+            &payer,
+            &user.pubkey(),
+            10_000_000,
+            &recent_blockhash,
+        )
+        .await?;
+
+        let create_instruction = Instruction::new_with_borsh(
+            program_id,
+            &DepositInstruction::ProcessCreateDeposit { args },
+            vec![
+                AccountMeta::new(deposit_account.pubkey(), true),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        );
+        let deposit_instruction = Instruction::new_with_borsh(
+            program_id,
+            &DepositInstruction::ProcessDepositTranfer {
+                amount: deposit_amount,
+                deadline_slot: None,
+            },
+            vec![
+                AccountMeta::new(deposit_account.pubkey(), false),
+                AccountMeta::new(user.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        );
+
+        let mut setup_transaction = Transaction::new_with_payer(
+            &[create_instruction, deposit_instruction],
+            Some(&payer.pubkey()),
+        );
+        setup_transaction.sign(&[&payer, &deposit_account, &user], recent_blockhash);
+        banks_client.process_transaction(setup_transaction).await?;
+
+        Ok(FundedDeposit {
+            banks_client,
+            payer,
+            recent_blockhash,
+            program_id,
+            deposit_account,
+            user,
+        })
+    }
+
+    // Compute units из метаданных симуляции; транзакция при этом не выполняется
+    async fn simulated_compute_units(
+        banks_client: &mut BanksClient,
+        transaction: &Transaction,
+    ) -> Result<u64, BanksClientError> {
+        let simulation = banks_client
+            .simulate_transaction(transaction.clone())
+            .await?;
+        if let Some(Err(err)) = simulation.result {
+            return Err(err.into());
+        }
+
+        Ok(simulation
+            .simulation_details
+            .expect("Simulation details should be returned")
+            .units_consumed)
+    }
+
+    // Проверяет по симуляции, что транзакция уложилась в max_cu, затем выполняет ее
+    async fn process_and_assert_cu(
+        banks_client: &mut BanksClient,
+        transaction: Transaction,
+        max_cu: u64,
+    ) -> Result<(), BanksClientError> {
+        let consumed = simulated_compute_units(banks_client, &transaction).await?;
+        assert!(
+            consumed <= max_cu,
+            "Transaction consumed {} CU, budget is {}",
+            consumed,
+            max_cu
+        );
+
+        banks_client.process_transaction(transaction).await
+    }
+
+    fn withdraw_instruction(
+        setup: &FundedDeposit,
+        amount: u64,
+        treasury: Option<Pubkey>,
+    ) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta::new(setup.deposit_account.pubkey(), false),
+            AccountMeta::new(setup.user.pubkey(), true),
+        ];
+        accounts.extend(treasury.map(|treasury| AccountMeta::new(treasury, false)));
+        accounts.push(AccountMeta::new_readonly(setup.payer.pubkey(), true));
+
+        Instruction::new_with_borsh(
+            setup.program_id,
+            &DepositInstruction::ProcessWithdraw {
+                amount,
+                state_hash: None,
+            },
+            accounts,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_non_owner_cannot_withdraw_or_resize() -> Result<(), TransportError> {
+        let mut setup = setup_funded_deposit(CreateDepositArgs::default(), 1_000_000).await?;
+        let deposit = setup.deposit_account.pubkey();
+        let balance_before = setup.banks_client.get_balance(deposit).await?;
+
+        // user пополнял депозит, но владелец - payer; белый список пуст
+        let attempts = [
+            instruction::withdraw(
+                &setup.program_id,
+                &deposit,
+                &setup.user.pubkey(),
+                None,
+                Lamports(100_000),
+                None,
+            )
+            .unwrap()
+            .instruction,
+            instruction::withdraw_for_owner(
+                &setup.program_id,
+                &deposit,
+                &setup.user.pubkey(),
+                &setup.user.pubkey(),
+                None,
+                Lamports(100_000),
+                None,
+            )
+            .unwrap()
+            .instruction,
+            instruction::realloc(
+                &setup.program_id,
+                &deposit,
+                &setup.user.pubkey(),
+                DepositState::LEN as u64 + 100,
+            )
+            .unwrap()
+            .instruction,
+        ];
+        for instruction in attempts {
+            let mut transaction =
+                Transaction::new_with_payer(&[instruction], Some(&setup.payer.pubkey()));
+            transaction.sign(&[&setup.payer, &setup.user], setup.recent_blockhash);
+            let err = setup
+                .banks_client
+                .process_transaction(transaction)
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err.unwrap(),
+                TransactionError::InstructionError(
+                    0,
+                    InstructionError::Custom(DepositError::Unauthorized as u32)
+                )
+            );
+        }
+
+        let account = setup.banks_client.get_account(deposit).await?.unwrap();
+        assert_eq!(account.lamports, balance_before);
+        assert_eq!(account.data.len(), DepositState::LEN);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_conserves_lamports() -> Result<(), TransportError> {
+        let withdraw_amount = 400_000;
+        let mut setup = setup_funded_deposit(CreateDepositArgs::default(), 1_000_000).await?;
+        let deposit_pubkey = setup.deposit_account.pubkey();
+        let user_pubkey = setup.user.pubkey();
+
+        let deposit_before = setup.banks_client.get_balance(deposit_pubkey).await?;
+        let user_before = setup.banks_client.get_balance(user_pubkey).await?;
+
+        // Комиссию платит payer, поэтому сумма депозита и пользователя не должна меняться
+        let mut withdraw_transaction = Transaction::new_with_payer(
+            &[withdraw_instruction(&setup, withdraw_amount, None)],
+            Some(&setup.payer.pubkey()),
+        );
+        withdraw_transaction.sign(&[&setup.payer, &setup.user], setup.recent_blockhash);
+        setup
+            .banks_client
+            .process_transaction(withdraw_transaction)
+            .await?;
+
+        let deposit_after = setup.banks_client.get_balance(deposit_pubkey).await?;
+        let user_after = setup.banks_client.get_balance(user_pubkey).await?;
+
+        assert_eq!(deposit_before + user_before, deposit_after + user_after);
+        assert_eq!(deposit_before - deposit_after, withdraw_amount);
+        assert_eq!(user_after - user_before, withdraw_amount);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_routes_fee_to_treasury() -> Result<(), TransportError> {
+        let treasury = Pubkey::new_unique();
+        let withdraw_amount = 400_000;
+        let mut setup = setup_funded_deposit(
+            CreateDepositArgs {
+                fee_bps: 250,
+                treasury,
+                ..CreateDepositArgs::default()
+            },
+            1_000_000,
+        )
+        .await?;
+
+        let (payer, recent_blockhash) = (setup.payer.insecure_clone(), setup.recent_blockhash);
+        fund_account(
+            &mut setup.banks_client,
+            &payer,
+            &treasury,
+            1_000_000,
+            &recent_blockhash,
+        )
+        .await?;
+
+        let user_before = setup.banks_client.get_balance(setup.user.pubkey()).await?;
+        let treasury_before = setup.banks_client.get_balance(treasury).await?;
+
+        let mut withdraw_transaction = Transaction::new_with_payer(
+            &[withdraw_instruction(
+                &setup,
+                withdraw_amount,
+                Some(treasury),
+            )],
+            Some(&payer.pubkey()),
+        );
+        withdraw_transaction.sign(&[&payer, &setup.user], recent_blockhash);
+        setup
+            .banks_client
+            .process_transaction(withdraw_transaction)
+            .await?;
+
+        let user_after = setup.banks_client.get_balance(setup.user.pubkey()).await?;
+        let treasury_after = setup.banks_client.get_balance(treasury).await?;
+
+        // 2.5% от 400_000
+        assert_eq!(treasury_after - treasury_before, 10_000);
+        assert_eq!(user_after - user_before, 390_000);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_requires_treasury_when_fee_set() -> Result<(), TransportError> {
+        let mut setup = setup_funded_deposit(
+            CreateDepositArgs {
+                fee_bps: 250,
+                treasury: Pubkey::new_unique(),
+                ..CreateDepositArgs::default()
+            },
+            1_000_000,
+        )
+        .await?;
+
+        let mut missing_treasury = Transaction::new_with_payer(
+            &[instruction::withdraw(
+                &setup.program_id,
+                &setup.deposit_account.pubkey(),
+                &setup.user.pubkey(),
+                None,
+                Lamports(400_000),
+                None,
+            )
+            .unwrap()
+            .instruction],
+            Some(&setup.payer.pubkey()),
+        );
+        missing_treasury.sign(&[&setup.payer, &setup.user], setup.recent_blockhash);
+        let err = setup
+            .banks_client
+            .process_transaction(missing_treasury)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)
+        );
+
+        let mut wrong_treasury = Transaction::new_with_payer(
+            &[withdraw_instruction(
+                &setup,
+                400_000,
+                Some(Pubkey::new_unique()),
+            )],
+            Some(&setup.payer.pubkey()),
+        );
+        wrong_treasury.sign(&[&setup.payer, &setup.user], setup.recent_blockhash);
+        let err = setup
+            .banks_client
+            .process_transaction(wrong_treasury)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(DepositError::InvalidTreasury as u32)
+            )
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_with_separate_payer_records_owner() -> Result<(), TransportError> {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "deposit_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+        let deposit_account = Keypair::new();
+        let owner = Keypair::new();
+        let create_instruction = instruction::create_deposit_for_owner(
+            &program_id,
+            &deposit_account.pubkey(),
+            &payer.pubkey(),
+            &owner.pubkey(),
+            CreateDepositArgs::default(),
+        )
+        .unwrap()
+        .instruction;
+
+        let payer_before = banks_client.get_balance(payer.pubkey()).await?;
+        let mut transaction = Transaction::new_with_payer(
+            std::slice::from_ref(&create_instruction),
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &deposit_account, &owner], recent_blockhash);
+        let fee = banks_client
+            .get_fee_for_message(transaction.message().clone())
+            .await?
+            .expect("Blockhash is valid");
+        banks_client.process_transaction(transaction).await?;
+
+        let rent = banks_client
+            .get_rent()
+            .await?
+            .minimum_balance(DepositState::LEN);
+        assert_eq!(
+            banks_client.get_balance(payer.pubkey()).await?,
+            payer_before - rent - fee
+        );
+        assert_eq!(banks_client.get_balance(owner.pubkey()).await?, 0);
+
+        let mut unsigned_owner = create_instruction;
+        unsigned_owner.accounts[3].is_signer = false;
+        let mut transaction = Transaction::new_with_payer(&[unsigned_owner], Some(&payer.pubkey()));
+        transaction.sign(&[&payer, &deposit_account], recent_blockhash);
+        let err = banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)
+        );
+
+        let account = banks_client
+            .get_account(deposit_account.pubkey())
+            .await?
+            .expect("Deposit account should exist");
+        assert_eq!(
+            DepositState::unpack(&account.data).unwrap().owner,
+            owner.pubkey()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_deposit_deadline_slot_is_enforced() -> Result<(), TransportError> {
+        let program_id = Pubkey::new_unique();
+        let mut context = ProgramTest::new(
+            "deposit_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start_with_context()
+        .await;
+        context.warp_to_slot(100).unwrap();
+
+        let deposit_account = Keypair::new();
+        let payer = context.payer.insecure_clone();
+        let recent_blockhash = context.banks_client.get_latest_blockhash().await?;
+        let mut create_transaction = Transaction::new_with_payer(
+            &[instruction::create_deposit(
+                &program_id,
+                &deposit_account.pubkey(),
+                &payer.pubkey(),
+                CreateDepositArgs::default(),
+            )
+            .unwrap()
+            .instruction],
+            Some(&payer.pubkey()),
+        );
+        create_transaction.sign(&[&payer, &deposit_account], recent_blockhash);
+        context
+            .banks_client
+            .process_transaction(create_transaction)
+            .await?;
+
+        let deposit_with_deadline = |deadline_slot| {
+            let mut transaction = Transaction::new_with_payer(
+                &[instruction::deposit(
+                    &program_id,
+                    &deposit_account.pubkey(),
+                    &payer.pubkey(),
+                    None,
+                    Lamports(10_000),
+                    Some(deadline_slot),
+                )
+                .unwrap()
+                .instruction],
+                Some(&payer.pubkey()),
+            );
+            transaction.sign(&[&payer], recent_blockhash);
+            transaction
+        };
+
+        let err = context
+            .banks_client
+            .process_transaction(deposit_with_deadline(50))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(DepositError::DeadlineExceeded as u32)
+            )
+        );
+
+        let balance_before = context
+            .banks_client
+            .get_balance(deposit_account.pubkey())
+            .await?;
+        context
+            .banks_client
+            .process_transaction(deposit_with_deadline(200))
+            .await?;
+        assert_eq!(
+            context
+                .banks_client
+                .get_balance(deposit_account.pubkey())
+                .await?,
+            balance_before + 10_000
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_deposit_below_minimum_is_rejected() -> Result<(), TransportError> {
+        let args = CreateDepositArgs {
+            min_deposit: 10_000,
+            ..CreateDepositArgs::default()
+        };
+        let mut setup = setup_funded_deposit(args, 10_000).await?;
+        let deposit_pubkey = setup.deposit_account.pubkey();
+        let deposit_before = setup.banks_client.get_balance(deposit_pubkey).await?;
+
+        let mut below_minimum = Transaction::new_with_payer(
+            &[instruction::deposit(
+                &setup.program_id,
+                &deposit_pubkey,
+                &setup.user.pubkey(),
+                None,
+                Lamports(5_000),
+                None,
+            )
+            .unwrap()
+            .instruction],
+            Some(&setup.payer.pubkey()),
+        );
+        below_minimum.sign(&[&setup.payer, &setup.user], setup.recent_blockhash);
+        let err = setup
+            .banks_client
+            .process_transaction(below_minimum)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(DepositError::BelowMinimum as u32)
+            )
+        );
+
+        let rent = setup
+            .banks_client
+            .get_rent()
+            .await?
+            .minimum_balance(DepositState::LEN);
+        assert_eq!(deposit_before, rent + 10_000);
+        assert_eq!(
+            setup.banks_client.get_balance(deposit_pubkey).await?,
+            deposit_before
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_realloc_grows_account_and_tops_up_rent() -> Result<(), TransportError> {
+        let mut setup = setup_funded_deposit(CreateDepositArgs::default(), 0).await?;
+        let deposit_pubkey = setup.deposit_account.pubkey();
+        let new_size = DepositState::LEN + 100;
+
+        let mut transaction = Transaction::new_with_payer(
+            &[instruction::realloc(
+                &setup.program_id,
+                &deposit_pubkey,
+                &setup.payer.pubkey(),
+                new_size as u64,
+            )
+            .unwrap()
+            .instruction],
+            Some(&setup.payer.pubkey()),
+        );
+        transaction.sign(&[&setup.payer], setup.recent_blockhash);
+        setup.banks_client.process_transaction(transaction).await?;
+
+        let account = setup
+            .banks_client
+            .get_account(deposit_pubkey)
+            .await?
+            .expect("Deposit account should exist");
+        let rent = setup.banks_client.get_rent().await?;
+
+        assert_eq!(account.data.len(), new_size);
+        assert!(account.data[DepositState::LEN..]
+            .iter()
+            .all(|&byte| byte == 0));
+        assert_eq!(account.lamports, rent.minimum_balance(new_size));
+        assert_eq!(
+            DepositState::deserialize(&mut &account.data[..]).unwrap(),
+            DepositState {
+                owner: setup.payer.pubkey(),
+                ..DepositState::default()
+            }
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_realloc_rejects_shrinking_below_state() -> Result<(), TransportError> {
+        let mut setup = setup_funded_deposit(CreateDepositArgs::default(), 0).await?;
+
+        let mut transaction = Transaction::new_with_payer(
+            &[instruction::realloc(
+                &setup.program_id,
+                &setup.deposit_account.pubkey(),
+                &setup.payer.pubkey(),
+                DepositState::LEN as u64 - 1,
+            )
+            .unwrap()
+            .instruction],
+            Some(&setup.payer.pubkey()),
+        );
+        transaction.sign(&[&setup.payer], setup.recent_blockhash);
+        let err = setup
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidRealloc)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_migrate_upgrades_v0_account_in_place() -> Result<(), TransportError> {
+        let program_id = Pubkey::new_unique();
+        let deposit = Keypair::new();
+        let deposit_pubkey = deposit.pubkey();
+        let treasury = Pubkey::new_unique();
+
+        let mut v0_data = Vec::new();
+        v0_data.extend_from_slice(&250u16.to_le_bytes());
+        v0_data.extend_from_slice(treasury.as_ref());
+        v0_data.extend_from_slice(&10_000u64.to_le_bytes());
+
+        let mut program_test = ProgramTest::new(
+            "deposit_program",
+            program_id,
+            processor!(process_instruction),
+        );
+        program_test.add_account(
+            deposit_pubkey,
+            Account {
+                lamports: Rent::default().minimum_balance(DepositState::V0_LEN),
+                data: v0_data,
+                owner: program_id,
+                ..Account::default()
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+        let migrate = instruction::migrate(&program_id, &deposit_pubkey, &payer.pubkey())
+            .unwrap()
+            .instruction;
+
+        // У депозита версии 0 нет владельца, и без подписи самого депозита его не присвоить
+        let mut transaction =
+            Transaction::new_with_payer(std::slice::from_ref(&migrate), Some(&payer.pubkey()));
+        transaction.sign(&[&payer], recent_blockhash);
+        let err = banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)
+        );
+
+        let mut signed_migrate = migrate;
+        signed_migrate.accounts[0].is_signer = true;
+        let mut transaction = Transaction::new_with_payer(&[signed_migrate], Some(&payer.pubkey()));
+        transaction.sign(&[&payer, &deposit], recent_blockhash);
+        banks_client.process_transaction(transaction).await?;
+
+        let account = banks_client
+            .get_account(deposit_pubkey)
+            .await?
+            .expect("Deposit account should exist");
+        let rent = banks_client.get_rent().await?;
+
+        assert_eq!(account.data.len(), DepositState::LEN);
+        assert_eq!(account.lamports, rent.minimum_balance(DepositState::LEN));
+        assert_eq!(
+            DepositState::unpack(&account.data).unwrap(),
+            DepositState {
+                version: CURRENT_VERSION,
+                fee_bps: 250,
+                treasury,
+                min_deposit: 10_000,
+                owner: payer.pubkey(),
+                ..DepositState::default()
+            }
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lenient_balance_reports_system_account() -> Result<(), TransportError> {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "deposit_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+        let wallet = Pubkey::new_unique();
+        fund_account(
+            &mut banks_client,
+            &payer,
+            &wallet,
+            5_000_000,
+            &recent_blockhash,
+        )
+        .await?;
+
+        let strict = Transaction::new_signed_with_payer(
+            &[crate::instruction::balance(&program_id, &wallet)
+                .unwrap()
+                .instruction],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        );
+        let err = banks_client.process_transaction(strict).await.unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(0, InstructionError::IncorrectProgramId)
+        );
+
+        let lenient = Transaction::new_signed_with_payer(
+            &[crate::instruction::balance_lenient(&program_id, &wallet)
+                .unwrap()
+                .instruction],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        );
+        let result = banks_client
+            .process_transaction_with_metadata(lenient)
+            .await?;
+        assert!(result.result.is_ok());
+
+        let logs = result.metadata.expect("Metadata is recorded").log_messages;
+        let warning = format!(
+            "Program log: Warning: account {} is owned by {}, not by the program.",
+            wallet,
+            system_program::id()
+        );
+        let balance = format!(
+            "Program log: Deposit account {} has balance: 5000000 lamports",
+            wallet
+        );
+        assert!(logs.contains(&warning), "{:?}", logs);
+        assert!(logs.contains(&balance), "{:?}", logs);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_cap_applies_over_slot_window() -> Result<(), TransportError> {
+        let program_id = Pubkey::new_unique();
+        let mut context = ProgramTest::new(
+            "deposit_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start_with_context()
+        .await;
+        context.warp_to_slot(10).unwrap();
+
+        let deposit_account = Keypair::new();
+        let payer = context.payer.insecure_clone();
+        let recent_blockhash = context.banks_client.get_latest_blockhash().await?;
+        let args = CreateDepositArgs {
+            withdraw_cap: 300_000,
+            cap_window_slots: 100,
+            ..CreateDepositArgs::default()
+        };
+        let mut setup_transaction = Transaction::new_with_payer(
+            &[
+                instruction::create_deposit(
+                    &program_id,
+                    &deposit_account.pubkey(),
+                    &payer.pubkey(),
+                    args,
+                )
+                .unwrap()
+                .instruction,
+                instruction::deposit(
+                    &program_id,
+                    &deposit_account.pubkey(),
+                    &payer.pubkey(),
+                    None,
+                    Lamports(1_000_000),
+                    None,
+                )
+                .unwrap()
+                .instruction,
+            ],
+            Some(&payer.pubkey()),
+        );
+        setup_transaction.sign(&[&payer, &deposit_account], recent_blockhash);
+        context
+            .banks_client
+            .process_transaction(setup_transaction)
+            .await?;
+
+        let withdraw = |amount: u64, recent_blockhash| {
+            let mut transaction = Transaction::new_with_payer(
+                &[instruction::withdraw(
+                    &program_id,
+                    &deposit_account.pubkey(),
+                    &payer.pubkey(),
+                    None,
+                    Lamports(amount),
+                    None,
+                )
+                .unwrap()
+                .instruction],
+                Some(&payer.pubkey()),
+            );
+            transaction.sign(&[&payer], recent_blockhash);
+            transaction
+        };
+
+        // До лимита включительно
+        for amount in [200_000, 100_000] {
+            context
+                .banks_client
+                .process_transaction(withdraw(amount, recent_blockhash))
+                .await?;
+        }
+
+        let err = context
+            .banks_client
+            .process_transaction(withdraw(1, recent_blockhash))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(DepositError::DailyLimitExceeded as u32)
+            )
+        );
+
+        // Выводы из прошлого окна больше не учитываются
+        context.warp_to_slot(120).unwrap();
+        let recent_blockhash = context.banks_client.get_latest_blockhash().await?;
+        context
+            .banks_client
+            .process_transaction(withdraw(200_000, recent_blockhash))
+            .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_authority_freezes_deposit_until_thawed() -> Result<(), TransportError> {
+        let mut setup = setup_funded_deposit(CreateDepositArgs::default(), 1_000_000).await?;
+        let (program_id, deposit) = (setup.program_id, setup.deposit_account.pubkey());
+        let (owner, recent_blockhash) = (setup.payer.insecure_clone(), setup.recent_blockhash);
+        let authority = Keypair::new();
+        let stranger = Keypair::new();
+
+        let signed = |instruction: Instruction, signer: &Keypair| {
+            let mut transaction =
+                Transaction::new_with_payer(&[instruction], Some(&owner.pubkey()));
+            transaction.sign(&[&owner, signer], recent_blockhash);
+            transaction
+        };
+        let withdraw = |amount: u64| {
+            signed(
+                instruction::withdraw(
+                    &program_id,
+                    &deposit,
+                    &owner.pubkey(),
+                    None,
+                    Lamports(amount),
+                    None,
+                )
+                .unwrap()
+                .instruction,
+                &owner,
+            )
+        };
+
+        setup
+            .banks_client
+            .process_transaction(signed(
+                instruction::set_authority(
+                    &program_id,
+                    &deposit,
+                    &owner.pubkey(),
+                    &authority.pubkey(),
+                )
+                .unwrap()
+                .instruction,
+                &owner,
+            ))
+            .await?;
+
+        // Посторонний подписант не может заморозить депозит
+        let err = setup
+            .banks_client
+            .process_transaction(signed(
+                instruction::freeze(&program_id, &deposit, &stranger.pubkey())
+                    .unwrap()
+                    .instruction,
+                &stranger,
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(DepositError::Unauthorized as u32)
+            )
+        );
+
+        setup
+            .banks_client
+            .process_transaction(signed(
+                instruction::freeze(&program_id, &deposit, &authority.pubkey())
+                    .unwrap()
+                    .instruction,
+                &authority,
+            ))
+            .await?;
+
+        let err = setup
+            .banks_client
+            .process_transaction(withdraw(100_000))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(DepositError::Frozen as u32)
+            )
+        );
+
+        setup
+            .banks_client
+            .process_transaction(signed(
+                instruction::thaw(&program_id, &deposit, &authority.pubkey())
+                    .unwrap()
+                    .instruction,
+                &authority,
+            ))
+            .await?;
+        setup
+            .banks_client
+            .process_transaction(withdraw(110_000))
+            .await?;
+
+        let account = setup
+            .banks_client
+            .get_account(deposit)
+            .await?
+            .expect("Deposit account should exist");
+        let state = DepositState::unpack(&account.data).unwrap();
+        assert_eq!(state.authority, authority.pubkey());
+        assert!(!state.frozen);
+        assert_eq!(state.seq, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_whitelist_rejects_other_destinations() -> Result<(), TransportError> {
+        let mut setup = setup_funded_deposit(CreateDepositArgs::default(), 1_000_000).await?;
+        let (program_id, deposit) = (setup.program_id, setup.deposit_account.pubkey());
+        let owner = setup.payer.insecure_clone();
+
+        let mut whitelist_transaction = Transaction::new_with_payer(
+            &[instruction::set_whitelist(
+                &program_id,
+                &deposit,
+                &owner.pubkey(),
+                &[setup.user.pubkey()],
+            )
+            .unwrap()
+            .instruction],
+            Some(&owner.pubkey()),
+        );
+        whitelist_transaction.sign(&[&owner], setup.recent_blockhash);
+        setup
+            .banks_client
+            .process_transaction(whitelist_transaction)
+            .await?;
+
+        // Сам владелец не в списке, поэтому вывести себе не может
+        let mut disallowed = Transaction::new_with_payer(
+            &[instruction::withdraw(
+                &program_id,
+                &deposit,
+                &owner.pubkey(),
+                None,
+                Lamports(100_000),
+                None,
+            )
+            .unwrap()
+            .instruction],
+            Some(&owner.pubkey()),
+        );
+        disallowed.sign(&[&owner], setup.recent_blockhash);
+        let err = setup
+            .banks_client
+            .process_transaction(disallowed)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(DepositError::DestinationNotAllowed as u32)
+            )
+        );
+
+        let user_before = setup.banks_client.get_balance(setup.user.pubkey()).await?;
+        let mut allowed = Transaction::new_with_payer(
+            &[withdraw_instruction(&setup, 100_000, None)],
+            Some(&owner.pubkey()),
+        );
+        allowed.sign(&[&owner, &setup.user], setup.recent_blockhash);
+        setup.banks_client.process_transaction(allowed).await?;
+
+        let user_after = setup.banks_client.get_balance(setup.user.pubkey()).await?;
+        assert_eq!(user_after - user_before, 100_000);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tagged_deposit_purpose_is_returned_by_get_state() -> Result<(), TransportError> {
+        let mut setup = setup_funded_deposit(CreateDepositArgs::default(), 1_000_000).await?;
+        let (program_id, deposit) = (setup.program_id, setup.deposit_account.pubkey());
+
+        for (index, purpose) in DepositPurpose::ALL.into_iter().enumerate() {
+            // Разные суммы, чтобы транзакции не совпадали по подписи
+            let amount = 10_000 + index as u64;
+            let mut transaction = Transaction::new_with_payer(
+                &[
+                    instruction::tagged_deposit(
+                        &program_id,
+                        &deposit,
+                        &setup.user.pubkey(),
+                        Lamports(amount),
+                        purpose,
+                    )
+                    .unwrap()
+                    .instruction,
+                    instruction::get_state(&program_id, &deposit)
+                        .unwrap()
+                        .instruction,
+                ],
+                Some(&setup.payer.pubkey()),
+            );
+            transaction.sign(&[&setup.payer, &setup.user], setup.recent_blockhash);
+            let result = setup
+                .banks_client
+                .process_transaction_with_metadata(transaction)
+                .await?;
+            assert!(result.result.is_ok());
+
+            let return_data = result
+                .metadata
+                .expect("Metadata is recorded")
+                .return_data
+                .expect("Get state sets return data");
+            assert_eq!(return_data.program_id, program_id);
+            let state = DepositState::try_from_slice(&return_data.data).unwrap();
+            assert_eq!(state.last_purpose, Some(purpose));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_returns_remaining_balance() -> Result<(), TransportError> {
+        let mut setup = setup_funded_deposit(CreateDepositArgs::default(), 1_000_000).await?;
+        let deposit_before = setup
+            .banks_client
+            .get_balance(setup.deposit_account.pubkey())
+            .await?;
+
+        let mut transaction = Transaction::new_with_payer(
+            &[withdraw_instruction(&setup, 300_000, None)],
+            Some(&setup.payer.pubkey()),
+        );
+        transaction.sign(&[&setup.payer, &setup.user], setup.recent_blockhash);
+        let result = setup
+            .banks_client
+            .process_transaction_with_metadata(transaction)
+            .await?;
+        assert!(result.result.is_ok());
+
+        let return_data = result
+            .metadata
+            .expect("Metadata is recorded")
+            .return_data
+            .expect("Withdraw sets return data");
+        assert_eq!(return_data.program_id, setup.program_id);
+        let remaining = u64::from_le_bytes(return_data.data.try_into().unwrap());
+        assert_eq!(remaining, deposit_before - 300_000);
+        assert_eq!(
+            setup
+                .banks_client
+                .get_balance(setup.deposit_account.pubkey())
+                .await?,
+            remaining
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_increments_sequence() -> Result<(), TransportError> {
+        let mut setup = setup_funded_deposit(CreateDepositArgs::default(), 1_000_000).await?;
+        let deposit_pubkey = setup.deposit_account.pubkey();
+
+        for (expected_seq, amount) in [(1, 100_000), (2, 110_000), (3, 120_000)] {
+            let mut transaction = Transaction::new_with_payer(
+                &[withdraw_instruction(&setup, amount, None)],
+                Some(&setup.payer.pubkey()),
+            );
+            transaction.sign(&[&setup.payer, &setup.user], setup.recent_blockhash);
+            let result = setup
+                .banks_client
+                .process_transaction_with_metadata(transaction)
+                .await?;
+            assert!(result.result.is_ok());
+
+            let logs = result.metadata.expect("Metadata is recorded").log_messages;
+            let event = format!(
+                "EVENT withdraw seq={} deposit={}",
+                expected_seq, deposit_pubkey
+            );
+            assert!(
+                logs.iter().any(|line| line.contains(&event)),
+                "no {} in {:?}",
+                event,
+                logs
+            );
+
+            let account = setup
+                .banks_client
+                .get_account(deposit_pubkey)
+                .await?
+                .expect("Deposit account should exist");
+            assert_eq!(
+                DepositState::unpack(&account.data).unwrap().seq,
+                expected_seq
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_rejects_stale_state_hash() -> Result<(), TransportError> {
+        let mut setup = setup_funded_deposit(CreateDepositArgs::default(), 1_000_000).await?;
+        let deposit_pubkey = setup.deposit_account.pubkey();
+
+        let stale_hash = DepositState {
+            min_deposit: 1,
+            ..DepositState::default()
+        }
+        .hash();
+        let mut stale_withdraw = Transaction::new_with_payer(
+            &[instruction::withdraw_for_owner(
+                &setup.program_id,
+                &deposit_pubkey,
+                &setup.payer.pubkey(),
+                &setup.user.pubkey(),
+                None,
+                Lamports(100_000),
+                Some(stale_hash),
+            )
+            .unwrap()
+            .instruction],
+            Some(&setup.payer.pubkey()),
+        );
+        stale_withdraw.sign(&[&setup.payer, &setup.user], setup.recent_blockhash);
+        let err = setup
+            .banks_client
+            .process_transaction(stale_withdraw)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(DepositError::StaleState as u32)
+            )
+        );
+
+        let account = setup
+            .banks_client
+            .get_account(deposit_pubkey)
+            .await?
+            .expect("Deposit account should exist");
+        let fresh_hash = deposit_state_hash(&account.data).unwrap();
+        let mut fresh_withdraw = Transaction::new_with_payer(
+            &[instruction::withdraw_for_owner(
+                &setup.program_id,
+                &deposit_pubkey,
+                &setup.payer.pubkey(),
+                &setup.user.pubkey(),
+                None,
+                Lamports(100_000),
+                Some(fresh_hash),
+            )
+            .unwrap()
+            .instruction],
+            Some(&setup.payer.pubkey()),
+        );
+        fresh_withdraw.sign(&[&setup.payer, &setup.user], setup.recent_blockhash);
+        setup
+            .banks_client
+            .process_transaction(fresh_withdraw)
+            .await?;
+
+        assert_eq!(
+            setup.banks_client.get_balance(deposit_pubkey).await?,
+            account.lamports - 100_000
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cpi_compute_units_within_budget() -> Result<(), TransportError> {
+        let mut setup = setup_funded_deposit(CreateDepositArgs::default(), 1_000_000).await?;
+        let program_id = setup.program_id;
+        let deposit_pubkey = setup.deposit_account.pubkey();
+        let user_pubkey = setup.user.pubkey();
+        let new_deposit = Keypair::new();
+
+        let cases = [
+            (
+                instruction::create_deposit(
+                    &program_id,
+                    &new_deposit.pubkey(),
+                    &user_pubkey,
+                    CreateDepositArgs::default(),
+                )
+                .unwrap()
+                .instruction,
+                vec![&setup.payer, &setup.user, &new_deposit],
+                CREATE_CPI_CU_BUDGET,
+            ),
+            (
+                instruction::deposit(
+                    &program_id,
+                    &deposit_pubkey,
+                    &user_pubkey,
+                    None,
+                    Lamports(100_000),
+                    None,
+                )
+                .unwrap()
+                .instruction,
+                vec![&setup.payer, &setup.user],
+                DEPOSIT_CPI_CU_BUDGET,
+            ),
+            (
+                instruction::withdraw_for_owner(
+                    &program_id,
+                    &deposit_pubkey,
+                    &setup.payer.pubkey(),
+                    &user_pubkey,
+                    None,
+                    Lamports(100_000),
+                    None,
+                )
+                .unwrap()
+                .instruction,
+                vec![&setup.payer, &setup.user],
+                WITHDRAW_CPI_CU_BUDGET,
+            ),
+            (
+                instruction::balance(&program_id, &deposit_pubkey)
+                    .unwrap()
+                    .instruction,
+                vec![&setup.payer],
+                BALANCE_CPI_CU_BUDGET,
+            ),
+        ];
+
+        for (instruction, signers, budget) in cases {
+            let mut transaction =
+                Transaction::new_with_payer(&[instruction], Some(&setup.payer.pubkey()));
+            transaction.sign(&signers, setup.recent_blockhash);
+
+            process_and_assert_cu(&mut setup.banks_client, transaction, budget).await?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_inverts_pack_for_every_variant() {
+        let amounts = [0, 1, u64::MAX - 1, u64::MAX];
+        let args = |value: u64| CreateDepositArgs {
+            fee_bps: value as u16,
+            treasury: Pubkey::new_unique(),
+            min_deposit: value,
+            withdraw_cap: value,
+            cap_window_slots: value,
+            referral_bps: u16::MAX,
+        };
+
+        let mut instructions = vec![
+            DepositInstruction::ProcessBalance,
+            DepositInstruction::ProcessMigrate,
+            DepositInstruction::ProcessBalanceLenient,
+            DepositInstruction::ProcessSetAuthority {
+                authority: Pubkey::new_unique(),
+            },
+            DepositInstruction::ProcessFreeze,
+            DepositInstruction::ProcessThaw,
+            DepositInstruction::ProcessClose,
+            DepositInstruction::ProcessSetWhitelist {
+                destinations: [Pubkey::new_unique(); MAX_WHITELIST],
+            },
+            DepositInstruction::ProcessSetWhitelist {
+                destinations: [Pubkey::default(); MAX_WHITELIST],
+            },
+            DepositInstruction::ProcessGetState,
+        ];
+        for amount in amounts {
+            for optional in [None, Some(0), Some(u64::MAX)] {
+                instructions.push(DepositInstruction::ProcessDepositTranfer {
+                    amount,
+                    deadline_slot: optional,
+                });
+            }
+            for state_hash in [None, Some([0; 32]), Some([u8::MAX; 32])] {
+                instructions.push(DepositInstruction::ProcessWithdraw { amount, state_hash });
+            }
+            instructions.push(DepositInstruction::ProcessCreateDeposit { args: args(amount) });
+            instructions.push(DepositInstruction::ProcessCreatePdaDeposit {
+                bump: amount as u8,
+                args: args(amount),
+            });
+            instructions.push(DepositInstruction::ProcessRealloc { new_size: amount });
+            for purpose in DepositPurpose::ALL {
+                instructions.push(DepositInstruction::ProcessTaggedDeposit { amount, purpose });
+            }
+        }
+
+        for instruction in instructions {
+            let data = instruction.pack();
+            assert_eq!(data, borsh::to_vec(&instruction).unwrap());
+            assert_eq!(
+                DepositInstruction::unpack(&data).unwrap(),
+                instruction,
+                "{:?}",
+                data
+            );
+        }
+    }
+
+    // Байты собираются вручную, а не через pack: тест фиксирует формат, а не симметрию
+    fn bytes(parts: &[&[u8]]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    #[test]
+    fn test_unpack_pins_wire_format_of_every_branch() {
+        let key = Pubkey::new_from_array([7; 32]);
+        let legacy_args: &[u8] =
+            &bytes(&[&25u16.to_le_bytes(), key.as_ref(), &100u64.to_le_bytes()]);
+        let capped_args: &[u8] =
+            &bytes(&[legacy_args, &500u64.to_le_bytes(), &10u64.to_le_bytes()]);
+        let full_args: &[u8] = &bytes(&[capped_args, &30u16.to_le_bytes()]);
+        let args = |withdraw_cap, cap_window_slots, referral_bps| CreateDepositArgs {
+            fee_bps: 25,
+            treasury: key,
+            min_deposit: 100,
+            withdraw_cap,
+            cap_window_slots,
+            referral_bps,
+        };
+
+        let cases = [
+            (
+                bytes(&[&[0], full_args]),
+                DepositInstruction::ProcessCreateDeposit {
+                    args: args(500, 10, 30),
+                },
+            ),
+            (
+                bytes(&[&[0], capped_args]),
+                DepositInstruction::ProcessCreateDeposit {
+                    args: args(500, 10, 0),
+                },
+            ),
+            (
+                bytes(&[&[0], legacy_args]),
+                DepositInstruction::ProcessCreateDeposit {
+                    args: args(0, 0, 0),
+                },
+            ),
+            (
+                bytes(&[&[1], &42u64.to_le_bytes()]),
+                DepositInstruction::ProcessDepositTranfer {
+                    amount: 42,
+                    deadline_slot: None,
+                },
+            ),
+            (
+                bytes(&[&[1], &42u64.to_le_bytes(), &[1], &9u64.to_le_bytes()]),
+                DepositInstruction::ProcessDepositTranfer {
+                    amount: 42,
+                    deadline_slot: Some(9),
+                },
+            ),
+            (
+                bytes(&[&[2], &42u64.to_le_bytes()]),
+                DepositInstruction::ProcessWithdraw {
+                    amount: 42,
+                    state_hash: None,
+                },
+            ),
+            (
+                bytes(&[&[2], &42u64.to_le_bytes(), &[1], &[3; 32]]),
+                DepositInstruction::ProcessWithdraw {
+                    amount: 42,
+                    state_hash: Some([3; 32]),
+                },
+            ),
+            (vec![3], DepositInstruction::ProcessBalance),
+            (
+                bytes(&[&[4, 254], full_args]),
+                DepositInstruction::ProcessCreatePdaDeposit {
+                    bump: 254,
+                    args: args(500, 10, 30),
+                },
+            ),
+            (
+                bytes(&[&[5], &512u64.to_le_bytes()]),
+                DepositInstruction::ProcessRealloc { new_size: 512 },
+            ),
+            (vec![6], DepositInstruction::ProcessMigrate),
+            (vec![7], DepositInstruction::ProcessBalanceLenient),
+            (
+                bytes(&[&[8], key.as_ref()]),
+                DepositInstruction::ProcessSetAuthority { authority: key },
+            ),
+            (vec![9], DepositInstruction::ProcessFreeze),
+            (vec![10], DepositInstruction::ProcessThaw),
+            (vec![11], DepositInstruction::ProcessClose),
+            (
+                bytes(&[&[12], &[7; 32 * MAX_WHITELIST]]),
+                DepositInstruction::ProcessSetWhitelist {
+                    destinations: [key; MAX_WHITELIST],
+                },
+            ),
+            (
+                bytes(&[&[13], &42u64.to_le_bytes(), &[0]]),
+                DepositInstruction::ProcessTaggedDeposit {
+                    amount: 42,
+                    purpose: DepositPurpose::Savings,
+                },
+            ),
+            (
+                bytes(&[&[13], &42u64.to_le_bytes(), &[3]]),
+                DepositInstruction::ProcessTaggedDeposit {
+                    amount: 42,
+                    purpose: DepositPurpose::Fee,
+                },
+            ),
+            (vec![14], DepositInstruction::ProcessGetState),
+        ];
+
+        for (data, expected) in cases {
+            assert_eq!(
+                DepositInstruction::unpack(&data),
+                Ok(expected),
+                "{:?}",
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn test_unpack_rejects_malformed_input() {
+        let short_amount = 42u64.to_le_bytes()[..7].to_vec();
+        let malformed: Vec<Vec<u8>> = vec![
+            // Пустые данные и неизвестный вариант
+            vec![],
+            vec![15],
+            vec![u8::MAX],
+            // Сумма короче 8 байт
+            bytes(&[&[1], &short_amount]),
+            bytes(&[&[2], &short_amount]),
+            vec![1],
+            vec![2],
+            // Байт наличия без значения и неизвестный байт наличия
+            bytes(&[&[1], &42u64.to_le_bytes(), &[1]]),
+            bytes(&[&[2], &42u64.to_le_bytes(), &[1], &[0; 31]]),
+            bytes(&[&[1], &42u64.to_le_bytes(), &[2], &9u64.to_le_bytes()]),
+            // Параметры депозита не совпадают ни с одной из известных длин
+            bytes(&[&[0], &[0; CreateDepositArgs::LEGACY_LEN - 1]]),
+            bytes(&[&[0], &[0; CreateDepositArgs::LEN + 1]]),
+            vec![4],
+            bytes(&[&[4, 255], &[0; CreateDepositArgs::LEGACY_LEN - 1]]),
+            // Поля фиксированной длины короче или длиннее нужного
+            bytes(&[&[5], &[0; 7]]),
+            bytes(&[&[5], &[0; 9]]),
+            bytes(&[&[8], &[0; 31]]),
+            bytes(&[&[8], &[0; 33]]),
+            bytes(&[&[12], &[0; 32 * MAX_WHITELIST - 1]]),
+            // Назначение отсутствует или не входит в DepositPurpose
+            bytes(&[&[13], &42u64.to_le_bytes()]),
+            bytes(&[&[13], &42u64.to_le_bytes(), &[4]]),
+            bytes(&[&[13], &42u64.to_le_bytes(), &[u8::MAX]]),
+        ];
+
+        for data in malformed {
+            assert_eq!(
+                DepositInstruction::unpack(&data),
+                Err(ProgramError::InvalidInstructionData),
+                "{:?}",
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn test_withdraw_invariant_violation() {
+        assert!(check_withdraw_invariant(&[1_000, 500, 0], &[600, 900, 0], 400).is_ok());
+        assert!(check_withdraw_invariant(&[1_000, 500, 0], &[600, 890, 10], 400).is_ok());
+        assert_eq!(
+            check_withdraw_invariant(&[1_000, 500, 0], &[600, 800, 0], 400),
+            Err(DepositError::InvariantViolation)
+        );
+        assert_eq!(
+            check_withdraw_invariant(&[1_000, 500, 0], &[700, 800, 0], 400),
+            Err(DepositError::InvariantViolation)
+        );
+    }
+
+    fn create_pda_instruction(program_id: Pubkey, user: &Keypair, bump: u8) -> Instruction {
+        let (deposit_address, _) = find_deposit_address(&program_id, &user.pubkey());
+
+        Instruction::new_with_borsh(
+            program_id,
+            &DepositInstruction::ProcessCreatePdaDeposit {
+                bump,
+                args: CreateDepositArgs::default(),
+            },
+            vec![
+                AccountMeta::new(deposit_address, false),
+                AccountMeta::new(user.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_create_pda_deposit_with_bump() -> Result<(), TransportError> {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "deposit_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        let (deposit_address, bump) = find_deposit_address(&program_id, &payer.pubkey());
+
+        let mut transaction = Transaction::new_with_payer(
+            &[create_pda_instruction(program_id, &payer, bump)],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer], recent_blockhash);
+        banks_client.process_transaction(transaction).await?;
+
+        let deposit_account_data = banks_client
+            .get_account(deposit_address)
+            .await?
+            .expect("Deposit PDA should exist");
+
+        assert_eq!(deposit_account_data.owner, program_id);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_pda_deposit_rejects_wrong_bump() -> Result<(), TransportError> {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "deposit_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        let (deposit_address, bump) = find_deposit_address(&program_id, &payer.pubkey());
+
+        let mut transaction = Transaction::new_with_payer(
+            &[create_pda_instruction(
+                program_id,
+                &payer,
+                bump.wrapping_sub(1),
+            )],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer], recent_blockhash);
+        let err = banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidSeeds)
+        );
+        assert!(banks_client.get_account(deposit_address).await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_deposit_credits_referrer_share() -> Result<(), TransportError> {
+        let args = CreateDepositArgs {
+            referral_bps: 1_000,
+            ..CreateDepositArgs::default()
+        };
+        let mut setup = setup_funded_deposit(args, 1_000_000).await?;
+        let (program_id, deposit) = (setup.program_id, setup.deposit_account.pubkey());
+        let referrer = Pubkey::new_unique();
+        fund_account(
+            &mut setup.banks_client,
+            &setup.payer,
+            &referrer,
+            1_000_000,
+            &setup.recent_blockhash,
+        )
+        .await?;
+        let deposit_before = setup.banks_client.get_balance(deposit).await?;
+
+        let deposit_with = |referrer: &Pubkey| {
+            Transaction::new_signed_with_payer(
+                &[instruction::deposit(
+                    &program_id,
+                    &deposit,
+                    &setup.user.pubkey(),
+                    Some(referrer),
+                    Lamports(5_000_000),
+                    None,
+                )
+                .unwrap()
+                .instruction],
+                Some(&setup.payer.pubkey()),
+                &[&setup.payer, &setup.user],
+                setup.recent_blockhash,
+            )
+        };
+
+        // Реферал не может совпадать с плательщиком депозита
+        let err = setup
+            .banks_client
+            .process_transaction(deposit_with(&setup.user.pubkey()))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(DepositError::InvalidReferrer as u32)
+            )
+        );
+
+        setup
+            .banks_client
+            .process_transaction(deposit_with(&referrer))
+            .await?;
+
+        assert_eq!(setup.banks_client.get_balance(referrer).await?, 1_500_000);
+        assert_eq!(
+            setup.banks_client.get_balance(deposit).await?,
+            deposit_before + 4_500_000
+        );
+        let account = setup.banks_client.get_account(deposit).await?.unwrap();
+        assert_eq!(
+            DepositState::unpack(&account.data)
+                .unwrap()
+                .referral_payouts,
+            500_000
+        );
+
+        Ok(())
+    }
+
+    // Единый сквозной сценарий create -> deposit -> balance -> withdraw -> close
+    // через клиентские билдеры, с проверкой балансов, состояния и событий на каждом шаге
+    #[tokio::test]
+    async fn test_full_instruction_pipeline() -> Result<(), TransportError> {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, owner, recent_blockhash) = ProgramTest::new(
+            "deposit_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+        let deposit_account = Keypair::new();
+        let deposit = deposit_account.pubkey();
+        let (user, destination) = (Keypair::new(), Pubkey::new_unique());
+        let rent = banks_client
+            .get_rent()
+            .await?
+            .minimum_balance(DepositState::LEN);
+        let (deposit_amount, withdraw_amount) = (3_000_000, 1_000_000);
+
+        let signed = |instruction: Instruction, signers: &[&Keypair]| {
+            Transaction::new_signed_with_payer(
+                &[instruction],
+                Some(&owner.pubkey()),
+                signers,
+                recent_blockhash,
+            )
+        };
+        let logs = |result: BanksTransactionResultWithMetadata| {
+            assert!(result.result.is_ok(), "{:?}", result.result);
+            result.metadata.expect("Metadata is recorded").log_messages
+        };
+
+        // create: аккаунт принадлежит программе, оплачен ровно rent, владелец - плательщик
+        banks_client
+            .process_transaction(signed(
+                instruction::create_deposit(
+                    &program_id,
+                    &deposit,
+                    &owner.pubkey(),
+                    CreateDepositArgs::default(),
+                )
+                .unwrap()
+                .instruction,
+                &[&owner, &deposit_account],
+            ))
+            .await?;
+        let account = banks_client.get_account(deposit).await?.unwrap();
+        assert_eq!(account.owner, program_id);
+        assert_eq!(account.lamports, rent);
+        let state = DepositState::unpack(&account.data).unwrap();
+        assert_eq!(state.owner, owner.pubkey());
+        assert_eq!((state.version, state.seq), (CURRENT_VERSION, 0));
+
+        // deposit: сумма пришла на депозит целиком, без скрытых списаний
+        banks_client
+            .process_transaction(signed(
+                instruction::deposit(
+                    &program_id,
+                    &deposit,
+                    &owner.pubkey(),
+                    None,
+                    Lamports(deposit_amount),
+                    None,
+                )
+                .unwrap()
+                .instruction,
+                &[&owner],
+            ))
+            .await?;
+        assert_eq!(
+            banks_client.get_balance(deposit).await?,
+            rent + deposit_amount
+        );
+
+        // balance: программа сообщает тот же баланс, что видит банк
+        let balance_logs = logs(
+            banks_client
+                .process_transaction_with_metadata(signed(
+                    instruction::balance(&program_id, &deposit)
+                        .unwrap()
+                        .instruction,
+                    &[&owner],
+                ))
+                .await?,
+        );
+        let expected = format!("has balance: {} lamports", rent + deposit_amount);
+        assert!(balance_logs.iter().any(|line| line.contains(&expected)));
+
+        // withdraw: user получает сумму без комиссии, seq растет, событие и return data
+        // отражают именно этот вывод
+        let result = banks_client
+            .process_transaction_with_metadata(signed(
+                instruction::withdraw_for_owner(
+                    &program_id,
+                    &deposit,
+                    &owner.pubkey(),
+                    &user.pubkey(),
+                    None,
+                    Lamports(withdraw_amount),
+                    None,
+                )
+                .unwrap()
+                .instruction,
+                &[&owner, &user],
+            ))
+            .await?;
+        let return_data = result
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.return_data.clone())
+            .expect("Withdraw sets return data");
+        let remaining = rent + deposit_amount - withdraw_amount;
+        assert_eq!(return_data.data, remaining.to_le_bytes());
+        let withdraw_event = format!(
+            "EVENT withdraw seq=1 deposit={} user={} amount={} fee=0",
+            deposit,
+            user.pubkey(),
+            withdraw_amount
+        );
+        assert!(logs(result)
+            .iter()
+            .any(|line| line.contains(&withdraw_event)));
+        assert_eq!(
+            banks_client.get_balance(user.pubkey()).await?,
+            withdraw_amount
+        );
+        assert_eq!(banks_client.get_balance(deposit).await?, remaining);
+        let account = banks_client.get_account(deposit).await?.unwrap();
+        assert_eq!(DepositState::unpack(&account.data).unwrap().seq, 1);
+
+        // close: остаток вместе с rent уходит на destination, аккаунт удаляется
+        let close_logs = logs(
+            banks_client
+                .process_transaction_with_metadata(signed(
+                    instruction::close(&program_id, &deposit, &owner.pubkey(), &destination)
+                        .unwrap()
+                        .instruction,
+                    &[&owner],
+                ))
+                .await?,
+        );
+        let close_event = format!(
+            "EVENT close seq=1 deposit={} destination={} amount={}",
+            deposit, destination, remaining
         );
+        assert!(close_logs.iter().any(|line| line.contains(&close_event)));
+        assert_eq!(banks_client.get_balance(destination).await?, remaining);
+        assert!(banks_client.get_account(deposit).await?.is_none());
 
         Ok(())
     }
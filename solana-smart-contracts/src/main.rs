@@ -1,41 +1,874 @@
-use solana_client::rpc_client::RpcClient;
-use solana_program::instruction::Instruction;
-use solana_sdk::instruction::AccountMeta;
-use solana_sdk::message::Message;
+use serde::Deserialize;
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    rpc_client::RpcClient,
+    rpc_config::RpcSendTransactionConfig,
+    rpc_request::{RpcError, RpcResponseErrorData},
+};
 use solana_sdk::signer::Signer;
-use solana_sdk::{pubkey::Pubkey, signature::Keypair, transaction::Transaction};
-use std::str::FromStr;
+use solana_sdk::{
+    account::Account,
+    bpf_loader, bpf_loader_deprecated, bpf_loader_upgradeable,
+    commitment_config::{CommitmentConfig, CommitmentLevel},
+    hash::Hash,
+    instruction::InstructionError,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signature},
+    transaction::{Transaction, TransactionError},
+};
+use solana_smart_contracts::{
+    find_deposit_address, instruction, lamports::Lamports, state::DepositState, CreateDepositArgs,
+};
+use std::{collections::HashMap, env, fs, process::ExitCode, str::FromStr, thread, time::Duration};
+use tracing::{error, info, warn};
 
-fn main() {
-    // Указываем адрес контракта
-    let program_id = Pubkey::from_str("YOUR_PROGRAM_ID_HERE").unwrap();
+// Один модуль ссылок на эксплорер на все крейты, лежит в solana-rpc-transactions
+#[path = "../../solana-rpc-transactions/src/explorer.rs"]
+mod explorer;
+#[path = "../../solana-rpc-transactions/src/logging.rs"]
+mod logging;
+#[cfg(test)]
+#[path = "../../solana-rpc-transactions/src/mock.rs"]
+mod mock;
+mod rent;
+mod snapshot;
 
-    // Указываем адрес кошелька
-    let payer = Keypair::new();
-    let client = RpcClient::new("https://api.devnet.solana.com");
+use explorer::{explorer_url, Cluster};
+use logging::{init_logging, LogFormat};
+use rent::RentCache;
+use snapshot::assert_deposit_state;
 
-    // Создаем инструкцию для депозита (0 - депозита, 1 - вывода)
-    let lamports: u64 = 1000000; // Пример: 1 SOL = 1,000,000 лампортов
-    let instruction_data = [0, (lamports & 0xFF) as u8]; // Тип операции и сумма
+static CONFIG_PATH: &str = "config.yaml";
+static BLOCKHASH_ATTEMPTS: usize = 4;
+static BLOCKHASH_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+static SEND_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
 
-    let accounts = vec![
-        AccountMeta::new(payer.try_pubkey().expect("Failed to resolve pubkey"), true),
-        AccountMeta::new(program_id, false),
+// Запрашиваем blockhash повторно, удваивая паузу после каждой неудачи
+fn get_latest_blockhash_with_retry(
+    client: &RpcClient,
+    attempts: usize,
+    initial_backoff: Duration,
+) -> Result<Hash, Box<dyn std::error::Error + Send + Sync>> {
+    let mut backoff = initial_backoff;
+
+    for attempt in 1..=attempts {
+        match client.get_latest_blockhash() {
+            Ok(blockhash) => return Ok(blockhash),
+            Err(err) if attempt < attempts => {
+                warn!(
+                    "Attempt {}/{} to fetch blockhash failed: {}, retry in {:?}",
+                    attempt, attempts, err, backoff
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Err("No blockhash fetch attempts were made".into())
+}
+
+// Программа развернута, если ее аккаунт исполняемый и принадлежит BPF-загрузчику
+fn check_program_account(program_id: &Pubkey, account: Option<&Account>) -> Result<(), String> {
+    let Some(account) = account else {
+        return Err(format!("Program account {} does not exist", program_id));
+    };
+
+    if !account.executable {
+        return Err(format!("Account {} is not executable", program_id));
+    }
+
+    let loaders = [
+        bpf_loader::id(),
+        bpf_loader_deprecated::id(),
+        bpf_loader_upgradeable::id(),
     ];
+    if !loaders.contains(&account.owner) {
+        return Err(format!(
+            "Account {} is owned by {}, not by a BPF loader",
+            program_id, account.owner
+        ));
+    }
+
+    Ok(())
+}
+
+fn verify_program_deployed(client: &RpcClient, program_id: &Pubkey) -> Result<(), String> {
+    let account = client
+        .get_account_with_commitment(program_id, client.commitment())
+        .map_err(|err| format!("Failed to fetch program account {}: {}", program_id, err))?
+        .value;
+
+    check_program_account(program_id, account.as_ref())
+}
+
+// Создание депозита и первый взнос одной транзакцией: выполнятся либо обе инструкции,
+// либо ни одной; подписывают payer и новый аккаунт депозита
+fn create_and_deposit_transaction(
+    program_id: &Pubkey,
+    payer: &Keypair,
+    deposit: &Keypair,
+    args: CreateDepositArgs,
+    amount: Lamports,
+    recent_blockhash: Hash,
+) -> Result<Transaction, Box<dyn std::error::Error + Send + Sync>> {
+    let instructions = [
+        instruction::create_deposit(program_id, &deposit.pubkey(), &payer.pubkey(), args)?,
+        instruction::deposit(
+            program_id,
+            &deposit.pubkey(),
+            &payer.pubkey(),
+            None,
+            amount,
+            None,
+        )?,
+    ];
+    let signers: HashMap<Pubkey, &dyn Signer> = HashMap::from([
+        (payer.pubkey(), payer as &dyn Signer),
+        (deposit.pubkey(), deposit as &dyn Signer),
+    ]);
+
+    Ok(instruction::signed_transaction(
+        &instructions,
+        &payer.pubkey(),
+        &signers,
+        recent_blockhash,
+    )?)
+}
+
+// Конфиг в том виде, в каком он лежит в YAML
+#[derive(Deserialize)]
+struct ConfigFile {
+    program_id: String,
+    rpc_url: String,
+    // Путь к keypair плательщика в формате solana-keygen
+    keypair_path: String,
+    #[serde(default)]
+    log_format: LogFormat,
+    // Настройки отправки читаем строками, чтобы ошибка называла поле и значение
+    #[serde(default)]
+    commitment: Option<String>,
+    #[serde(default)]
+    skip_preflight: Option<String>,
+    #[serde(default)]
+    send_attempts: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Config {
+    program_id: Pubkey,
+    rpc_url: String,
+    keypair_path: String,
+    log_format: LogFormat,
+    send_settings: SendSettings,
+}
+
+// Адрес программы проверяется сразу, чтобы ошибка указывала на конфиг, а не на отправку
+fn parse_config(content: &str) -> Result<Config, String> {
+    let file: ConfigFile = serde_yaml::from_str(content).map_err(|err| err.to_string())?;
+    let program_id = Pubkey::from_str(&file.program_id).map_err(|err| {
+        format!(
+            "Invalid program_id {:?}: {}, expected a base58 program address",
+            file.program_id, err
+        )
+    })?;
+    let send_settings = SendSettings::from_config(&file)?;
+
+    Ok(Config {
+        program_id,
+        rpc_url: file.rpc_url,
+        keypair_path: file.keypair_path,
+        log_format: file.log_format,
+        send_settings,
+    })
+}
+
+fn load_config(path: &str) -> Result<Config, String> {
+    let content =
+        fs::read_to_string(path).map_err(|err| format!("Failed to read {}: {}", path, err))?;
+    parse_config(&content).map_err(|err| format!("Failed to parse {}: {}", path, err))
+}
+
+// Как клиент отправляет транзакции; задается полями commitment, skip_preflight
+// и send_attempts в config.yaml
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SendSettings {
+    commitment: CommitmentLevel,
+    skip_preflight: bool,
+    // Сколько раз отправлять транзакцию при сетевых сбоях, включая первую попытку
+    max_attempts: usize,
+}
+
+impl Default for SendSettings {
+    fn default() -> Self {
+        SendSettings {
+            commitment: CommitmentLevel::Confirmed,
+            skip_preflight: false,
+            max_attempts: 3,
+        }
+    }
+}
+
+impl SendSettings {
+    fn from_config(file: &ConfigFile) -> Result<Self, String> {
+        let mut settings = SendSettings::default();
+
+        if let Some(value) = &file.commitment {
+            settings.commitment = CommitmentLevel::from_str(value).map_err(|_| {
+                format!(
+                    "Invalid commitment {:?}, expected processed, confirmed or finalized",
+                    value
+                )
+            })?;
+        }
+        if let Some(value) = &file.skip_preflight {
+            settings.skip_preflight = value.parse().map_err(|_| {
+                format!("Invalid skip_preflight {:?}, expected true or false", value)
+            })?;
+        }
+        if let Some(value) = &file.send_attempts {
+            settings.max_attempts = value
+                .parse()
+                .ok()
+                .filter(|attempts| *attempts > 0)
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid send_attempts {:?}, expected a positive number",
+                        value
+                    )
+                })?;
+        }
 
-    // Создаем инструкцию
-    let instruction = Instruction::new_with_bytes(program_id, &instruction_data, accounts);
-    let message = Message::new(&[instruction], Some(&payer.pubkey()));
+        Ok(settings)
+    }
+
+    fn rpc_send_config(&self) -> RpcSendTransactionConfig {
+        RpcSendTransactionConfig {
+            skip_preflight: self.skip_preflight,
+            preflight_commitment: Some(self.commitment),
+            ..RpcSendTransactionConfig::default()
+        }
+    }
+}
+
+// Отправляем с настройками и ждем подтверждения на заданном уровне
+fn send_and_confirm(
+    client: &RpcClient,
+    transaction: &Transaction,
+    settings: &SendSettings,
+) -> Result<Signature, Box<ClientError>> {
+    let commitment = CommitmentConfig {
+        commitment: settings.commitment,
+    };
+    let signature = client.send_transaction_with_config(transaction, settings.rpc_send_config())?;
+    client.poll_for_signature_with_commitment(&signature, commitment)?;
+    // Опрос завершается при любом статусе, в том числе у упавшей транзакции
+    if let Some(Err(err)) = client.get_signature_status_with_commitment(&signature, commitment)? {
+        return Err(Box::new(err.into()));
+    }
+
+    Ok(signature)
+}
+
+// Сетевые сбои повторяем, удваивая паузу; ошибку самой транзакции повтор не исправит.
+// Транзакция та же, поэтому повторная отправка не приведет к двойному списанию
+fn send_transaction(
+    client: &RpcClient,
+    transaction: &Transaction,
+    settings: &SendSettings,
+    initial_backoff: Duration,
+) -> Result<Signature, Box<dyn std::error::Error + Send + Sync>> {
+    let attempts = settings.max_attempts.max(1);
+    let mut backoff = initial_backoff;
+
+    for attempt in 1..=attempts {
+        match send_and_confirm(client, transaction, settings) {
+            Ok(signature) => return Ok(signature),
+            Err(err) if attempt < attempts && err.get_transaction_error().is_none() => {
+                warn!(
+                    "Attempt {}/{} to send transaction failed: {}, retry in {:?}",
+                    attempt, attempts, err, backoff
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+            Err(err) => return Err(explain_send_error(client, transaction, *err)),
+        }
+    }
+
+    Err("No send attempts were made".into())
+}
+
+// Печатаем подпись со ссылкой на explorer; код выхода говорит, прошла ли транзакция
+fn send_and_report(
+    client: &RpcClient,
+    transaction: &Transaction,
+    settings: &SendSettings,
+    initial_backoff: Duration,
+) -> ExitCode {
+    match send_transaction(client, transaction, settings, initial_backoff) {
+        Ok(signature) => {
+            println!(
+                "Transaction {} confirmed ({:?})\nExplorer: {}",
+                signature,
+                settings.commitment,
+                explorer_url(&signature, Cluster::from_rpc_url(&client.url()))
+            );
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            error!("Error sending transaction: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// IncorrectProgramId почти всегда значит, что клиент смотрит не на ту программу
+// или не в тот кластер; повтор тут не поможет, поэтому сразу объясняем причину
+fn explain_send_error(
+    client: &RpcClient,
+    transaction: &Transaction,
+    err: ClientError,
+) -> Box<dyn std::error::Error + Send + Sync> {
+    let Some(TransactionError::InstructionError(index, InstructionError::IncorrectProgramId)) =
+        err.get_transaction_error()
+    else {
+        return describe_send_error(err);
+    };
+    let Some(program_id) = transaction
+        .message
+        .instructions
+        .get(index as usize)
+        .map(|instruction| *instruction.program_id(&transaction.message.account_keys))
+    else {
+        return describe_send_error(err);
+    };
+
+    let deployment = match verify_program_deployed(client, &program_id) {
+        Ok(()) => format!("program {} is deployed on {}", program_id, client.url()),
+        Err(err) => err,
+    };
+    format!(
+        "Instruction {} failed with IncorrectProgramId: an account it uses is owned by a different program. \
+         Check that program id {} and the cluster {} are correct ({})",
+        index,
+        program_id,
+        client.url(),
+        deployment
+    )
+    .into()
+}
+
+// Если транзакция не прошла preflight, добавляем к ошибке логи программы
+fn describe_send_error(err: ClientError) -> Box<dyn std::error::Error + Send + Sync> {
+    if let ClientErrorKind::RpcError(RpcError::RpcResponseError {
+        data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+        message,
+        ..
+    }) = err.kind()
+    {
+        let logs = result.logs.clone().unwrap_or_default();
+        return format!("{}\nProgram logs:\n  {}", message, logs.join("\n  ")).into();
+    }
+
+    err.into()
+}
+
+// Подкоманда deposit-address <program_id> <user>: адрес депозита и bump,
+// вычисленные так же, как в программе
+fn deposit_address_command(args: &[String]) -> Result<String, String> {
+    let [program_id, user] = args else {
+        return Err("Usage: deposit-address <program_id> <user_pubkey>".to_string());
+    };
+
+    let program_id = Pubkey::from_str(program_id)
+        .map_err(|err| format!("Invalid program id {}: {}", program_id, err))?;
+    let user =
+        Pubkey::from_str(user).map_err(|err| format!("Invalid user pubkey {}: {}", user, err))?;
+    let (address, bump) = find_deposit_address(&program_id, &user);
+
+    Ok(format!("Deposit address: {}\nBump: {}", address, bump))
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("deposit-address") {
+        match deposit_address_command(&args[1..]) {
+            Ok(output) => println!("{}", output),
+            Err(err) => {
+                eprintln!("{}", err);
+                return ExitCode::FAILURE;
+            }
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    // Адрес контракта, кластер, кошелек, настройки отправки и формат логов берем из конфига;
+    // логирование еще не настроено, поэтому ошибку конфига пишем прямо в stderr
+    let config = match load_config(CONFIG_PATH) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    init_logging(config.log_format);
+    let program_id = config.program_id;
+    let settings = config.send_settings;
+
+    let payer = match read_keypair_file(&config.keypair_path) {
+        Ok(payer) => payer,
+        Err(err) => {
+            error!("Failed to read keypair {}: {}", config.keypair_path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let client = RpcClient::new_with_commitment(
+        config.rpc_url,
+        CommitmentConfig {
+            commitment: settings.commitment,
+        },
+    );
+
+    // Перед отправкой убеждаемся, что по адресу действительно развернута программа
+    if let Err(err) = verify_program_deployed(&client, &program_id) {
+        error!("Program is not deployed: {}", err);
+        return ExitCode::FAILURE;
+    }
+
+    // Создаем новый депозит и сразу пополняем его
+    let lamports = Lamports::from_sol(0.001).expect("Deposit amount is a valid SOL value");
+    let deposit = Keypair::new();
+    let mut rent_cache = RentCache::new();
+    match rent_cache.minimum_balance(&client, DepositState::LEN) {
+        Ok(minimum) => info!(
+            "Deposit account needs at least {} lamports to stay rent-exempt",
+            minimum
+        ),
+        Err(err) => warn!("Failed to fetch rent-exempt minimum: {}", err),
+    }
 
     // Создаем и отправляем транзакцию
-    let recent_blockhash = client.get_latest_blockhash().unwrap();
-    let mut transaction = Transaction::new_unsigned(message);
-    transaction.recent_blockhash = recent_blockhash;
-    transaction.sign(&[&payer], recent_blockhash);
-
-    let result = client.send_and_confirm_transaction(&transaction);
-    match result {
-        Ok(_) => println!("Transaction successfully sent."),
-        Err(err) => eprintln!("Error sending transaction: {}", err),
+    let recent_blockhash = match get_latest_blockhash_with_retry(
+        &client,
+        BLOCKHASH_ATTEMPTS,
+        BLOCKHASH_INITIAL_BACKOFF,
+    ) {
+        Ok(blockhash) => blockhash,
+        Err(err) => {
+            error!(
+                "Failed to fetch latest blockhash after {} attempts: {}",
+                BLOCKHASH_ATTEMPTS, err
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    let transaction = match create_and_deposit_transaction(
+        &program_id,
+        &payer,
+        &deposit,
+        CreateDepositArgs::default(),
+        lamports,
+        recent_blockhash,
+    ) {
+        Ok(transaction) => transaction,
+        Err(err) => {
+            error!("Failed to build create and deposit transaction: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if send_and_report(&client, &transaction, &settings, SEND_INITIAL_BACKOFF) != ExitCode::SUCCESS
+    {
+        return ExitCode::FAILURE;
+    }
+    info!("Deposit account {} created.", deposit.pubkey());
+
+    // Новый депозит должен совпасть с состоянием по умолчанию для этого владельца
+    let expected = DepositState {
+        owner: payer.pubkey(),
+        ..DepositState::default()
+    };
+    match assert_deposit_state(&client, &deposit.pubkey(), &expected) {
+        Ok(()) => {
+            info!("Deposit state matches the expected snapshot.");
+            ExitCode::SUCCESS
+        }
+        Err(diff) => {
+            error!("{}", diff);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock::{timeout_error, MockRpc};
+    use serde_json::json;
+    use solana_client::{rpc_request::RpcRequest, rpc_response::RpcSimulateTransactionResult};
+    use solana_program_test::{processor, ProgramTest};
+    use solana_smart_contracts::process_instruction;
+
+    #[test]
+    fn test_deposit_address_command_matches_program() {
+        let program_id = Pubkey::new_from_array([7; 32]);
+        let user = Pubkey::new_from_array([9; 32]);
+        let (address, bump) = find_deposit_address(&program_id, &user);
+
+        let output = deposit_address_command(&[program_id.to_string(), user.to_string()]).unwrap();
+
+        assert_eq!(
+            output,
+            format!("Deposit address: {}\nBump: {}", address, bump)
+        );
+        assert_eq!(
+            Pubkey::create_program_address(
+                &[solana_smart_contracts::DEPOSIT_SEED, user.as_ref(), &[bump]],
+                &program_id
+            )
+            .unwrap(),
+            address
+        );
+    }
+
+    #[test]
+    fn test_deposit_address_command_rejects_bad_input() {
+        assert!(deposit_address_command(&[]).is_err());
+        assert!(
+            deposit_address_command(&["not-a-key".to_string(), "x".to_string()])
+                .unwrap_err()
+                .starts_with("Invalid program id")
+        );
+    }
+
+    #[test]
+    fn test_config_parses_program_id() {
+        let program_id = Pubkey::new_from_array([7; 32]);
+        let content = format!(
+            "program_id: {}\nrpc_url: https://api.devnet.solana.com\nkeypair_path: payer.json\n",
+            program_id
+        );
+
+        assert_eq!(
+            parse_config(&content).unwrap(),
+            Config {
+                program_id,
+                rpc_url: "https://api.devnet.solana.com".to_string(),
+                keypair_path: "payer.json".to_string(),
+                log_format: LogFormat::Plain,
+                send_settings: SendSettings::default(),
+            }
+        );
+    }
+
+    // Минимальный конфиг с дополнительными строками YAML
+    fn config_with(extra: &str) -> Result<Config, String> {
+        parse_config(&format!(
+            "program_id: {}\nrpc_url: http://rpc\nkeypair_path: payer.json\n{}",
+            Pubkey::new_unique(),
+            extra
+        ))
+    }
+
+    #[test]
+    fn test_config_sets_send_settings_and_log_format() {
+        let config = config_with(
+            "commitment: processed\nskip_preflight: true\nsend_attempts: 5\nlog_format: json\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(
+            config.send_settings,
+            SendSettings {
+                commitment: CommitmentLevel::Processed,
+                skip_preflight: true,
+                max_attempts: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn test_config_rejects_invalid_send_settings() {
+        assert_eq!(
+            config_with("commitment: fast\n").unwrap_err(),
+            "Invalid commitment \"fast\", expected processed, confirmed or finalized"
+        );
+        assert_eq!(
+            config_with("skip_preflight: maybe\n").unwrap_err(),
+            "Invalid skip_preflight \"maybe\", expected true or false"
+        );
+        assert_eq!(
+            config_with("send_attempts: 0\n").unwrap_err(),
+            "Invalid send_attempts \"0\", expected a positive number"
+        );
+    }
+
+    #[test]
+    fn test_config_rejects_invalid_program_id() {
+        let content =
+            "program_id: YOUR_PROGRAM_ID_HERE\nrpc_url: http://rpc\nkeypair_path: payer.json\n";
+
+        assert_eq!(
+            parse_config(content).unwrap_err(),
+            "Invalid program_id \"YOUR_PROGRAM_ID_HERE\": Invalid Base58 string, \
+             expected a base58 program address"
+        );
+        assert!(
+            parse_config("rpc_url: http://rpc\nkeypair_path: payer.json\n")
+                .unwrap_err()
+                .contains("missing field `program_id`")
+        );
+    }
+
+    #[test]
+    fn test_blockhash_retry_succeeds_after_failures() {
+        let rpc = MockRpc::new();
+        rpc.push(RpcRequest::GetLatestBlockhash, Err(timeout_error()))
+            .push(RpcRequest::GetLatestBlockhash, Err(timeout_error()));
+        let client = rpc.client();
+
+        let result = get_latest_blockhash_with_retry(&client, 3, Duration::from_millis(1));
+
+        assert!(result.is_ok());
+        assert_eq!(rpc.calls(RpcRequest::GetLatestBlockhash), 3);
+    }
+
+    #[test]
+    fn test_blockhash_retry_gives_up_after_attempts() {
+        let rpc = MockRpc::new();
+        for _ in 0..2 {
+            rpc.push(RpcRequest::GetLatestBlockhash, Err(timeout_error()));
+        }
+        let client = rpc.client();
+
+        let result = get_latest_blockhash_with_retry(&client, 2, Duration::from_millis(1));
+
+        assert!(result.is_err());
+        assert_eq!(rpc.calls(RpcRequest::GetLatestBlockhash), 2);
+    }
+
+    fn transfer_transaction(payer: &Keypair) -> Transaction {
+        Transaction::new_signed_with_payer(
+            &[solana_sdk::system_instruction::transfer(
+                &payer.pubkey(),
+                &Pubkey::new_unique(),
+                1,
+            )],
+            Some(&payer.pubkey()),
+            &[payer],
+            Hash::default(),
+        )
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_send_settings_are_applied() {
+        let rpc = MockRpc::new();
+        let client = rpc.client();
+        let settings = config_with("commitment: processed\nskip_preflight: true\n")
+            .unwrap()
+            .send_settings;
+        let transaction = transfer_transaction(&Keypair::new());
+
+        let signature =
+            send_transaction(&client, &transaction, &settings, Duration::from_millis(1)).unwrap();
+
+        assert_eq!(signature, transaction.signatures[0]);
+        let params = rpc.params(RpcRequest::SendTransaction);
+        assert_eq!(params.len(), 1);
+        assert_eq!(params[0][1]["skipPreflight"], json!(true));
+        assert_eq!(params[0][1]["preflightCommitment"], json!("processed"));
+        assert_eq!(
+            rpc.params(RpcRequest::GetSignatureStatuses)[0],
+            json!([[signature.to_string()]])
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_send_retries_network_failures_then_succeeds() {
+        let rpc = MockRpc::new();
+        rpc.push(RpcRequest::SendTransaction, Err(timeout_error()))
+            .push(RpcRequest::SendTransaction, Err(timeout_error()));
+        let client = rpc.client();
+        let transaction = transfer_transaction(&Keypair::new());
+
+        let code = send_and_report(
+            &client,
+            &transaction,
+            &SendSettings::default(),
+            Duration::from_millis(1),
+        );
+
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 3);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_send_failure_after_attempts_exits_with_failure() {
+        let rpc = MockRpc::new();
+        for _ in 0..2 {
+            rpc.push(RpcRequest::SendTransaction, Err(timeout_error()));
+        }
+        // Ошибку транзакции не повторяем: одна попытка, даже если лимит больше
+        rpc.push(
+            RpcRequest::SendTransaction,
+            Err(TransactionError::InsufficientFundsForFee.into()),
+        );
+        let client = rpc.client();
+        let transaction = transfer_transaction(&Keypair::new());
+        let settings = SendSettings {
+            max_attempts: 2,
+            ..SendSettings::default()
+        };
+
+        let code = send_and_report(&client, &transaction, &settings, Duration::from_millis(1));
+
+        assert_eq!(code, ExitCode::FAILURE);
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 2);
+
+        let code = send_and_report(&client, &transaction, &settings, Duration::from_millis(1));
+
+        assert_eq!(code, ExitCode::FAILURE);
+        assert_eq!(rpc.calls(RpcRequest::SendTransaction), 3);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_incorrect_program_id_gets_friendly_message() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "deposit_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+        // Кошелек плательщика принадлежит System Program, а не программе депозитов
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction::balance(&program_id, &payer.pubkey())
+                .unwrap()
+                .instruction],
+            Some(&payer.pubkey()),
+            &[&payer],
+            recent_blockhash,
+        );
+        let tx_err = banks_client
+            .process_transaction(transaction.clone())
+            .await
+            .unwrap_err()
+            .unwrap();
+        let client = MockRpc::new().client();
+
+        let message =
+            explain_send_error(&client, &transaction, ClientErrorKind::from(tx_err).into())
+                .to_string();
+
+        assert!(message.starts_with("Instruction 0 failed with IncorrectProgramId"));
+        assert!(message.contains(&format!("Check that program id {}", program_id)));
+        assert!(message.contains(&format!("Program account {} does not exist", program_id)));
+        assert_eq!(
+            explain_send_error(&client, &transaction, timeout_error()).to_string(),
+            timeout_error().to_string()
+        );
+    }
+
+    #[test]
+    fn test_preflight_failure_includes_program_logs() {
+        let err = ClientError::from(RpcError::RpcResponseError {
+            code: -32002,
+            message: "Transaction simulation failed".to_string(),
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(
+                RpcSimulateTransactionResult {
+                    logs: Some(vec![
+                        "Program log: Insufficient funds in user account.".to_string()
+                    ]),
+                    err: None,
+                    accounts: None,
+                    units_consumed: None,
+                    return_data: None,
+                    inner_instructions: None,
+                },
+            ),
+        });
+
+        assert_eq!(
+            describe_send_error(err).to_string(),
+            "Transaction simulation failed\nProgram logs:\n  Program log: Insufficient funds in user account."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_program_deployment_is_verified() {
+        let program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::default();
+        program_test.add_account(
+            program_id,
+            Account {
+                lamports: 1_000_000,
+                data: vec![],
+                owner: bpf_loader_upgradeable::id(),
+                executable: true,
+                rent_epoch: 0,
+            },
+        );
+        let (mut banks_client, payer, _) = program_test.start().await;
+
+        let deployed = banks_client.get_account(program_id).await.unwrap();
+        assert_eq!(
+            check_program_account(&program_id, deployed.as_ref()),
+            Ok(())
+        );
+
+        let random = Pubkey::new_unique();
+        let missing = banks_client.get_account(random).await.unwrap();
+        assert_eq!(
+            check_program_account(&random, missing.as_ref()),
+            Err(format!("Program account {} does not exist", random))
+        );
+
+        let wallet = banks_client.get_account(payer.pubkey()).await.unwrap();
+        assert_eq!(
+            check_program_account(&payer.pubkey(), wallet.as_ref()),
+            Err(format!("Account {} is not executable", payer.pubkey()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_and_deposit_in_one_transaction() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "deposit_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+        let deposit = Keypair::new();
+        let amount = Lamports(1_000_000);
+
+        let transaction = create_and_deposit_transaction(
+            &program_id,
+            &payer,
+            &deposit,
+            CreateDepositArgs::default(),
+            amount,
+            recent_blockhash,
+        )
+        .unwrap();
+        assert_eq!(transaction.message.instructions.len(), 2);
+        banks_client.process_transaction(transaction).await.unwrap();
+
+        let account = banks_client
+            .get_account(deposit.pubkey())
+            .await
+            .unwrap()
+            .expect("Deposit account should exist");
+        let rent = banks_client.get_rent().await.unwrap();
+        assert_eq!(account.owner, program_id);
+        assert_eq!(
+            account.lamports,
+            rent.minimum_balance(DepositState::LEN) + u64::from(amount)
+        );
     }
 }
@@ -0,0 +1,87 @@
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{account::from_account, rent::Rent, sysvar};
+use std::collections::HashMap;
+
+// Rent кластера запрашивается один раз, minimum_balance для каждого размера считается
+// локально и запоминается, чтобы планировщик не ходил в сеть за каждым аккаунтом
+#[derive(Default)]
+pub struct RentCache {
+    rent: Option<Rent>,
+    balances: HashMap<usize, u64>,
+}
+
+impl RentCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn minimum_balance(
+        &mut self,
+        client: &RpcClient,
+        size: usize,
+    ) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(balance) = self.balances.get(&size) {
+            return Ok(*balance);
+        }
+
+        let rent = match self.rent {
+            Some(rent) => rent,
+            None => {
+                let account = client.get_account(&sysvar::rent::id())?;
+                let rent: Rent =
+                    from_account(&account).ok_or("Failed to decode the rent sysvar account")?;
+                self.rent = Some(rent);
+                rent
+            }
+        };
+
+        let balance = rent.minimum_balance(size);
+        self.balances.insert(size, balance);
+        Ok(balance)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockRpc;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+
+    #[test]
+    fn test_repeated_sizes_do_not_refetch_rent() {
+        let rent = Rent {
+            lamports_per_byte_year: 10,
+            ..Rent::default()
+        };
+        let data = bincode::serialize(&rent).unwrap();
+        let rpc = MockRpc::new();
+        rpc.push(
+            RpcRequest::GetAccountInfo,
+            Ok(json!({
+                "context": { "slot": 1 },
+                "value": {
+                    "data": [STANDARD.encode(&data), "base64"],
+                    "executable": false,
+                    "lamports": 1_000_000,
+                    "owner": sysvar::id().to_string(),
+                    "rentEpoch": 0,
+                    "space": data.len()
+                }
+            })),
+        );
+        let client = rpc.client();
+        let mut cache = RentCache::new();
+
+        for size in [0, 165, 82, 165, 0] {
+            assert_eq!(
+                cache.minimum_balance(&client, size).unwrap(),
+                rent.minimum_balance(size)
+            );
+        }
+
+        assert_eq!(rpc.calls(RpcRequest::GetAccountInfo), 1);
+        assert_eq!(cache.balances.len(), 3);
+    }
+}
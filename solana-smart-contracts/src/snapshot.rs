@@ -0,0 +1,156 @@
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_smart_contracts::state::DepositState;
+use std::fmt;
+
+// Поле состояния, значение которого в сети отличается от ожидаемого
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldMismatch {
+    pub field: &'static str,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateDiff {
+    // Аккаунт не удалось получить или разобрать как депозит
+    Unreadable(String),
+    Fields(Vec<FieldMismatch>),
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateDiff::Unreadable(err) => write!(f, "Deposit state is unreadable: {}", err),
+            StateDiff::Fields(mismatches) => {
+                let fields: Vec<String> = mismatches
+                    .iter()
+                    .map(|mismatch| {
+                        format!(
+                            "{}: expected {}, got {}",
+                            mismatch.field, mismatch.expected, mismatch.actual
+                        )
+                    })
+                    .collect();
+                write!(f, "Deposit state differs: {}", fields.join("; "))
+            }
+        }
+    }
+}
+
+// Сравнивает перечисленные поля и собирает все расхождения, а не только первое
+macro_rules! diff_fields {
+    ($expected:expr, $actual:expr, $($field:ident),+ $(,)?) => {{
+        let mut mismatches = Vec::new();
+        $(
+            if $expected.$field != $actual.$field {
+                mismatches.push(FieldMismatch {
+                    field: stringify!($field),
+                    expected: format!("{:?}", $expected.$field),
+                    actual: format!("{:?}", $actual.$field),
+                });
+            }
+        )+
+        mismatches
+    }};
+}
+
+fn diff_states(expected: &DepositState, actual: &DepositState) -> Vec<FieldMismatch> {
+    diff_fields!(
+        expected,
+        actual,
+        version,
+        fee_bps,
+        treasury,
+        min_deposit,
+        seq,
+        owner,
+        withdraw_cap,
+        cap_window_slots,
+        recent_withdrawals,
+        authority,
+        frozen,
+        referral_bps,
+        referral_payouts,
+        withdraw_whitelist,
+        last_purpose,
+    )
+}
+
+// Состояние депозита в сети должно совпасть с ожидаемым снимком поле в поле
+pub fn assert_deposit_state(
+    client: &RpcClient,
+    pubkey: &Pubkey,
+    expected: &DepositState,
+) -> Result<(), StateDiff> {
+    let account = client
+        .get_account(pubkey)
+        .map_err(|err| StateDiff::Unreadable(err.to_string()))?;
+    let actual = DepositState::unpack(&account.data)
+        .map_err(|err| StateDiff::Unreadable(err.to_string()))?;
+
+    let mismatches = diff_states(expected, &actual);
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(StateDiff::Fields(mismatches))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockRpc;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+
+    fn deposit_account_response(program_id: &Pubkey, state: &DepositState) -> serde_json::Value {
+        let data = borsh::to_vec(state).unwrap();
+        json!({
+            "context": { "slot": 1 },
+            "value": {
+                "data": [STANDARD.encode(&data), "base64"],
+                "executable": false,
+                "lamports": 1_000_000,
+                "owner": program_id.to_string(),
+                "rentEpoch": 0,
+                "space": data.len()
+            }
+        })
+    }
+
+    #[test]
+    fn test_matching_state_passes_and_mismatch_is_reported() {
+        let (program_id, deposit) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let expected = DepositState {
+            owner: Pubkey::new_unique(),
+            seq: 3,
+            ..DepositState::default()
+        };
+        let on_chain = DepositState {
+            seq: 4,
+            ..expected.clone()
+        };
+        let rpc = MockRpc::new();
+        rpc.push(
+            RpcRequest::GetAccountInfo,
+            Ok(deposit_account_response(&program_id, &expected)),
+        )
+        .push(
+            RpcRequest::GetAccountInfo,
+            Ok(deposit_account_response(&program_id, &on_chain)),
+        );
+        let client = rpc.client();
+
+        assert_eq!(assert_deposit_state(&client, &deposit, &expected), Ok(()));
+        assert_eq!(
+            assert_deposit_state(&client, &deposit, &expected),
+            Err(StateDiff::Fields(vec![FieldMismatch {
+                field: "seq",
+                expected: "3".to_string(),
+                actual: "4".to_string(),
+            }]))
+        );
+    }
+}
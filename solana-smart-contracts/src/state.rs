@@ -0,0 +1,317 @@
+use crate::error::DepositError;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, hash::hash, program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+pub const MAX_FEE_BPS: u16 = 10_000;
+pub const CURRENT_VERSION: u8 = 1;
+// Сколько выводов внутри окна лимита хранится в состоянии по отдельности
+pub const MAX_WINDOW_WITHDRAWALS: usize = 8;
+// Сколько адресов помещается в белый список получателей вывода
+pub const MAX_WHITELIST: usize = 4;
+
+// Вывод, учитываемый в лимите за окно слотов
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct WithdrawalEntry {
+    pub amount: u64,
+    pub slot: u64,
+}
+
+// Назначение депозита, которое клиент передает в ProcessTaggedDeposit.
+// Borsh кодирует вариант одним байтом, неизвестный байт не разбирается
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepositPurpose {
+    Savings,
+    Payment,
+    Escrow,
+    Fee,
+}
+
+impl DepositPurpose {
+    pub const ALL: [DepositPurpose; 4] = [
+        DepositPurpose::Savings,
+        DepositPurpose::Payment,
+        DepositPurpose::Escrow,
+        DepositPurpose::Fee,
+    ];
+}
+
+// Данные, которые хранятся в аккаунте депозита
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct DepositState {
+    // Версия формата, позволяет различать старые аккаунты при миграции
+    pub version: u8,
+    pub fee_bps: u16,
+    pub treasury: Pubkey,
+    // Минимальная сумма одного депозита, 0 - без ограничения
+    pub min_deposit: u64,
+    // Номер последнего вывода, увеличивается на 1 при каждом withdraw
+    pub seq: u64,
+    // Владелец депозита; rent при создании мог оплатить другой аккаунт
+    pub owner: Pubkey,
+    // Сколько можно вывести за cap_window_slots слотов, 0 - без ограничения
+    pub withdraw_cap: u64,
+    pub cap_window_slots: u64,
+    // Выводы внутри окна; запись с amount 0 свободна
+    pub recent_withdrawals: [WithdrawalEntry; MAX_WINDOW_WITHDRAWALS],
+    // Кроме владельца может замораживать и размораживать депозит, Pubkey::default() - не задан
+    pub authority: Pubkey,
+    // Замороженный депозит не дает выводить средства
+    pub frozen: bool,
+    // Доля каждого депозита, которая уходит переданному рефералу
+    pub referral_bps: u16,
+    // Сколько всего лампортов выплачено рефералам
+    pub referral_payouts: u64,
+    // Куда разрешено выводить; Pubkey::default() - пустое место, пустой список - куда угодно
+    pub withdraw_whitelist: [Pubkey; MAX_WHITELIST],
+    // Назначение последнего депозита с меткой, None - таких депозитов не было
+    pub last_purpose: Option<DepositPurpose>,
+}
+
+// Формат версии 0: те же поля, но без байта версии
+#[derive(BorshDeserialize)]
+struct DepositStateV0 {
+    fee_bps: u16,
+    treasury: Pubkey,
+    min_deposit: u64,
+}
+
+impl Default for DepositState {
+    fn default() -> Self {
+        DepositState {
+            version: CURRENT_VERSION,
+            fee_bps: 0,
+            treasury: Pubkey::default(),
+            min_deposit: 0,
+            seq: 0,
+            owner: Pubkey::default(),
+            withdraw_cap: 0,
+            cap_window_slots: 0,
+            recent_withdrawals: [WithdrawalEntry::default(); MAX_WINDOW_WITHDRAWALS],
+            authority: Pubkey::default(),
+            frozen: false,
+            referral_bps: 0,
+            referral_payouts: 0,
+            withdraw_whitelist: [Pubkey::default(); MAX_WHITELIST],
+            last_purpose: None,
+        }
+    }
+}
+
+impl DepositState {
+    pub const LEN: usize = 1
+        + 2
+        + 32
+        + 8
+        + 8
+        + 32
+        + 8
+        + 8
+        + 16 * MAX_WINDOW_WITHDRAWALS
+        + 32
+        + 1
+        + 2
+        + 8
+        + 32 * MAX_WHITELIST
+        + 2;
+    pub const V0_LEN: usize = 2 + 32 + 8;
+
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        Self::unpack(&account.data.borrow())
+    }
+
+    // Кроме текущего формата бывают только аккаунты версии 0 без байта версии,
+    // их узнаем по длине данных и дополняем значениями по умолчанию до миграции
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() == Self::V0_LEN {
+            let state = DepositStateV0::deserialize(&mut &data[..])
+                .map_err(|_| ProgramError::InvalidAccountData)?;
+
+            return Ok(DepositState {
+                version: 0,
+                fee_bps: state.fee_bps,
+                treasury: state.treasury,
+                min_deposit: state.min_deposit,
+                ..DepositState::default()
+            });
+        }
+
+        let state =
+            Self::deserialize(&mut &data[..]).map_err(|_| ProgramError::InvalidAccountData)?;
+        if state.version != CURRENT_VERSION {
+            return Err(DepositError::UnsupportedVersion.into());
+        }
+
+        Ok(state)
+    }
+
+    // Хэш сериализованного состояния для оптимистичной блокировки
+    pub fn hash(&self) -> [u8; 32] {
+        let data = borsh::to_vec(self).expect("Deposit state always serializes");
+        hash(&data).to_bytes()
+    }
+
+    pub fn needs_migration(&self) -> bool {
+        self.version < CURRENT_VERSION
+    }
+
+    pub fn save(&self, account: &AccountInfo) -> ProgramResult {
+        self.serialize(&mut &mut account.data.borrow_mut()[..])
+            .map_err(|_| ProgramError::AccountDataTooSmall)
+    }
+
+    // Учитывает вывод в лимите за окно: выводы старше cap_window_slots слотов не считаются
+    pub fn record_withdrawal(&mut self, lamports: u64, slot: u64) -> Result<(), DepositError> {
+        if self.withdraw_cap == 0 || lamports == 0 {
+            return Ok(());
+        }
+
+        for entry in self.recent_withdrawals.iter_mut() {
+            if slot.saturating_sub(entry.slot) >= self.cap_window_slots {
+                *entry = WithdrawalEntry::default();
+            }
+        }
+
+        let used: u128 = self
+            .recent_withdrawals
+            .iter()
+            .map(|entry| entry.amount as u128)
+            .sum();
+        if used + lamports as u128 > self.withdraw_cap as u128 {
+            return Err(DepositError::DailyLimitExceeded);
+        }
+
+        // Без свободной записи добавляем сумму к самой свежей: она истечет позже,
+        // поэтому лимит становится только строже
+        let entry = match self
+            .recent_withdrawals
+            .iter_mut()
+            .find(|entry| entry.amount == 0)
+        {
+            Some(entry) => entry,
+            None => self
+                .recent_withdrawals
+                .iter_mut()
+                .max_by_key(|entry| entry.slot)
+                .expect("Window has at least one entry"),
+        };
+        entry.amount += lamports;
+        entry.slot = slot;
+
+        Ok(())
+    }
+
+    // Заморозкой управляют владелец и authority, если она задана
+    pub fn can_freeze(&self, signer: &Pubkey) -> bool {
+        *signer == self.owner || (self.authority != Pubkey::default() && *signer == self.authority)
+    }
+
+    // Пустой белый список ничего не ограничивает
+    pub fn allows_destination(&self, destination: &Pubkey) -> bool {
+        let mut allowed = self
+            .withdraw_whitelist
+            .iter()
+            .filter(|entry| **entry != Pubkey::default())
+            .peekable();
+        allowed.peek().is_none() || allowed.any(|entry| entry == destination)
+    }
+
+    // Комиссия протокола с суммы вывода, округляется вниз
+    pub fn withdraw_fee(&self, lamports: u64) -> u64 {
+        (lamports as u128 * self.fee_bps as u128 / MAX_FEE_BPS as u128) as u64
+    }
+
+    // Доля реферала с суммы депозита, округляется вниз
+    pub fn referral_share(&self, lamports: u64) -> u64 {
+        (lamports as u128 * self.referral_bps as u128 / MAX_FEE_BPS as u128) as u64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unpack_reads_v0_layout() {
+        let treasury = Pubkey::new_unique();
+        let mut data = Vec::new();
+        data.extend_from_slice(&250u16.to_le_bytes());
+        data.extend_from_slice(treasury.as_ref());
+        data.extend_from_slice(&10_000u64.to_le_bytes());
+
+        let state = DepositState::unpack(&data).unwrap();
+
+        assert_eq!(state.version, 0);
+        assert_eq!(state.fee_bps, 250);
+        assert_eq!(state.treasury, treasury);
+        assert_eq!(state.min_deposit, 10_000);
+        assert!(state.needs_migration());
+    }
+
+    #[test]
+    fn test_last_purpose_round_trips_through_account_data() {
+        for purpose in DepositPurpose::ALL {
+            let state = DepositState {
+                last_purpose: Some(purpose),
+                ..DepositState::default()
+            };
+            let data = borsh::to_vec(&state).unwrap();
+            assert_eq!(data.len(), DepositState::LEN);
+
+            assert_eq!(DepositState::unpack(&data).unwrap(), state);
+        }
+    }
+
+    #[test]
+    fn test_whitelist_limits_destinations_once_set() {
+        let allowed = Pubkey::new_unique();
+        let mut state = DepositState::default();
+        assert!(state.allows_destination(&Pubkey::new_unique()));
+
+        state.withdraw_whitelist[2] = allowed;
+
+        assert!(state.allows_destination(&allowed));
+        assert!(!state.allows_destination(&Pubkey::new_unique()));
+        assert!(!state.allows_destination(&Pubkey::default()));
+    }
+
+    #[test]
+    fn test_full_window_merges_into_newest_entry() {
+        let mut state = DepositState {
+            withdraw_cap: 1_000,
+            cap_window_slots: 100,
+            ..DepositState::default()
+        };
+        for slot in 0..MAX_WINDOW_WITHDRAWALS as u64 {
+            state.record_withdrawal(10, slot).unwrap();
+        }
+
+        state.record_withdrawal(20, 50).unwrap();
+
+        let newest = state.recent_withdrawals[MAX_WINDOW_WITHDRAWALS - 1];
+        assert_eq!(
+            newest,
+            WithdrawalEntry {
+                amount: 30,
+                slot: 50
+            }
+        );
+        assert_eq!(
+            state.record_withdrawal(901, 50),
+            Err(DepositError::DailyLimitExceeded)
+        );
+    }
+
+    #[test]
+    fn test_unpack_rejects_unknown_version() {
+        let mut data = borsh::to_vec(&DepositState::default()).unwrap();
+        data[0] = CURRENT_VERSION + 1;
+
+        assert_eq!(
+            DepositState::unpack(&data),
+            Err(DepositError::UnsupportedVersion.into())
+        );
+    }
+}
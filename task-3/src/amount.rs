@@ -0,0 +1,98 @@
+use serde::de::{self, Deserializer, Visitor};
+use std::fmt;
+
+pub const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+// Лампорт - одна миллиардная SOL, точнее сумму не записать
+const SOL_DECIMALS: usize = 9;
+
+// Десятичная сумма в SOL, например "0.002", в лампортах
+pub fn parse_sol(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+
+    if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+        return Err(format!("Invalid SOL amount {:?}", value));
+    }
+    if fraction.len() > SOL_DECIMALS {
+        return Err(format!(
+            "SOL amount {} has more than {} decimal places",
+            value, SOL_DECIMALS
+        ));
+    }
+
+    let whole: u64 = if whole.is_empty() {
+        0
+    } else {
+        whole
+            .parse()
+            .map_err(|_| format!("SOL amount {} is too large", value))?
+    };
+    let fraction: u64 = format!("{:0<width$}", fraction, width = SOL_DECIMALS)
+        .parse()
+        .expect("Nine ASCII digits always fit in u64");
+
+    whole
+        .checked_mul(LAMPORTS_PER_SOL)
+        .and_then(|lamports| lamports.checked_add(fraction))
+        .ok_or_else(|| format!("SOL amount {} is too large", value))
+}
+
+// Целое число в конфиге - лампорты, строка - десятичная сумма в SOL
+pub fn deserialize_lamports<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    struct LamportsVisitor;
+
+    impl Visitor<'_> for LamportsVisitor {
+        type Value = u64;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "an integer amount in lamports or a decimal SOL string")
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<u64, E> {
+            Ok(value)
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<u64, E> {
+            u64::try_from(value).map_err(|_| E::custom("Amount cannot be negative"))
+        }
+
+        // Дробное число YAML хранит как float и теряет точность, поэтому просим строку
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<u64, E> {
+            Err(E::custom(format!(
+                "Write SOL amounts as a quoted string, e.g. \"{}\"",
+                value
+            )))
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<u64, E> {
+            parse_sol(value).map_err(E::custom)
+        }
+    }
+
+    deserializer.deserialize_any(LamportsVisitor)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_sol_converts_to_lamports() {
+        assert_eq!(parse_sol("0.002"), Ok(2_000_000));
+        assert_eq!(parse_sol("1.5"), Ok(1_500_000_000));
+        assert_eq!(parse_sol("3"), Ok(3 * LAMPORTS_PER_SOL));
+        assert_eq!(parse_sol(".000000001"), Ok(1));
+    }
+
+    #[test]
+    fn test_parse_sol_rejects_extra_precision() {
+        assert_eq!(
+            parse_sol("0.0000000001"),
+            Err("SOL amount 0.0000000001 has more than 9 decimal places".to_string())
+        );
+        assert!(parse_sol("1.2.3").is_err());
+        assert!(parse_sol("-1").is_err());
+        assert!(parse_sol("99999999999").is_err());
+    }
+}
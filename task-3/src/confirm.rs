@@ -0,0 +1,94 @@
+use std::{future::Future, sync::Arc};
+use tokio::{
+    sync::{mpsc, Mutex},
+    task::JoinHandle,
+};
+use tracing::error;
+
+// Пул воркеров, разбирающих подписи из общего канала: подтверждения идут
+// параллельно, но не больше size одновременно. Отправка переводов не ждет подтверждений
+pub struct ConfirmPool {
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ConfirmPool {
+    pub fn spawn<T, F, Fut>(size: usize, rx: mpsc::Receiver<T>, confirm: F) -> Self
+    where
+        T: Send + 'static,
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let rx = Arc::new(Mutex::new(rx));
+        let confirm = Arc::new(confirm);
+
+        let workers = (0..size.max(1))
+            .map(|_| {
+                let (rx, confirm) = (Arc::clone(&rx), Arc::clone(&confirm));
+                tokio::spawn(async move {
+                    loop {
+                        // Блокировка держится только на время получения, не подтверждения
+                        let item = rx.lock().await.recv().await;
+                        match item {
+                            Some(item) => confirm(item).await,
+                            None => break,
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        ConfirmPool { workers }
+    }
+
+    // Ждет, пока воркеры разберут канал до закрытия
+    pub async fn join(self) {
+        for worker in self.workers {
+            if let Err(err) = worker.await {
+                error!("Confirmation worker failed: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::time::{sleep, Duration};
+
+    #[derive(Default)]
+    struct InFlight {
+        current: AtomicUsize,
+        max: AtomicUsize,
+        done: AtomicUsize,
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_confirms_concurrently_up_to_pool_size() {
+        let in_flight = Arc::new(InFlight::default());
+        let (tx, rx) = mpsc::channel(8);
+
+        let counter = Arc::clone(&in_flight);
+        let pool = ConfirmPool::spawn(3, rx, move |_signature: u64| {
+            let counter = Arc::clone(&counter);
+            async move {
+                let current = counter.current.fetch_add(1, Ordering::SeqCst) + 1;
+                counter.max.fetch_max(current, Ordering::SeqCst);
+                sleep(Duration::from_millis(100)).await;
+                counter.current.fetch_sub(1, Ordering::SeqCst);
+                counter.done.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        let started = tokio::time::Instant::now();
+        for signature in 0..7 {
+            tx.send(signature).await.unwrap();
+        }
+        drop(tx);
+        pool.join().await;
+
+        assert_eq!(in_flight.max.load(Ordering::SeqCst), 3);
+        assert_eq!(in_flight.done.load(Ordering::SeqCst), 7);
+        // Семь подтверждений по три за раз - три волны, а не семь
+        assert_eq!(started.elapsed(), Duration::from_millis(300));
+    }
+}
@@ -1,16 +1,47 @@
+use confirm::ConfirmPool;
+use explorer::{explorer_url, Cluster};
+use futures_util::Stream;
+use logging::{init_logging, LogFormat};
+use pacing::{Pacer, PacingMode};
+use reconnect::{Backoff, ReconnectBackoff};
+use resume::{load_last_slot, missed_slots, resume_slot, save_last_slot, ResumeConfig};
 use serde::Deserialize;
-use solana::{check_transaction_status, get_public_key, parse_bytes_from_string, send_sol};
-use solana_sdk::signature::Keypair;
+use solana::{
+    check_transaction_status, get_public_key, log_transaction_details, parse_bytes_from_string,
+    send_sol,
+};
+use solana_sdk::signature::{Keypair, Signature};
 use std::collections::HashMap;
-use std::{fs, sync::Arc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{
+    env, fs,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
 use yellowstone_grpc_client::GeyserGrpcClient;
+use yellowstone_grpc_proto::tonic::Status;
+mod amount;
+mod confirm;
+// Один модуль ссылок на эксплорер на все крейты, лежит в solana-rpc-transactions
+#[path = "../../solana-rpc-transactions/src/explorer.rs"]
+mod explorer;
+#[path = "../../solana-rpc-transactions/src/logging.rs"]
+mod logging;
+mod pacing;
+mod reconnect;
+mod resume;
 mod solana;
+mod watcher;
 use solana_sdk::pubkey::Pubkey;
 use tokio::sync::mpsc;
-use tokio::time::Instant;
-use yellowstone_grpc_proto::geyser::{SubscribeRequest, SubscribeRequestFilterBlocks};
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{error, info, warn};
+use watcher::{process_updates, WatchOptions, WatchSummary};
+use yellowstone_grpc_proto::geyser::{
+    SubscribeRequest, SubscribeRequestFilterBlocks, SubscribeUpdate,
+};
 
-use futures_util::StreamExt;
 use solana_client::rpc_client::RpcClient;
 
 static CONFIG_PATH: &str = "config.yaml";
@@ -23,58 +54,226 @@ struct Config {
     solana_rpc_url: String,
     gayser_rpc_url: String,
     geyser_x_token: String,
+    // Лампорты целым числом или SOL строкой: 2000000 или "0.002"
+    #[serde(deserialize_with = "amount::deserialize_lamports")]
     amount: u64,
+    #[serde(default)]
+    log_format: LogFormat,
+    // Минимальный интервал между переводами, 0 - без ограничения
+    #[serde(default)]
+    min_transfer_interval_ms: u64,
+    #[serde(default)]
+    pacing_mode: PacingMode,
+    // Интервал heartbeat-сообщений в логе, без него подписка молчит между обновлениями
+    #[serde(default)]
+    heartbeat_interval_secs: Option<u64>,
+    // Ограничение времени работы для тестовых прогонов, без него подписка бесконечна
+    #[serde(default)]
+    max_runtime_secs: Option<u64>,
+    #[serde(default)]
+    reconnect: ReconnectBackoff,
+    // При неудаче перевода запрашивать и выводить логи и compute units транзакции
+    #[serde(default)]
+    log_failed_transactions: bool,
+    // После перезапуска догоняем блоки, пропущенные с последнего обработанного слота
+    #[serde(default)]
+    resume: ResumeConfig,
+    // Кластер для ссылок на explorer, по умолчанию угадывается по solana_rpc_url
+    #[serde(default)]
+    explorer_cluster: Option<Cluster>,
+    // Сколько подтверждений может идти одновременно; 1 - по одному, как раньше
+    #[serde(default = "default_confirm_workers")]
+    confirm_workers: usize,
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config_content = fs::read_to_string(CONFIG_PATH).expect("Unable to read config file");
-    let config: Config = serde_yaml::from_str(&config_content).expect("Unable to parse config");
+fn default_confirm_workers() -> usize {
+    1
+}
 
-    let builder = match GeyserGrpcClient::build_from_shared(config.gayser_rpc_url) {
-        Ok(value) => value,
-        Err(err) => {
-            println!("Error occured {}", err);
-            panic!("Failed to build GeyserGrpcClient");
-        }
+// Строковые поля конфига, которые можно задать переменной окружения
+// Правило: если задано и в файле, и в окружении, побеждает окружение
+static ENV_OVERRIDES: &[(&str, &str)] = &[
+    ("sender_private_key", "SENDER_PRIVATE_KEY"),
+    ("sender_public_key", "SENDER_PUBLIC_KEY"),
+    ("recipient_wallet", "RECIPIENT_WALLET"),
+    ("solana_rpc_url", "SOLANA_RPC_URL"),
+    ("gayser_rpc_url", "GEYSER_RPC_URL"),
+    ("geyser_x_token", "GEYSER_X_TOKEN"),
+];
+
+fn load_config(
+    content: &str,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<Config, serde_yaml::Error> {
+    let mut values: serde_yaml::Mapping = match serde_yaml::from_str(content)? {
+        Some(values) => values,
+        None => serde_yaml::Mapping::new(),
     };
 
-    let mut client = builder
-        .x_token(Some(config.geyser_x_token))
-        .expect("Failed to add token")
+    for (field, variable) in ENV_OVERRIDES {
+        if let Some(value) = env(variable) {
+            values.insert((*field).into(), value.into());
+        }
+    }
+
+    let config: Config = serde_yaml::from_value(serde_yaml::Value::Mapping(values))?;
+    validate_wallets(&config)?;
+    Ok(config)
+}
+
+// Пустой отправитель или получатель дал бы подписку, которая никогда ничего не переведет
+fn validate_wallets(config: &Config) -> Result<(), serde_yaml::Error> {
+    let wallets = [
+        ("sender_private_key", &config.sender_private_key),
+        ("sender_public_key", &config.sender_public_key),
+        ("recipient_wallet", &config.recipient_wallet),
+    ];
+
+    for (field, value) in wallets {
+        if value.trim().is_empty() {
+            return Err(serde::de::Error::custom(format!(
+                "{} is empty, set it in {} or the environment",
+                field, CONFIG_PATH
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+// Подключение к Geyser и подписка на блоки с аккаунтом отправителя
+async fn subscribe(
+    url: &str,
+    x_token: &str,
+    account: &str,
+) -> Result<impl Stream<Item = Result<SubscribeUpdate, Status>> + Unpin, Box<dyn std::error::Error>>
+{
+    let mut client = GeyserGrpcClient::build_from_shared(url.to_string())?
+        .x_token(Some(x_token.to_string()))?
         .connect()
-        .await
-        .unwrap();
+        .await?;
+
     let mut blocks: HashMap<String, SubscribeRequestFilterBlocks> = HashMap::new();
     blocks.insert(
         "blocks".to_string(),
         SubscribeRequestFilterBlocks {
-            account_include: vec![config.sender_public_key],
+            account_include: vec![account.to_string()],
             ..SubscribeRequestFilterBlocks::default()
         },
     );
 
-    let mut request_filter: SubscribeRequest = SubscribeRequest::default();
-    request_filter.blocks = blocks;
-    let request = Some(request_filter);
-    let (_, mut stream) = client.subscribe_with_request(request).await.map_err(|e| {
-        eprintln!("Failed to subscribe: {:?}", e);
-        e
-    })?;
+    let request = SubscribeRequest {
+        blocks,
+        ..SubscribeRequest::default()
+    };
+    let (_, stream) = client.subscribe_with_request(Some(request)).await?;
+    Ok(stream)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Без файла конфиг целиком берется из окружения
+    let config_content = fs::read_to_string(CONFIG_PATH).unwrap_or_default();
+    let config = load_config(&config_content, |variable| env::var(variable).ok())
+        .expect("Unable to parse config");
+    init_logging(config.log_format);
 
-    let solana_rpc_client = RpcClient::new(config.solana_rpc_url);
+    let geyser_url = config.gayser_rpc_url.clone();
+    let geyser_x_token = config.geyser_x_token.clone();
+    let sender_public_key = config.sender_public_key.clone();
+
+    let cluster = config
+        .explorer_cluster
+        .unwrap_or_else(|| Cluster::from_rpc_url(&config.solana_rpc_url));
+    let solana_rpc_client = Arc::new(RpcClient::new(config.solana_rpc_url));
+    let backfill_client = Arc::clone(&solana_rpc_client);
+    let resume_config = config.resume.clone();
+    let slot_state_path = resume_config.slot_state_path.clone().map(PathBuf::from);
     let (tx, mut rx) = mpsc::channel::<String>(8);
     let tx_ref = Arc::new(tx);
 
-    let _task: tokio::task::JoinHandle<Result<(), ()>> = tokio::spawn(async move {
+    // Слот считается обработанным, когда перевод по нему подтвердился или был пропущен.
+    // Подтверждения завершаются не по порядку, поэтому сохраняем только больший слот
+    let last_processed = Arc::new(Mutex::new(0u64));
+    let mark_processed = move |slot: &str| {
+        let (Some(path), Ok(slot)) = (&slot_state_path, slot.parse::<u64>()) else {
+            return;
+        };
+        let mut last_processed = last_processed.lock().unwrap();
+        if slot <= *last_processed {
+            return;
+        }
+        match save_last_slot(path, slot) {
+            Ok(()) => *last_processed = slot,
+            Err(err) => warn!(
+                "Failed to save slot {} to {}: {}",
+                slot,
+                path.display(),
+                err
+            ),
+        }
+    };
+
+    // Неудачный перевод, как и раньше, останавливает новые отправки
+    let stopped = Arc::new(AtomicBool::new(false));
+    let (confirm_tx, confirm_rx) = mpsc::channel::<(String, Signature)>(8);
+    let confirm_pool = {
+        let (client, stopped, mark_processed) = (
+            Arc::clone(&solana_rpc_client),
+            Arc::clone(&stopped),
+            mark_processed.clone(),
+        );
+        let log_failed_transactions = config.log_failed_transactions;
+        ConfirmPool::spawn(
+            config.confirm_workers,
+            confirm_rx,
+            move |(slot, signature): (String, Signature)| {
+                let (client, stopped, mark_processed) = (
+                    Arc::clone(&client),
+                    Arc::clone(&stopped),
+                    mark_processed.clone(),
+                );
+                async move {
+                    match check_transaction_status(&client, &signature).await {
+                        Ok(_) => mark_processed(&slot),
+                        Err(err) => {
+                            error!("Error sending transaction {}", err);
+                            if log_failed_transactions {
+                                log_transaction_details(&client, &signature);
+                            }
+                            stopped.store(true, Ordering::SeqCst);
+                        }
+                    }
+                }
+            },
+        )
+    };
+
+    // Задача переводов возвращает, сколько переводов успела отправить
+    let transfers: tokio::task::JoinHandle<usize> = tokio::spawn(async move {
         let bytes =
             parse_bytes_from_string(&config.sender_private_key).expect("Failed to convert bytes");
         let sender_private_key = Keypair::from_bytes(&bytes).expect("Failed to parse private key");
         let receiver_public_key: Pubkey = get_public_key(&config.recipient_wallet);
+        let mut pacer = Pacer::new(
+            Duration::from_millis(config.min_transfer_interval_ms),
+            config.pacing_mode,
+        );
+        let mut sent = 0;
 
-        Ok(loop {
+        loop {
             match rx.recv().await {
-                Some(_) => {
+                Some(slot) => {
+                    if stopped.load(Ordering::SeqCst) {
+                        return sent;
+                    }
+
+                    if !pacer.admit().await {
+                        info!("Transfer skipped, minimum interval has not passed yet");
+                        mark_processed(&slot);
+                        continue;
+                    }
+
                     let start_time = Instant::now();
 
                     match send_sol(
@@ -87,47 +286,340 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     {
                         Ok(signature) => {
                             let duration = start_time.elapsed();
+                            sent += 1;
 
-                            println!("Transaction Hash: {:?}, Time: {:?}", signature, duration);
+                            println!(
+                                "Transaction Hash: {:?}, Time: {:?}, Explorer: {}",
+                                signature,
+                                duration,
+                                explorer_url(&signature, cluster)
+                            );
 
-                            match check_transaction_status(&solana_rpc_client, &signature).await {
-                                Ok(_) => (),
-                                Err(err) => {
-                                    println!("Error sending transaction {}", err);
-                                    return Ok(());
-                                }
+                            if confirm_tx.send((slot, signature)).await.is_err() {
+                                error!("Confirmation workers stopped, no more transfers");
+                                return sent;
                             }
                         }
                         Err(e) => {
-                            println!("Error sending from wallet transaction",);
-                            return Ok(());
+                            error!("Error sending from wallet transaction: {}", e);
+                            if let (true, Some(signature)) =
+                                (config.log_failed_transactions, e.signature)
+                            {
+                                log_transaction_details(&solana_rpc_client, &signature);
+                            }
+                            return sent;
                         }
                     }
                 }
                 None => {
-                    println!("Channel closed, no more messages to receive.");
+                    info!("Channel closed, no more messages to receive.");
                     break;
                 }
             }
-        })
+        }
+        sent
     });
 
-    while let Some(update) = stream.next().await {
-        match update {
-            Ok(data) => {
-                println!("Update from subscribtion, {:?}", data.update_oneof);
-                let tx_ref = Arc::clone(&tx_ref);
+    backfill_missed_slots(
+        &backfill_client,
+        &resume_config,
+        &sender_public_key,
+        &tx_ref,
+    )
+    .await;
 
-                tokio::spawn(async move {
-                    tx_ref.send("".to_string()).await;
-                });
-            }
-            Err(e) => {
-                eprintln!("Error receiving update: {:?}", e);
-                break;
+    // При обрыве подписки переподключаемся с растущей паузой, пока не истечет max_runtime
+    let started = Instant::now();
+    let max_runtime = config.max_runtime_secs.map(Duration::from_secs);
+    let mut backoff = Backoff::new(config.reconnect);
+    let mut summary = WatchSummary::default();
+
+    loop {
+        let remaining =
+            max_runtime.map(|max_runtime| max_runtime.saturating_sub(started.elapsed()));
+        if remaining == Some(Duration::ZERO) {
+            summary.deadline_reached = true;
+            break;
+        }
+
+        let connected_at = Instant::now();
+        match subscribe(&geyser_url, &geyser_x_token, &sender_public_key).await {
+            Ok(stream) => {
+                let options = WatchOptions {
+                    heartbeat: config.heartbeat_interval_secs.map(Duration::from_secs),
+                    max_runtime: remaining,
+                };
+                let connection = process_updates(stream, Arc::clone(&tx_ref), options).await;
+                summary.updates_seen += connection.updates_seen;
+                summary.triggers_fired += connection.triggers_fired;
+                summary.triggers_delivered += connection.triggers_delivered;
+                if connection.stream_error.is_some() {
+                    summary.stream_error = connection.stream_error;
+                }
+                if connection.deadline_reached {
+                    summary.deadline_reached = true;
+                    break;
+                }
+                backoff.connection_ended(connected_at.elapsed());
             }
+            Err(err) => error!("Failed to subscribe: {}", err),
         }
+
+        // Пауза не должна пережить max_runtime
+        let delay = match remaining {
+            Some(remaining) => backoff.next_delay().min(remaining),
+            None => backoff.next_delay(),
+        };
+        warn!("Subscription lost, reconnecting in {:?}", delay);
+        sleep(delay).await;
     }
 
+    // Закрываем канал, чтобы задача переводов завершилась после уже запущенных
+    drop(tx_ref);
+    let transfers_sent = match transfers.await {
+        Ok(sent) => sent,
+        Err(err) => {
+            error!("Transfer task failed: {}", err);
+            0
+        }
+    };
+    // Задача переводов закрыла канал подписей, дожидаемся уже отправленных
+    confirm_pool.join().await;
+    info!("{}", final_summary(&summary, transfers_sent));
+
     Ok(())
 }
+
+// Итог печатается при любом завершении: по max_runtime, после ошибки или конца стрима
+fn final_summary(summary: &WatchSummary, transfers_sent: usize) -> String {
+    let mut line = format!(
+        "Subscription ended after {} updates, {} transfers triggered ({} delivered), {} sent",
+        summary.updates_seen, summary.triggers_fired, summary.triggers_delivered, transfers_sent
+    );
+    if summary.deadline_reached {
+        line.push_str(", max runtime reached");
+    }
+    if let Some(err) = &summary.stream_error {
+        line.push_str(&format!(", last stream error: {}", err));
+    }
+    line
+}
+
+// Блоки с аккаунтом, пропущенные пока процесс не работал, отправляем в очередь переводов
+// до подписки, как если бы они пришли из Geyser
+async fn backfill_missed_slots(
+    client: &RpcClient,
+    config: &ResumeConfig,
+    account: &str,
+    trigger: &mpsc::Sender<String>,
+) {
+    let Some(path) = &config.slot_state_path else {
+        return;
+    };
+    let saved = load_last_slot(path.as_ref());
+    let tip = match client.get_slot() {
+        Ok(tip) => tip,
+        Err(err) => {
+            warn!("Failed to read current slot, not resuming: {}", err);
+            return;
+        }
+    };
+    let Some(from_slot) = resume_slot(saved, tip, config.max_resume_slots) else {
+        return;
+    };
+    let account = match Pubkey::from_str(account) {
+        Ok(account) => account,
+        Err(err) => {
+            warn!("Invalid sender public key, not resuming: {}", err);
+            return;
+        }
+    };
+
+    match missed_slots(client, &account, from_slot) {
+        Ok(slots) => {
+            info!(
+                "Resuming after slot {}, {} missed blocks",
+                from_slot,
+                slots.len()
+            );
+            for slot in slots {
+                if trigger.send(slot.to_string()).await.is_err() {
+                    break;
+                }
+            }
+        }
+        Err(err) => warn!(
+            "Failed to find missed blocks after slot {}: {}",
+            from_slot, err
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_client::rpc_request::RpcRequest;
+
+    static CONFIG: &str = "
+sender_private_key: '[1, 2, 3]'
+sender_public_key: sender
+recipient_wallet: recipient
+solana_rpc_url: http://file-rpc
+gayser_rpc_url: http://file-geyser
+amount: 1000
+";
+
+    fn env_from(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    // Слот на диске, текущий слот и подписи аккаунта как их видит мок RPC
+    async fn backfilled_slots(saved: u64, tip: u64) -> Vec<String> {
+        let path = env::temp_dir().join(format!("slot-{}", Pubkey::new_unique()));
+        save_last_slot(&path, saved).unwrap();
+        let config = ResumeConfig {
+            slot_state_path: Some(path.display().to_string()),
+            max_resume_slots: 9_000,
+        };
+        let signature = |slot: u64| {
+            serde_json::json!({
+                "signature": Signature::new_unique().to_string(),
+                "slot": slot,
+                "err": null,
+                "memo": null,
+                "blockTime": null
+            })
+        };
+        let mocks = HashMap::from([
+            (RpcRequest::GetSlot, serde_json::json!(tip)),
+            (
+                RpcRequest::GetSignaturesForAddress,
+                serde_json::json!([signature(140), signature(120), signature(100)]),
+            ),
+        ]);
+        let client = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+        let (trigger, mut triggered) = mpsc::channel(10);
+
+        backfill_missed_slots(
+            &client,
+            &config,
+            &Pubkey::new_unique().to_string(),
+            &trigger,
+        )
+        .await;
+        fs::remove_file(&path).unwrap();
+        drop(trigger);
+
+        let mut slots = Vec::new();
+        while let Some(slot) = triggered.recv().await {
+            slots.push(slot);
+        }
+        slots
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_startup_backfills_blocks_after_saved_slot() {
+        assert_eq!(backfilled_slots(100, 150).await, vec!["120", "140"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_startup_skips_backfill_for_too_old_slot() {
+        assert!(backfilled_slots(100, 20_000).await.is_empty());
+    }
+
+    #[test]
+    fn test_final_summary_reports_partial_results() {
+        let summary = WatchSummary {
+            updates_seen: 7,
+            triggers_fired: 3,
+            triggers_delivered: 2,
+            stream_error: Some("\"connection reset\"".to_string()),
+            ..WatchSummary::default()
+        };
+
+        assert_eq!(
+            final_summary(&summary, 1),
+            "Subscription ended after 7 updates, 3 transfers triggered (2 delivered), 1 sent, \
+             last stream error: \"connection reset\""
+        );
+    }
+
+    #[test]
+    fn test_env_overrides_file_value() {
+        let config = load_config(
+            CONFIG,
+            env_from(&[
+                ("SOLANA_RPC_URL", "http://env-rpc"),
+                ("GEYSER_X_TOKEN", "secret"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.solana_rpc_url, "http://env-rpc");
+        assert_eq!(config.gayser_rpc_url, "http://file-geyser");
+        assert_eq!(config.geyser_x_token, "secret");
+        assert_eq!(config.amount, 1000);
+    }
+
+    #[test]
+    fn test_missing_field_without_env_fails() {
+        let err = load_config(CONFIG, env_from(&[])).unwrap_err();
+
+        assert!(err.to_string().contains("geyser_x_token"), "{}", err);
+    }
+
+    #[test]
+    fn test_config_can_come_entirely_from_env() {
+        let config = load_config(
+            "amount: 5",
+            env_from(&[
+                ("SENDER_PRIVATE_KEY", "[1]"),
+                ("SENDER_PUBLIC_KEY", "sender"),
+                ("RECIPIENT_WALLET", "recipient"),
+                ("SOLANA_RPC_URL", "http://env-rpc"),
+                ("GEYSER_RPC_URL", "http://env-geyser"),
+                ("GEYSER_X_TOKEN", "secret"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.gayser_rpc_url, "http://env-geyser");
+        assert_eq!(config.recipient_wallet, "recipient");
+    }
+
+    #[test]
+    fn test_amount_can_be_given_in_sol() {
+        let config = load_config(
+            &CONFIG.replace("amount: 1000", "amount: '0.002'"),
+            env_from(&[("GEYSER_X_TOKEN", "secret")]),
+        )
+        .unwrap();
+
+        assert_eq!(config.amount, 2_000_000);
+
+        let err = load_config(
+            &CONFIG.replace("amount: 1000", "amount: 0.002"),
+            env_from(&[("GEYSER_X_TOKEN", "secret")]),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("quoted string"), "{}", err);
+    }
+
+    #[test]
+    fn test_empty_sender_fails_early() {
+        let err = load_config(
+            CONFIG,
+            env_from(&[("SENDER_PUBLIC_KEY", " "), ("GEYSER_X_TOKEN", "secret")]),
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "sender_public_key is empty, set it in config.yaml or the environment"
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use serde::Deserialize;
+use tokio::time::{sleep_until, Duration, Instant};
+
+// Что делать с переводом, пришедшим раньше, чем прошел интервал
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PacingMode {
+    // Дождаться конца интервала и выполнить
+    #[default]
+    Queue,
+    // Пропустить перевод
+    Drop,
+}
+
+// Не дает выполнять переводы чаще одного раза за interval
+pub struct Pacer {
+    interval: Duration,
+    mode: PacingMode,
+    last_transfer: Option<Instant>,
+}
+
+impl Pacer {
+    pub fn new(interval: Duration, mode: PacingMode) -> Self {
+        Pacer {
+            interval,
+            mode,
+            last_transfer: None,
+        }
+    }
+
+    // Возвращает true, если перевод можно выполнять сейчас
+    pub async fn admit(&mut self) -> bool {
+        if let Some(last_transfer) = self.last_transfer {
+            let next_allowed = last_transfer + self.interval;
+
+            if Instant::now() < next_allowed {
+                match self.mode {
+                    PacingMode::Queue => sleep_until(next_allowed).await,
+                    PacingMode::Drop => return false,
+                }
+            }
+        }
+
+        self.last_transfer = Some(Instant::now());
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    async fn flood(mode: PacingMode, triggers: usize) -> Vec<Instant> {
+        let (tx, mut rx) = mpsc::channel(triggers);
+        for _ in 0..triggers {
+            tx.send(String::new()).await.unwrap();
+        }
+        drop(tx);
+
+        let mut pacer = Pacer::new(Duration::from_millis(100), mode);
+        let mut transfers = Vec::new();
+        while rx.recv().await.is_some() {
+            if pacer.admit().await {
+                transfers.push(Instant::now());
+            }
+        }
+
+        transfers
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_queued_transfers_are_spaced_by_interval() {
+        let transfers = flood(PacingMode::Queue, 5).await;
+
+        assert_eq!(transfers.len(), 5);
+        for pair in transfers.windows(2) {
+            assert!(pair[1] - pair[0] >= Duration::from_millis(100));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_excess_transfers_are_dropped() {
+        assert_eq!(flood(PacingMode::Drop, 5).await.len(), 1);
+
+        let mut pacer = Pacer::new(Duration::from_millis(100), PacingMode::Drop);
+        assert!(pacer.admit().await);
+        assert!(!pacer.admit().await);
+        tokio::time::advance(Duration::from_millis(100)).await;
+        assert!(pacer.admit().await);
+    }
+}
@@ -0,0 +1,105 @@
+use rand::Rng;
+use serde::Deserialize;
+use tokio::time::Duration;
+
+// Пауза перед переподключением к Geyser, растет при каждой неудаче подряд
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ReconnectBackoff {
+    pub initial_ms: u64,
+    pub max_ms: u64,
+    pub multiplier: f64,
+    // Доля паузы, на которую она случайно сдвигается в обе стороны, 0 - без разброса
+    pub jitter: f64,
+    // Соединение, прожившее столько, считается стабильным и сбрасывает паузу
+    pub stable_after_secs: u64,
+}
+
+impl Default for ReconnectBackoff {
+    fn default() -> Self {
+        ReconnectBackoff {
+            initial_ms: 500,
+            max_ms: 30_000,
+            multiplier: 2.0,
+            jitter: 0.2,
+            stable_after_secs: 60,
+        }
+    }
+}
+
+pub struct Backoff {
+    config: ReconnectBackoff,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(config: ReconnectBackoff) -> Self {
+        Backoff {
+            config,
+            current: Duration::from_millis(config.initial_ms),
+        }
+    }
+
+    // Пауза перед следующей попыткой; следующая будет длиннее, но не больше max_ms
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        let max = Duration::from_millis(self.config.max_ms);
+        self.current = delay.mul_f64(self.config.multiplier.max(1.0)).min(max);
+
+        let jitter = self.config.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        delay.mul_f64(rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter))
+    }
+
+    // Короткое соединение продолжает серию неудач, стабильное ее сбрасывает
+    pub fn connection_ended(&mut self, uptime: Duration) {
+        if uptime >= Duration::from_secs(self.config.stable_after_secs) {
+            self.current = Duration::from_millis(self.config.initial_ms);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_and_resets_after_stable_connection() {
+        let mut backoff = Backoff::new(ReconnectBackoff {
+            initial_ms: 100,
+            max_ms: 500,
+            multiplier: 2.0,
+            jitter: 0.0,
+            stable_after_secs: 60,
+        });
+
+        let delays: Vec<u64> = (0..4)
+            .map(|_| backoff.next_delay().as_millis() as u64)
+            .collect();
+        assert_eq!(delays, vec![100, 200, 400, 500]);
+
+        backoff.connection_ended(Duration::from_secs(5));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(500));
+
+        backoff.connection_ended(Duration::from_secs(60));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(100));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_jitter_stays_within_bounds() {
+        let mut backoff = Backoff::new(ReconnectBackoff {
+            initial_ms: 1_000,
+            max_ms: 1_000,
+            jitter: 0.2,
+            ..ReconnectBackoff::default()
+        });
+
+        for _ in 0..100 {
+            let delay = backoff.next_delay();
+            assert!(delay >= Duration::from_millis(800) && delay <= Duration::from_millis(1_200));
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use serde::Deserialize;
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::{collections::BTreeSet, error::Error, fs, io, path::Path, str::FromStr};
+use tracing::warn;
+
+// Сколько подписей getSignaturesForAddress отдает за один запрос
+const SIGNATURES_PAGE: usize = 1000;
+
+// Где хранить последний обработанный слот и насколько старый слот еще догоняем
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ResumeConfig {
+    pub slot_state_path: Option<String>,
+    pub max_resume_slots: u64,
+}
+
+impl Default for ResumeConfig {
+    fn default() -> Self {
+        ResumeConfig {
+            slot_state_path: None,
+            // Около часа слотов по 400 мс
+            max_resume_slots: 9_000,
+        }
+    }
+}
+
+// Пустой или поврежденный файл считается отсутствующим: начинаем с текущего слота
+pub fn load_last_slot(path: &Path) -> Option<u64> {
+    let content = fs::read_to_string(path).ok()?;
+    match content.trim().parse() {
+        Ok(slot) => Some(slot),
+        Err(_) => {
+            warn!("Ignoring unreadable slot state in {}", path.display());
+            None
+        }
+    }
+}
+
+// Пишем во временный файл и переименовываем, чтобы при падении не остался обрывок
+pub fn save_last_slot(path: &Path, slot: u64) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, slot.to_string())?;
+    fs::rename(tmp, path)
+}
+
+// Слот, после которого нужно догнать пропущенные блоки; None - начинаем с текущего
+pub fn resume_slot(saved: Option<u64>, tip: u64, max_resume_slots: u64) -> Option<u64> {
+    let saved = saved.filter(|saved| *saved < tip)?;
+    if tip - saved > max_resume_slots {
+        warn!(
+            "Saved slot {} is {} slots behind the tip, older than {}; resuming from the tip",
+            saved,
+            tip - saved,
+            max_resume_slots
+        );
+        return None;
+    }
+    Some(saved)
+}
+
+// В yellowstone-grpc 1.12 из Cargo.toml у SubscribeRequest нет from_slot, поэтому пропущенные блоки с аккаунтом
+// находим через getSignaturesForAddress: слоты его транзакций после from_slot по возрастанию
+pub fn missed_slots(
+    client: &RpcClient,
+    account: &Pubkey,
+    from_slot: u64,
+) -> Result<Vec<u64>, Box<dyn Error + Send + Sync>> {
+    let mut slots = BTreeSet::new();
+    let mut before: Option<Signature> = None;
+
+    loop {
+        let page = client.get_signatures_for_address_with_config(
+            account,
+            GetConfirmedSignaturesForAddress2Config {
+                before,
+                until: None,
+                limit: Some(SIGNATURES_PAGE),
+                commitment: Some(client.commitment()),
+            },
+        )?;
+
+        let reached_saved = page.iter().any(|status| status.slot <= from_slot);
+        slots.extend(
+            page.iter()
+                .map(|status| status.slot)
+                .filter(|slot| *slot > from_slot),
+        );
+
+        // Подписи идут от новых к старым: дошли до сохраненного слота или до конца истории
+        if reached_saved || page.len() < SIGNATURES_PAGE {
+            break;
+        }
+        before = match page.last() {
+            Some(status) => Some(Signature::from_str(&status.signature)?),
+            None => break,
+        };
+    }
+
+    Ok(slots.into_iter().collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_missed_slots_are_after_saved_slot() {
+        let path = std::env::temp_dir().join(format!("slot-{}", Pubkey::new_unique()));
+        assert_eq!(load_last_slot(&path), None);
+        save_last_slot(&path, 100).unwrap();
+        let saved = load_last_slot(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(saved, Some(100));
+
+        let from_slot = resume_slot(saved, 150, 9_000).expect("Slot is recent enough");
+        let signature = |slot: u64| {
+            json!({
+                "signature": Signature::new_unique().to_string(),
+                "slot": slot,
+                "err": null,
+                "memo": null,
+                "blockTime": null
+            })
+        };
+        let mocks = HashMap::from([(
+            RpcRequest::GetSignaturesForAddress,
+            json!([
+                signature(140),
+                signature(120),
+                signature(120),
+                signature(100)
+            ]),
+        )]);
+        let client = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+
+        assert_eq!(
+            missed_slots(&client, &Pubkey::new_unique(), from_slot).unwrap(),
+            vec![120, 140]
+        );
+    }
+
+    #[test]
+    fn test_too_old_slot_resumes_from_tip() {
+        assert_eq!(resume_slot(Some(100), 200, 50), None);
+        assert_eq!(resume_slot(Some(100), 120, 50), Some(100));
+        assert_eq!(resume_slot(Some(300), 200, 50), None);
+        assert_eq!(resume_slot(None, 200, 50), None);
+    }
+}
@@ -1,83 +1,234 @@
-use solana_client::{client_error::ClientError, rpc_client::RpcClient};
-use solana_sdk::{
-    pubkey::Pubkey,
-    signature::{Keypair, Signature},
-    signer::Signer,
-    system_instruction,
-    transaction::Transaction,
-};
-use std::str::FromStr;
-
-// Отправка транзакции
-pub async fn send_sol(
-    client: &RpcClient,
-    sender: &Keypair,
-    receiver: &Pubkey,
-    amount: u64,
-) -> Result<Signature, Box<dyn std::error::Error + Send + Sync>> {
-    let instruction = system_instruction::transfer(&sender.pubkey(), receiver, amount);
-    let recent_blockhash = client
-        .get_latest_blockhash()
-        .expect("Cannot get latest blockhash");
-
-    let transaction = Transaction::new_signed_with_payer(
-        &[instruction],
-        Some(&sender.pubkey()),
-        &[sender],
-        recent_blockhash,
-    );
-
-    let signature = client.send_and_confirm_transaction(&transaction)?;
-
-    Ok(signature)
-}
-
-// Проверка статуса транзакции
-pub async fn check_transaction_status(
-    client: &RpcClient,
-    signature: &Signature,
-) -> Result<(), ClientError> {
-    return match client.get_signature_status(signature) {
-        Ok(value) => match value {
-            Some(value) => match value {
-                Ok(_) => Ok(()),
-                Err(err) => {
-                    println!("Transaction error!");
-                    Err(err.into())
-                }
-            },
-            None => return Ok(()),
-        },
-        Err(err) => {
-            println!("Transaction error!");
-            Err(err.into())
-        }
-    };
-}
-
-#[inline(always)]
-pub fn get_public_key(public_key: &str) -> Pubkey {
-    return Pubkey::from_str(&public_key).expect("Failed to parse public key");
-}
-
-#[inline(always)]
-pub fn parse_bytes_from_string(input: &str) -> Result<Vec<u8>, String> {
-    let trimmed = input.trim_matches(['[', ']'].as_ref());
-    let result: Result<Vec<u8>, _> = trimmed
-        .split(',')
-        .map(|s| {
-            s.trim()
-                .parse::<u16>()
-                .map_err(|e| format!("Failed to parse number: {}", e))
-                .and_then(|num| {
-                    if num > 255 {
-                        Err(format!("Number {} out of byte range", num))
-                    } else {
-                        Ok(num as u8)
-                    }
-                })
-        })
-        .collect();
-
-    result
-}
+use solana_client::{
+    client_error::ClientError, rpc_client::RpcClient, rpc_config::RpcTransactionConfig,
+};
+use solana_sdk::{
+    pubkey::Pubkey, signature::Signature, signer::Signer, system_instruction,
+    transaction::Transaction,
+};
+use std::{error::Error, fmt, str::FromStr};
+use tracing::{error, warn};
+
+// Неудачный перевод; подпись есть, если транзакция успела уйти в сеть
+#[derive(Debug)]
+pub struct TransferFailure {
+    pub signature: Option<Signature>,
+    pub error: Box<dyn Error + Send + Sync>,
+}
+
+impl fmt::Display for TransferFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.signature {
+            Some(signature) => write!(f, "{} (signature {})", self.error, signature),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl Error for TransferFailure {}
+
+// Подробности исполнения транзакции из getTransaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionDetails {
+    pub err: Option<String>,
+    pub logs: Vec<String>,
+    pub compute_units: Option<u64>,
+}
+
+// Отправка транзакции, подписать может любой Signer
+pub async fn send_sol<S: Signer>(
+    client: &RpcClient,
+    sender: &S,
+    receiver: &Pubkey,
+    amount: u64,
+) -> Result<Signature, TransferFailure> {
+    let instruction = system_instruction::transfer(&sender.pubkey(), receiver, amount);
+    let recent_blockhash = client
+        .get_latest_blockhash()
+        .expect("Cannot get latest blockhash");
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&sender.pubkey()),
+        &[sender],
+        recent_blockhash,
+    );
+
+    client
+        .send_and_confirm_transaction(&transaction)
+        .map_err(|err| TransferFailure {
+            signature: Some(transaction.signatures[0]),
+            error: err.into(),
+        })
+}
+
+pub fn fetch_transaction_details(
+    client: &RpcClient,
+    signature: &Signature,
+) -> Result<TransactionDetails, Box<dyn Error + Send + Sync>> {
+    let transaction = client.get_transaction_with_config(
+        signature,
+        RpcTransactionConfig {
+            encoding: None,
+            commitment: Some(client.commitment()),
+            max_supported_transaction_version: Some(0),
+        },
+    )?;
+    let meta = transaction
+        .transaction
+        .meta
+        .ok_or("Transaction has no status meta")?;
+
+    Ok(TransactionDetails {
+        err: meta.err.map(|err| err.to_string()),
+        logs: Option::<Vec<String>>::from(meta.log_messages).unwrap_or_default(),
+        compute_units: Option::<u64>::from(meta.compute_units_consumed),
+    })
+}
+
+// Выводит ошибку, логи программы и потраченные compute units упавшей транзакции
+pub fn log_transaction_details(client: &RpcClient, signature: &Signature) {
+    let details = match fetch_transaction_details(client, signature) {
+        Ok(details) => details,
+        Err(err) => {
+            warn!("Transaction {} details are unavailable: {}", signature, err);
+            return;
+        }
+    };
+
+    error!(
+        "Transaction {} failed: {}, compute units: {}",
+        signature,
+        details.err.as_deref().unwrap_or("no error recorded"),
+        details
+            .compute_units
+            .map_or("unknown".to_string(), |units| units.to_string())
+    );
+    for line in &details.logs {
+        error!("  {}", line);
+    }
+}
+
+// Проверка статуса транзакции
+pub async fn check_transaction_status(
+    client: &RpcClient,
+    signature: &Signature,
+) -> Result<(), ClientError> {
+    match client.get_signature_status(signature) {
+        Ok(value) => match value {
+            Some(value) => match value {
+                Ok(_) => Ok(()),
+                Err(err) => {
+                    error!("Transaction error!");
+                    Err(err.into())
+                }
+            },
+            None => Ok(()),
+        },
+        Err(err) => {
+            error!("Transaction error!");
+            Err(err)
+        }
+    }
+}
+
+#[inline(always)]
+pub fn get_public_key(public_key: &str) -> Pubkey {
+    Pubkey::from_str(public_key).expect("Failed to parse public key")
+}
+
+#[inline(always)]
+pub fn parse_bytes_from_string(input: &str) -> Result<Vec<u8>, String> {
+    let trimmed = input.trim_matches(['[', ']'].as_ref());
+    let result: Result<Vec<u8>, _> = trimmed
+        .split(',')
+        .enumerate()
+        .map(|(index, s)| {
+            let token = s.trim();
+            token
+                .parse::<u16>()
+                .map_err(|e| {
+                    format!(
+                        "Failed to parse number at index {} ({:?}): {}",
+                        index, token, e
+                    )
+                })
+                .and_then(|num| {
+                    if num > 255 {
+                        Err(format!(
+                            "Number {} at index {} out of byte range",
+                            num, index
+                        ))
+                    } else {
+                        Ok(num as u8)
+                    }
+                })
+        })
+        .collect();
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::logging::{BufferWriter, LogFormat};
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::signature::Keypair;
+    use std::collections::HashMap;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_failed_transfer_logs_transaction_details() {
+        let transaction_error = json!({ "InstructionError": [0, "UninitializedAccount"] });
+        let mocks = HashMap::from([(
+            RpcRequest::GetTransaction,
+            json!({
+                "slot": 2,
+                "transaction": ["", "base64"],
+                "meta": {
+                    "err": transaction_error,
+                    "status": { "Err": transaction_error },
+                    "fee": 5000,
+                    "preBalances": [],
+                    "postBalances": [],
+                    "logMessages": [
+                        "Program 11111111111111111111111111111111 invoke [1]",
+                        "Program 11111111111111111111111111111111 failed: uninitialized account"
+                    ],
+                    "computeUnitsConsumed": 150
+                },
+                "blockTime": null
+            }),
+        )]);
+        // Мок с таким url отвечает на getSignatureStatuses ошибкой инструкции
+        let client = RpcClient::new_mock_with_mocks("instruction_error".to_string(), mocks);
+        let buffer = BufferWriter::default();
+        let _guard = tracing::subscriber::set_default(buffer.subscriber(LogFormat::Plain));
+
+        let failure = send_sol(&client, &Keypair::new(), &Pubkey::new_unique(), 1)
+            .await
+            .unwrap_err();
+        log_transaction_details(&client, &failure.signature.unwrap());
+
+        let output = buffer.contents();
+        assert!(output.contains("compute units: 150"), "{}", output);
+        assert!(
+            output.contains("failed: uninitialized account"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn test_parse_bytes_reports_bad_element_index() {
+        let mut elements: Vec<String> = (0..64).map(|i| i.to_string()).collect();
+        elements[30] = "3o".to_string();
+        let input = format!("[{}]", elements.join(", "));
+
+        let err = parse_bytes_from_string(&input).unwrap_err();
+
+        assert!(err.contains("index 30"), "unexpected error: {}", err);
+        assert!(err.contains("\"3o\""), "unexpected error: {}", err);
+        assert_eq!(parse_bytes_from_string("[1, 2, 255]"), Ok(vec![1, 2, 255]));
+    }
+}
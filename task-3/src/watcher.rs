@@ -0,0 +1,299 @@
+use futures_util::{Stream, StreamExt};
+use std::{fmt::Debug, sync::Arc};
+use tokio::time::{interval_at, sleep_until, Duration, Instant, Interval, MissedTickBehavior};
+use tokio::{sync::mpsc, task::JoinSet};
+use tracing::{debug, error, info};
+use yellowstone_grpc_proto::geyser::{subscribe_update::UpdateOneof, SubscribeUpdate};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WatchSummary {
+    pub updates_seen: usize,
+    pub triggers_fired: usize,
+    // Сколько триггеров дошло до задачи переводов к моменту возврата
+    pub triggers_delivered: usize,
+    pub stream_error: Option<String>,
+    pub heartbeats: usize,
+    // Подписка остановлена по max_runtime, а не закончилась сама
+    pub deadline_reached: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WatchOptions {
+    // Раз в этот интервал пишем в лог, что подписка жива
+    pub heartbeat: Option<Duration>,
+    // Через это время подписка останавливается, даже если обновления еще идут
+    pub max_runtime: Option<Duration>,
+}
+
+// Читаем обновления подписки и на каждый блок с нашим аккаунтом запускаем перевод.
+// Пинги и прочие обновления только учитываются, источник стрима может быть любым.
+// Итог возвращается, как бы ни закончился стрим, но только после отправки всех триггеров
+pub async fn process_updates<S, E>(
+    mut stream: S,
+    trigger: Arc<mpsc::Sender<String>>,
+    options: WatchOptions,
+) -> WatchSummary
+where
+    S: Stream<Item = Result<SubscribeUpdate, E>> + Unpin,
+    E: Debug,
+{
+    let mut summary = WatchSummary::default();
+    let mut last_trigger: Option<Instant> = None;
+    let mut in_flight = JoinSet::new();
+    let deadline = options
+        .max_runtime
+        .map(|max_runtime| Instant::now() + max_runtime);
+    let mut heartbeat = options.heartbeat.map(|period| {
+        let mut heartbeat = interval_at(Instant::now() + period, period);
+        heartbeat.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        heartbeat
+    });
+
+    loop {
+        tokio::select! {
+            update = stream.next() => match update {
+                Some(Ok(data)) => {
+                    summary.updates_seen += 1;
+
+                    match data.update_oneof {
+                        Some(UpdateOneof::Block(block)) => {
+                            info!("Block update from subscribtion, slot {}", block.slot);
+                            summary.triggers_fired += 1;
+                            last_trigger = Some(Instant::now());
+                            let trigger = Arc::clone(&trigger);
+
+                            in_flight.spawn(async move {
+                                trigger.send(block.slot.to_string()).await
+                            });
+                        }
+                        other => debug!("Update from subscribtion, {:?}", other),
+                    }
+                }
+                Some(Err(e)) => {
+                    error!("Error receiving update: {:?}", e);
+                    summary.stream_error = Some(format!("{:?}", e));
+                    break;
+                }
+                None => break,
+            },
+            _ = next_deadline(deadline) => {
+                info!("Max runtime reached, stopping subscription");
+                summary.deadline_reached = true;
+                break;
+            },
+            _ = next_heartbeat(&mut heartbeat) => {
+                summary.heartbeats += 1;
+                match last_trigger {
+                    Some(at) => info!(
+                        "Heartbeat: {} updates seen, {} triggers fired, last trigger {:?} ago",
+                        summary.updates_seen,
+                        summary.triggers_fired,
+                        at.elapsed()
+                    ),
+                    None => info!(
+                        "Heartbeat: {} updates seen, no triggers fired yet",
+                        summary.updates_seen
+                    ),
+                }
+            }
+        }
+    }
+
+    while let Some(sent) = in_flight.join_next().await {
+        match sent {
+            Ok(Ok(())) => summary.triggers_delivered += 1,
+            Ok(Err(err)) => error!("Failed to trigger transfer: {}", err),
+            Err(err) => error!("Trigger task failed: {}", err),
+        }
+    }
+
+    summary
+}
+
+// Без heartbeat эта ветка select! никогда не срабатывает
+async fn next_heartbeat(heartbeat: &mut Option<Interval>) {
+    match heartbeat {
+        Some(heartbeat) => {
+            heartbeat.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+async fn next_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_util::stream;
+    use yellowstone_grpc_proto::geyser::{SubscribeUpdateBlock, SubscribeUpdatePing};
+
+    fn block_update(slot: u64) -> SubscribeUpdate {
+        SubscribeUpdate {
+            filters: vec!["blocks".to_string()],
+            update_oneof: Some(UpdateOneof::Block(SubscribeUpdateBlock {
+                slot,
+                ..SubscribeUpdateBlock::default()
+            })),
+        }
+    }
+
+    fn ping_update() -> SubscribeUpdate {
+        SubscribeUpdate {
+            filters: vec![],
+            update_oneof: Some(UpdateOneof::Ping(SubscribeUpdatePing {})),
+        }
+    }
+
+    async fn drain(mut rx: mpsc::Receiver<String>) -> Vec<String> {
+        let mut triggers = Vec::new();
+        while let Some(trigger) = rx.recv().await {
+            triggers.push(trigger);
+        }
+        triggers.sort();
+        triggers
+    }
+
+    #[tokio::test]
+    async fn test_blocks_trigger_transfers() {
+        let (tx, rx) = mpsc::channel(8);
+        let updates: Vec<Result<SubscribeUpdate, String>> = vec![
+            Ok(block_update(10)),
+            Ok(ping_update()),
+            Ok(block_update(11)),
+        ];
+
+        let summary =
+            process_updates(stream::iter(updates), Arc::new(tx), WatchOptions::default()).await;
+
+        assert_eq!(
+            summary,
+            WatchSummary {
+                updates_seen: 3,
+                triggers_fired: 2,
+                triggers_delivered: 2,
+                stream_error: None,
+                heartbeats: 0,
+                deadline_reached: false,
+            }
+        );
+        assert_eq!(drain(rx).await, vec!["10".to_string(), "11".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_error_stops_processing() {
+        let (tx, rx) = mpsc::channel(8);
+        let updates: Vec<Result<SubscribeUpdate, String>> = vec![
+            Ok(block_update(10)),
+            Err("connection reset".to_string()),
+            Ok(block_update(11)),
+        ];
+
+        let summary =
+            process_updates(stream::iter(updates), Arc::new(tx), WatchOptions::default()).await;
+
+        assert_eq!(summary.updates_seen, 1);
+        assert_eq!(summary.triggers_fired, 1);
+        assert_eq!(
+            summary.stream_error.as_deref(),
+            Some("\"connection reset\"")
+        );
+        assert_eq!(drain(rx).await, vec!["10".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_end_waits_for_in_flight_triggers() {
+        // Канал на один слот: триггеры еще висят в отправке, когда стрим кончается
+        let (tx, mut rx) = mpsc::channel(1);
+        let updates: Vec<Result<SubscribeUpdate, String>> = vec![
+            Ok(block_update(10)),
+            Ok(block_update(11)),
+            Ok(ping_update()),
+            Ok(block_update(12)),
+        ];
+        let reader = tokio::spawn(async move {
+            let mut triggers = Vec::new();
+            while let Some(trigger) = rx.recv().await {
+                triggers.push(trigger);
+                tokio::task::yield_now().await;
+            }
+            triggers
+        });
+
+        let tx = Arc::new(tx);
+        let summary = process_updates(
+            stream::iter(updates),
+            Arc::clone(&tx),
+            WatchOptions::default(),
+        )
+        .await;
+        drop(tx);
+
+        assert_eq!(summary.updates_seen, 4);
+        assert_eq!(summary.triggers_fired, 3);
+        assert_eq!(summary.triggers_delivered, 3);
+        assert_eq!(summary.stream_error, None);
+        assert!(!summary.deadline_reached);
+        let mut triggers = reader.await.unwrap();
+        triggers.sort();
+        assert_eq!(triggers, vec!["10", "11", "12"]);
+    }
+
+    #[tokio::test]
+    async fn test_triggers_after_transfer_task_stopped_are_not_delivered() {
+        let (tx, rx) = mpsc::channel(8);
+        drop(rx);
+        let updates: Vec<Result<SubscribeUpdate, String>> =
+            vec![Ok(block_update(10)), Ok(block_update(11))];
+
+        let summary =
+            process_updates(stream::iter(updates), Arc::new(tx), WatchOptions::default()).await;
+
+        assert_eq!(summary.triggers_fired, 2);
+        assert_eq!(summary.triggers_delivered, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_heartbeat_is_emitted_while_stream_is_idle() {
+        let (tx, _rx) = mpsc::channel(8);
+        let updates = Box::pin(stream::once(async {
+            tokio::time::sleep(Duration::from_secs(25)).await;
+            Ok::<_, String>(ping_update())
+        }));
+
+        let options = WatchOptions {
+            heartbeat: Some(Duration::from_secs(10)),
+            ..WatchOptions::default()
+        };
+        let summary = process_updates(updates, Arc::new(tx), options).await;
+
+        assert_eq!(summary.heartbeats, 2);
+        assert_eq!(summary.updates_seen, 1);
+        assert_eq!(summary.triggers_fired, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_watcher_stops_after_max_runtime() {
+        let (tx, rx) = mpsc::channel(8);
+        let updates = Box::pin(
+            stream::iter(vec![Ok::<_, String>(block_update(10))]).chain(stream::pending()),
+        );
+        let options = WatchOptions {
+            max_runtime: Some(Duration::from_secs(30)),
+            ..WatchOptions::default()
+        };
+        let start = Instant::now();
+
+        let summary = process_updates(updates, Arc::new(tx), options).await;
+
+        assert!(summary.deadline_reached);
+        assert_eq!(summary.triggers_fired, 1);
+        assert_eq!(start.elapsed(), Duration::from_secs(30));
+        assert_eq!(drain(rx).await, vec!["10".to_string()]);
+    }
+}